@@ -0,0 +1,105 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::{ChartId, ChartPattern, FoundFile};
+
+///
+/// A rule that two identities are the same product, ie a quad renamed between survey years, so
+/// that the editions from either side of the rename are versions of one chart.  Written
+/// `FROM -> TO`, both [`ChartPattern`]s; a scale or source left out of `TO` is kept from the
+/// matched chart.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChartAlias {
+    pub from: ChartPattern,
+    pub to: ChartPattern,
+}
+
+impl ChartAlias {
+    ///
+    /// The identity the chart is filed under, if the alias matches it
+    pub fn apply(&self, id: &ChartId) -> Option<ChartId> {
+        if !self.from.matches(id) {
+            return None;
+        }
+        let source = self
+            .to
+            .source
+            .clone()
+            .or_else(|| id.source().map(Into::into));
+        Some(ChartId::new(
+            source,
+            self.to.name.clone(),
+            self.to.scale.or(id.scale()),
+        ))
+    }
+}
+
+impl FromStr for ChartAlias {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((from, to)) = s.split_once("->") else {
+            return Err(format!("Invalid alias {s}, expected FROM -> TO"));
+        };
+        Ok(ChartAlias {
+            from: from.parse()?,
+            to: to.parse()?,
+        })
+    }
+}
+
+impl Display for ChartAlias {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.from, self.to)
+    }
+}
+
+///
+/// A profile's [`ChartAlias`]es.  A chart renamed more than once is followed through every
+/// rename, ie `A -> B` and `B -> C` file `A` under `C`.
+#[derive(Debug, Clone, Default)]
+pub struct ChartAliases {
+    aliases: Vec<ChartAlias>,
+}
+
+impl ChartAliases {
+    pub fn new(aliases: Vec<ChartAlias>) -> ChartAliases {
+        ChartAliases { aliases }
+    }
+
+    pub fn push(&mut self, alias: ChartAlias) {
+        self.aliases.push(alias);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ChartAlias> {
+        self.aliases.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+
+    ///
+    /// The identity the chart is filed under, following the first matching alias until none
+    /// match.  Each alias is followed at most once, so a cycle of them can't loop forever.
+    pub fn resolve(&self, id: &ChartId) -> Option<ChartId> {
+        let mut resolved: Option<ChartId> = None;
+        for _ in 0..self.aliases.len() {
+            let current = resolved.as_ref().unwrap_or(id);
+            match self.aliases.iter().find_map(|a| a.apply(current)) {
+                Some(next) if next != *current => resolved = Some(next),
+                _ => break,
+            }
+        }
+        resolved
+    }
+
+    ///
+    /// Files the found file under the identity its chart resolves to
+    pub fn file(&self, file: FoundFile) -> FoundFile {
+        match self.resolve(file.id()) {
+            Some(id) => file.with_id(id),
+            None => file,
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::{format_size, ChartId, CleanPlan, FoundFile};
+
+const BAR_WIDTH: usize = 40;
+
+///
+/// The width of the bins of an [`AgeHistogram`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum AgeBucket {
+    Month,
+    #[default]
+    Quarter,
+    Year,
+}
+
+impl AgeBucket {
+    ///
+    /// The index of the bin the file's edition falls in, counting from year 0
+    fn index(&self, file: &FoundFile) -> i64 {
+        let date = file.version().date();
+        let (year, month) = (i64::from(date.year()), date.month_of_year() as i64 - 1);
+        match self {
+            AgeBucket::Month => year * 12 + month,
+            AgeBucket::Quarter => year * 4 + month / 3,
+            AgeBucket::Year => year,
+        }
+    }
+
+    fn label(&self, index: i64) -> String {
+        match self {
+            AgeBucket::Month => format!("{}-{:02}", index.div_euclid(12), index.rem_euclid(12) + 1),
+            AgeBucket::Quarter => format!("{}-Q{}", index.div_euclid(4), index.rem_euclid(4) + 1),
+            AgeBucket::Year => format!("{index}"),
+        }
+    }
+}
+
+impl FromStr for AgeBucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "month" => Ok(AgeBucket::Month),
+            "quarter" => Ok(AgeBucket::Quarter),
+            "year" => Ok(AgeBucket::Year),
+            _ => Err(format!(
+                "Unknown bucket {s}, expected month, quarter or year"
+            )),
+        }
+    }
+}
+
+impl Display for AgeBucket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgeBucket::Month => write!(f, "month"),
+            AgeBucket::Quarter => write!(f, "quarter"),
+            AgeBucket::Year => write!(f, "year"),
+        }
+    }
+}
+
+///
+/// The distribution of edition dates across a set of files, ie the kept charts of an archive,
+/// for a picture of how fresh it is.
+#[derive(Debug, Default)]
+pub struct AgeHistogram {
+    pub bucket: AgeBucket,
+    /// Files and bytes per bin, by bin index
+    bins: BTreeMap<i64, (usize, u64)>,
+}
+
+impl AgeHistogram {
+    pub fn new<'a>(bucket: AgeBucket, files: impl IntoIterator<Item = &'a FoundFile>) -> Self {
+        let mut bins = BTreeMap::new();
+        for file in files {
+            let bin: &mut (usize, u64) = bins.entry(bucket.index(file)).or_default();
+            bin.0 += 1;
+            bin.1 += file.size();
+        }
+        AgeHistogram { bucket, bins }
+    }
+
+    ///
+    /// Every bin from the oldest edition to the newest, empty ones included, as
+    /// `(label, files, bytes)`
+    pub fn bins(&self) -> Vec<(String, usize, u64)> {
+        let (Some(first), Some(last)) = (self.bins.keys().next(), self.bins.keys().last()) else {
+            return Vec::new();
+        };
+        (*first..=*last)
+            .map(|idx| {
+                let (files, bytes) = self.bins.get(&idx).copied().unwrap_or_default();
+                (self.bucket.label(idx), files, bytes)
+            })
+            .collect()
+    }
+
+    ///
+    /// The histogram as text, one line per bin with a bar scaled to the fullest bin
+    pub fn render(&self) -> Vec<String> {
+        let bins = self.bins();
+        let max = bins
+            .iter()
+            .map(|(_, files, _)| *files)
+            .max()
+            .unwrap_or_default();
+        bins.into_iter()
+            .map(|(label, files, bytes)| {
+                let bar = (files * BAR_WIDTH).div_ceil(max.max(1));
+                let bar = "#".repeat(bar);
+                format!(
+                    "{label:>8} {bar:<BAR_WIDTH$} {files} ({})",
+                    format_size(bytes)
+                )
+            })
+            .collect()
+    }
+}
+
+///
+/// The versions of one chart family on disk, before and after a plan.
+#[derive(Debug, Clone)]
+pub struct Family {
+    pub id: ChartId,
+    pub versions_before: usize,
+    pub bytes_before: u64,
+    pub versions_after: usize,
+    pub bytes_after: u64,
+}
+
+///
+/// Every chart family of a plan, for finding where a change of retention policy would pay off
+/// most.
+#[derive(Debug, Default)]
+pub struct FamilyReport {
+    families: Vec<Family>,
+}
+
+impl FamilyReport {
+    pub fn new<'a>(plan: &CleanPlan<'a>) -> FamilyReport {
+        let mut families: BTreeMap<&ChartId, Family> = BTreeMap::new();
+        let mut add = |file: &'a FoundFile, stays: bool| {
+            let family = families.entry(file.id()).or_insert_with(|| Family {
+                id: file.id().clone(),
+                versions_before: 0,
+                bytes_before: 0,
+                versions_after: 0,
+                bytes_after: 0,
+            });
+            family.versions_before += 1;
+            family.bytes_before += file.size();
+            if stays {
+                family.versions_after += 1;
+                family.bytes_after += file.size();
+            }
+        };
+        let stay = plan
+            .keep
+            .iter()
+            .chain(&plan.deferred)
+            .chain(&plan.held)
+            .chain(&plan.aging);
+        let held_back = plan
+            .protected
+            .iter()
+            .chain(&plan.retained)
+            .chain(&plan.locked);
+        for file in stay.chain(held_back) {
+            add(file, true);
+        }
+        for removal in &plan.remove {
+            add(removal.file, false);
+        }
+        for file in &plan.retired {
+            add(file, false);
+        }
+        FamilyReport {
+            families: families.into_values().collect(),
+        }
+    }
+
+    ///
+    /// The `n` families with the most versions before the plan, ties broken by bytes
+    pub fn most_versions(&self, n: usize) -> Vec<&Family> {
+        let mut families: Vec<&Family> = self.families.iter().collect();
+        families.sort_by(|a, b| {
+            let key = |f: &Family| (Reverse(f.versions_before), Reverse(f.bytes_before));
+            (key(a), &a.id).cmp(&(key(b), &b.id))
+        });
+        families.truncate(n);
+        families
+    }
+
+    ///
+    /// The `n` families taking the most bytes before the plan, ties broken by versions
+    pub fn most_bytes(&self, n: usize) -> Vec<&Family> {
+        let mut families: Vec<&Family> = self.families.iter().collect();
+        families.sort_by(|a, b| {
+            let key = |f: &Family| (Reverse(f.bytes_before), Reverse(f.versions_before));
+            (key(a), &a.id).cmp(&(key(b), &b.id))
+        });
+        families.truncate(n);
+        families
+    }
+}
+
+impl Display for Family {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} versions ({}) → {} ({})",
+            self.id,
+            self.versions_before,
+            format_size(self.bytes_before),
+            self.versions_after,
+            format_size(self.bytes_after)
+        )
+    }
+}
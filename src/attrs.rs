@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use irox_log::log::info;
+
+use crate::{Error, ErrorContext, Executor, Phase};
+
+///
+/// How many paths are handed to one `lsattr` or `chattr`
+const BATCH: usize = 256;
+
+///
+/// A file attribute that stops a file being removed, whatever its permissions.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum FileAttr {
+    /// Linux `chattr +i`
+    Immutable,
+    /// Linux `chattr +a`
+    AppendOnly,
+    /// The Windows read-only attribute
+    ReadOnly,
+    /// The Windows system attribute
+    System,
+}
+
+impl Display for FileAttr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileAttr::Immutable => write!(f, "immutable"),
+            FileAttr::AppendOnly => write!(f, "append-only"),
+            FileAttr::ReadOnly => write!(f, "read-only"),
+            FileAttr::System => write!(f, "system"),
+        }
+    }
+}
+
+///
+/// The attributes of each of the files that would stop it being removed, leaving out files
+/// without any.  On Linux the flags are read with `lsattr`, so where it isn't installed, or the
+/// filesystem has no flags, nothing is found.
+pub fn locked_files(paths: &[PathBuf]) -> BTreeMap<PathBuf, Vec<FileAttr>> {
+    let mut locked = BTreeMap::new();
+    for batch in paths.chunks(BATCH) {
+        read_attrs(batch, &mut locked);
+    }
+    locked
+}
+
+#[cfg(target_os = "linux")]
+fn read_attrs(paths: &[PathBuf], locked: &mut BTreeMap<PathBuf, Vec<FileAttr>>) {
+    use irox_log::log::debug;
+
+    let output = match Command::new("lsattr")
+        .arg("-d")
+        .arg("--")
+        .args(paths)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Unable to run lsattr, not checking file attributes: {e}");
+            return;
+        }
+    };
+    // lsattr exits non-zero if any file had no flags to read, but still lists the rest
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((flags, path)) = line.split_once(' ') else {
+            continue;
+        };
+        let attrs: Vec<FileAttr> = [('i', FileAttr::Immutable), ('a', FileAttr::AppendOnly)]
+            .into_iter()
+            .filter(|(flag, _)| flags.contains(*flag))
+            .map(|(_, attr)| attr)
+            .collect();
+        if !attrs.is_empty() {
+            locked.insert(PathBuf::from(path), attrs);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn read_attrs(paths: &[PathBuf], locked: &mut BTreeMap<PathBuf, Vec<FileAttr>>) {
+    use std::os::windows::fs::MetadataExt;
+
+    const READONLY: u32 = 0x1;
+    const SYSTEM: u32 = 0x4;
+    for path in paths {
+        let Ok(meta) = std::fs::symlink_metadata(path) else {
+            continue;
+        };
+        let bits = meta.file_attributes();
+        let attrs: Vec<FileAttr> = [(READONLY, FileAttr::ReadOnly), (SYSTEM, FileAttr::System)]
+            .into_iter()
+            .filter(|(bit, _)| bits & bit != 0)
+            .map(|(_, attr)| attr)
+            .collect();
+        if !attrs.is_empty() {
+            locked.insert(path.clone(), attrs);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn read_attrs(_paths: &[PathBuf], _locked: &mut BTreeMap<PathBuf, Vec<FileAttr>>) {}
+
+///
+/// Clears the attributes from the file so it can be removed
+pub fn clear_attrs(path: &Path, attrs: &[FileAttr]) -> Result<(), Error> {
+    let (program, args): (&str, Vec<&str>) = if cfg!(windows) {
+        let mut args = Vec::new();
+        if attrs.contains(&FileAttr::ReadOnly) {
+            args.push("-R");
+        }
+        if attrs.contains(&FileAttr::System) {
+            args.push("-S");
+        }
+        ("attrib", args)
+    } else {
+        let mut args = Vec::new();
+        if attrs.contains(&FileAttr::Immutable) {
+            args.push("-i");
+        }
+        if attrs.contains(&FileAttr::AppendOnly) {
+            args.push("-a");
+        }
+        args.push("--");
+        ("chattr", args)
+    };
+    let status = Command::new(program).args(args).arg(path).status();
+    let status = status.context(Phase::Delete, path)?;
+    if !status.success() {
+        let msg = format!(
+            "{program} failed to clear the {} attributes",
+            format_attrs(attrs)
+        );
+        return Err(std::io::Error::other(msg)).context(Phase::Delete, path);
+    }
+    Ok(())
+}
+
+///
+/// The attributes as a comma separated list, ie `immutable, append-only`
+pub fn format_attrs(attrs: &[FileAttr]) -> String {
+    attrs
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+///
+/// Clears the attributes of locked files just before the executor removes them, for
+/// `--force-attrs`.  Executors that leave files where they are, ie a dry run, clear nothing.
+pub struct ForceAttrs {
+    executor: Box<dyn Executor>,
+    locked: BTreeMap<PathBuf, Vec<FileAttr>>,
+}
+
+impl ForceAttrs {
+    pub fn new(executor: Box<dyn Executor>, locked: BTreeMap<PathBuf, Vec<FileAttr>>) -> Self {
+        ForceAttrs { executor, locked }
+    }
+
+    fn clear(&self, path: &Path) -> Result<(), Error> {
+        match self.locked.get(path) {
+            Some(attrs) if self.executor.changes_files() => {
+                info!(
+                    "Clearing the {} attributes of {}",
+                    format_attrs(attrs),
+                    path.display()
+                );
+                clear_attrs(path, attrs)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Executor for ForceAttrs {
+    fn name(&self) -> &'static str {
+        self.executor.name()
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        self.clear(path)?;
+        self.executor.remove(root, path)
+    }
+
+    fn remove_superseded(
+        &self,
+        root: &Path,
+        path: &Path,
+        by: Option<&Path>,
+    ) -> Result<Option<PathBuf>, Error> {
+        self.clear(path)?;
+        self.executor.remove_superseded(root, path, by)
+    }
+
+    fn changes_files(&self) -> bool {
+        self.executor.changes_files()
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        self.executor.finish()
+    }
+}
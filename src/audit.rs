@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use crate::{Baseline, ChartId, ChecksumDb, Error, ErrorContext, FoundFile, Phase, Scan};
+
+///
+/// A difference between the archive and what the last good run left it as.
+#[derive(Debug)]
+pub enum Drift {
+    /// A kept chart that's gone, deleted outside the tool
+    Missing { chart: ChartId, path: PathBuf },
+    /// A kept file that's turned up at another path
+    Moved {
+        chart: ChartId,
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// A chart that wasn't in the archive at all
+    NewChart { chart: ChartId, path: PathBuf },
+    /// An edition newer than the one kept
+    NewVersion { chart: ChartId, path: PathBuf },
+    /// A kept chart whose newest file is now older than the one kept
+    Downgraded { chart: ChartId, path: PathBuf },
+    /// A kept file whose size or modification time changed since its checksum was recorded
+    Modified { path: PathBuf },
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::Missing { chart, path } => write!(f, "missing: {chart} ({})", path.display()),
+            Drift::Moved { chart, from, to } => {
+                write!(
+                    f,
+                    "moved: {chart} from {} to {}",
+                    from.display(),
+                    to.display()
+                )
+            }
+            Drift::NewChart { chart, path } => write!(f, "new chart: {chart} ({})", path.display()),
+            Drift::NewVersion { chart, path } => {
+                write!(f, "new version: {chart} ({})", path.display())
+            }
+            Drift::Downgraded { chart, path } => {
+                write!(f, "older than kept: {chart} ({})", path.display())
+            }
+            Drift::Modified { path } => write!(f, "modified: {}", path.display()),
+        }
+    }
+}
+
+///
+/// The outcome of an [`audit`].
+#[derive(Debug, Default)]
+pub struct AuditReport {
+    /// The charts checked against the baseline
+    pub checked: usize,
+    pub drift: Vec<Drift>,
+    pub errors: Vec<Error>,
+}
+
+///
+/// Compares a scan against the baseline of the last good run, and the checksums of its kept
+/// files if they're recorded.  Read only: nothing is planned or changed.
+pub fn audit(scan: &Scan, baseline: &Baseline, checksums: Option<&ChecksumDb>) -> AuditReport {
+    let mut report = AuditReport::default();
+    let mut found: BTreeMap<&ChartId, Vec<&FoundFile>> = BTreeMap::new();
+    for file in scan.to_keep.iter().chain(&scan.to_remove) {
+        found.entry(file.id()).or_default().push(file);
+    }
+    for (chart, kept) in baseline.editions() {
+        report.checked += 1;
+        let versions = found.get(chart).map(Vec::as_slice).unwrap_or_default();
+        let at_path = versions.iter().find(|f| f.relative_path() == kept.path);
+        let same = versions
+            .iter()
+            .find(|f| baseline.newer_than(f).is_none() && baseline.older_than(f).is_none());
+        match (at_path, same) {
+            (Some(file), _) => {
+                let path = &file.full_path();
+                let entry = checksums.and_then(|db| db.get(path));
+                if let Some(entry) = entry {
+                    match std::fs::metadata(path).context(Phase::Scan, path) {
+                        Ok(meta) if entry.matches(&meta) => {}
+                        Ok(_) => report.drift.push(Drift::Modified {
+                            path: file.relative_path(),
+                        }),
+                        Err(e) => report.errors.push(e),
+                    }
+                }
+            }
+            (None, Some(file)) => report.drift.push(Drift::Moved {
+                chart: chart.clone(),
+                from: kept.path.clone(),
+                to: file.relative_path(),
+            }),
+            (None, None) => report.drift.push(Drift::Missing {
+                chart: chart.clone(),
+                path: kept.path.clone(),
+            }),
+        }
+        if let Some(newest) = scan.to_keep.iter().find(|f| f.id() == chart) {
+            if baseline.newer_than(newest).is_some() {
+                report.drift.push(Drift::Downgraded {
+                    chart: chart.clone(),
+                    path: newest.relative_path(),
+                });
+            }
+        }
+        for file in versions.iter().filter(|f| baseline.older_than(f).is_some()) {
+            report.drift.push(Drift::NewVersion {
+                chart: chart.clone(),
+                path: file.relative_path(),
+            });
+        }
+    }
+    for file in &scan.to_keep {
+        if baseline.get(file.id()).is_none() {
+            report.drift.push(Drift::NewChart {
+                chart: file.id().clone(),
+                path: file.relative_path(),
+            });
+        }
+    }
+    report
+}
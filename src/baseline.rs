@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use irox_time::epoch::UnixTimestamp;
+
+use crate::{ChartId, ChartVersion, CleanPlan, Error, ErrorContext, ErrorKind, FoundFile, Phase};
+
+const HEADER: &str = "# charts-clean baseline v1";
+
+///
+/// The edition of a chart recorded in a [`Baseline`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BaselineEdition {
+    /// Publication time, seconds since the epoch
+    pub time: i64,
+    pub edition: Option<u32>,
+    /// The kept file, relative to the root
+    pub path: PathBuf,
+}
+
+impl BaselineEdition {
+    ///
+    /// Ordered the same way as [`ChartVersion`]s
+    fn key(&self) -> (i64, Option<u32>) {
+        (self.time, self.edition)
+    }
+}
+
+fn version_key(version: &ChartVersion) -> (i64, Option<u32>) {
+    let time = UnixTimestamp::from(version.timestamp())
+        .get_offset()
+        .as_seconds_f64();
+    (time.floor() as i64, version.edition())
+}
+
+///
+/// The editions kept by the last good run of a profile.  Planning against a baseline refuses to
+/// leave any chart older than its baseline edition, so a clock that jumped backwards or a parser
+/// regression can't quietly downgrade the archive to stale charts.
+///
+/// Stored as a tab separated file, one chart per line:
+/// `<source>\t<name>\t<scale>\t<time>\t<edition>\t<path>`
+#[derive(Debug, Default)]
+pub struct Baseline {
+    editions: BTreeMap<ChartId, BaselineEdition>,
+}
+
+impl Baseline {
+    ///
+    /// Loads the baseline at the path, or an empty one if there isn't one yet
+    pub fn load(path: &Path) -> Result<Baseline, Error> {
+        let mut baseline = Baseline::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(baseline),
+            Err(e) => return Err(e).context(Phase::Setup, path),
+        };
+        for (idx, line) in text.lines().enumerate() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| {
+                let msg = format!("line {}: {msg}", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Setup).with_path(path)
+            };
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [source, name, scale, time, edition, file] = fields.as_slice() else {
+                return Err(err("Unrecognized entry"));
+            };
+            let optional = |v: &str| (!v.is_empty()).then(|| v.parse::<u32>()).transpose();
+            let id = ChartId::new(
+                (!source.is_empty()).then(|| Arc::from(*source)),
+                Arc::from(*name),
+                optional(scale).map_err(|_| err("Invalid scale"))?,
+            );
+            let edition = BaselineEdition {
+                time: time.parse().map_err(|_| err("Invalid time"))?,
+                edition: optional(edition).map_err(|_| err("Invalid edition"))?,
+                path: PathBuf::from(file),
+            };
+            baseline.editions.insert(id, edition);
+        }
+        Ok(baseline)
+    }
+
+    ///
+    /// The editions a plan keeps
+    pub fn from_plan(plan: &CleanPlan) -> Baseline {
+        let editions = plan
+            .keep
+            .iter()
+            .filter(|file| !plan.primaries.contains_key(&file.full_path()))
+            .map(|file| {
+                let (time, edition) = version_key(file.version());
+                let edition = BaselineEdition {
+                    time,
+                    edition,
+                    path: file.relative_path(),
+                };
+                (file.id().clone(), edition)
+            })
+            .collect();
+        Baseline { editions }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = format!("{HEADER}\n");
+        for (id, e) in &self.editions {
+            let scale = id.scale().map(|s| s.to_string()).unwrap_or_default();
+            let edition = e.edition.map(|s| s.to_string()).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{scale}\t{}\t{edition}\t{}",
+                id.source().unwrap_or_default(),
+                id.name(),
+                e.time,
+                e.path.display()
+            );
+        }
+        std::fs::write(path, out).context(Phase::Setup, path)
+    }
+
+    pub fn editions(&self) -> impl Iterator<Item = (&ChartId, &BaselineEdition)> {
+        self.editions.iter()
+    }
+
+    pub fn get(&self, id: &ChartId) -> Option<&BaselineEdition> {
+        self.editions.get(id)
+    }
+
+    ///
+    /// The baseline edition of the file's chart, if the file is older than it
+    pub fn newer_than(&self, file: &FoundFile) -> Option<&BaselineEdition> {
+        self.get(file.id())
+            .filter(|baseline| baseline.key() > version_key(file.version()))
+    }
+
+    ///
+    /// The baseline edition of the file's chart, if the file is newer than it
+    pub fn older_than(&self, file: &FoundFile) -> Option<&BaselineEdition> {
+        self.get(file.id())
+            .filter(|baseline| baseline.key() < version_key(file.version()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.editions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.editions.is_empty()
+    }
+}
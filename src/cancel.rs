@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Error, ErrorKind, Phase};
+
+///
+/// Cooperative cancellation for long running work.  Clones share the same flag, so any of them
+/// can cancel the rest, e.g. from a signal handler or another thread of an embedding application.
+/// A token can also carry a deadline, after which it reports itself cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    ///
+    /// A token sharing this one's flag that also gives up after `timeout` from now, if one is
+    /// given.  An earlier deadline inherited from this token still applies.
+    #[must_use]
+    pub fn with_timeout(&self, timeout: Option<Duration>) -> CancelToken {
+        let mut token = self.clone();
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            if self.deadline.is_none_or(|(d, _)| deadline < d) {
+                token.deadline = Some((deadline, timeout));
+            }
+        }
+        token
+    }
+
+    ///
+    /// Cancels this token and every token cloned from it
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.check(Phase::Setup).is_err()
+    }
+
+    ///
+    /// Returns an error for the phase if the token has been cancelled or its deadline has passed
+    pub fn check(&self, phase: Phase) -> Result<(), Error> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Error::new(
+                ErrorKind::Cancelled("cancelled".to_string()),
+                phase,
+            ));
+        }
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                let msg = format!("timed out after {}s", timeout.as_secs());
+                Err(Error::new(ErrorKind::Cancelled(msg), phase))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use irox_time::datetime::UTCDateTime;
+use irox_time::epoch::UnixTimestamp;
+
+use crate::{
+    ChartId, ChartPattern, ChartVersion, CleanPlan, Digest, Error, ErrorContext, ErrorKind,
+    FoundFile, Phase, Provenance,
+};
+
+const HEADER: &str = "# charts-clean catalog v1";
+
+///
+/// A file recorded in a [`Catalog`].
+#[derive(Debug, Clone)]
+pub struct CatalogEntry {
+    pub id: ChartId,
+    /// Publication time, seconds since the epoch
+    pub time: i64,
+    pub edition: Option<u32>,
+    /// Relative to the root
+    pub path: PathBuf,
+    pub size: u64,
+    /// Set if the run that recorded the file hashed it
+    pub digest: Option<Digest>,
+    /// The newest edition of its chart, rather than one held back from removal
+    pub current: bool,
+}
+
+impl CatalogEntry {
+    fn new(file: &FoundFile, current: bool) -> CatalogEntry {
+        let time = UnixTimestamp::from(file.version().timestamp())
+            .get_offset()
+            .as_seconds_f64();
+        CatalogEntry {
+            id: file.id().clone(),
+            time: time.floor() as i64,
+            edition: file.version().edition(),
+            path: file.relative_path(),
+            size: file.size(),
+            digest: file.digest(),
+            current,
+        }
+    }
+
+    pub fn version(&self) -> ChartVersion {
+        let timestamp = UnixTimestamp::from_seconds_f64(self.time as f64);
+        ChartVersion::new(UTCDateTime::from(timestamp), self.edition)
+    }
+
+    ///
+    /// The leading token of the chart name, the state for USGS topos, as
+    /// [`crate::GroupKey::State`]
+    pub fn state(&self) -> &str {
+        self.id.name().split('_').next().unwrap_or_default()
+    }
+}
+
+///
+/// What a clean found in a profile's archive, kept between runs so the archive can be searched
+/// with `query` without scanning it.  Rewritten by every clean that isn't a dry run, with the
+/// files left once its removals are done: the kept editions, and those held back from removal
+/// for whatever reason.
+///
+/// Only what's read from the file names and the filesystem is recorded, along with the
+/// profile's [`Provenance`]; the geographic extents inside the charts aren't read.
+///
+/// Stored as a tab separated file, one file per line:
+/// `<source>\t<name>\t<scale>\t<time>\t<edition>\t<size>\t<sha1>\t<current|held>\t<path>`
+#[derive(Debug, Default)]
+pub struct Catalog {
+    pub profile: String,
+    pub root: PathBuf,
+    /// When the catalog was written, seconds since the epoch
+    pub updated: u64,
+    pub provenance: Provenance,
+    pub entries: Vec<CatalogEntry>,
+}
+
+impl Catalog {
+    pub fn from_plan(profile: &str, root: &Path, plan: &CleanPlan, updated: u64) -> Catalog {
+        let held = plan
+            .deferred
+            .iter()
+            .chain(&plan.held)
+            .chain(&plan.protected);
+        let held = held
+            .chain(&plan.retained)
+            .chain(&plan.locked)
+            .chain(&plan.aging);
+        let mut entries: Vec<CatalogEntry> = plan
+            .keep
+            .iter()
+            .map(|f| CatalogEntry::new(f, true))
+            .chain(held.map(|f| CatalogEntry::new(f, false)))
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Catalog {
+            profile: profile.to_string(),
+            root: root.to_path_buf(),
+            updated,
+            provenance: Provenance::default(),
+            entries,
+        }
+    }
+
+    ///
+    /// Loads the catalog at the path, or an empty one if a clean hasn't written it yet
+    pub fn load(path: &Path) -> Result<Catalog, Error> {
+        let mut catalog = Catalog::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(catalog),
+            Err(e) => return Err(e).context(Phase::Setup, path),
+        };
+        for (idx, line) in text.lines().enumerate() {
+            let err = |msg: &str| {
+                let msg = format!("line {}: {msg}", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Setup).with_path(path)
+            };
+            if let Some(profile) = line.strip_prefix("#profile\t") {
+                catalog.profile = profile.to_string();
+                continue;
+            }
+            if let Some(root) = line.strip_prefix("#root\t") {
+                catalog.root = PathBuf::from(root);
+                continue;
+            }
+            if let Some(updated) = line.strip_prefix("#updated\t") {
+                catalog.updated = updated.parse().map_err(|_| err("Invalid time"))?;
+                continue;
+            }
+            if let Some((key, value)) = line.strip_prefix('#').and_then(|h| h.split_once('\t')) {
+                catalog.provenance.read_header(key, value);
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [source, name, scale, time, edition, size, digest, status, file] = &fields[..]
+            else {
+                return Err(err("Unrecognized entry"));
+            };
+            let optional = |v: &str| (!v.is_empty()).then(|| v.parse::<u32>()).transpose();
+            let digest = match *digest {
+                "" => None,
+                hex => Some(Digest::from_hex(hex).ok_or_else(|| err("Invalid hash"))?),
+            };
+            catalog.entries.push(CatalogEntry {
+                id: ChartId::new(
+                    (!source.is_empty()).then(|| Arc::from(*source)),
+                    Arc::from(*name),
+                    optional(scale).map_err(|_| err("Invalid scale"))?,
+                ),
+                time: time.parse().map_err(|_| err("Invalid time"))?,
+                edition: optional(edition).map_err(|_| err("Invalid edition"))?,
+                path: PathBuf::from(file),
+                size: size.parse().map_err(|_| err("Invalid size"))?,
+                digest,
+                current: *status == "current",
+            });
+        }
+        Ok(catalog)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = format!("{HEADER}\n");
+        let _ = writeln!(out, "#profile\t{}", self.profile);
+        let _ = writeln!(out, "#root\t{}", self.root.display());
+        let _ = writeln!(out, "#updated\t{}", self.updated);
+        for line in self.provenance.header_lines() {
+            let _ = writeln!(out, "{line}");
+        }
+        for e in &self.entries {
+            let optional = |v: Option<u32>| v.map(|v| v.to_string()).unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                e.id.source().unwrap_or_default(),
+                e.id.name(),
+                optional(e.id.scale()),
+                e.time,
+                optional(e.edition),
+                e.size,
+                e.digest.map(|d| d.to_hex()).unwrap_or_default(),
+                if e.current { "current" } else { "held" },
+                e.path.display()
+            );
+        }
+        std::fs::write(path, out).context(Phase::Setup, path)
+    }
+
+    pub fn query<'a>(&'a self, query: &'a CatalogQuery) -> impl Iterator<Item = &'a CatalogEntry> {
+        self.entries.iter().filter(|e| query.matches(e))
+    }
+}
+
+///
+/// Filters for [`Catalog::query`], every one that's set has to match.
+#[derive(Debug, Clone, Default)]
+pub struct CatalogQuery {
+    /// As [`crate::GroupKey::State`], ie `OK`
+    pub state: Option<String>,
+    pub source: Option<String>,
+    pub chart: Option<ChartPattern>,
+    /// Published before this, seconds since the epoch
+    pub published_before: Option<i64>,
+    /// Only the newest edition of each chart, leaving out those held back from removal
+    pub current_only: bool,
+}
+
+impl CatalogQuery {
+    pub fn matches(&self, entry: &CatalogEntry) -> bool {
+        self.state.as_deref().is_none_or(|s| entry.state() == s)
+            && self
+                .source
+                .as_deref()
+                .is_none_or(|s| entry.id.source() == Some(s))
+            && self.chart.as_ref().is_none_or(|c| c.matches(&entry.id))
+            && self.published_before.is_none_or(|t| entry.time < t)
+            && (entry.current || !self.current_only)
+    }
+}
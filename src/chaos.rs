@@ -0,0 +1,160 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irox_log::log::warn;
+
+use crate::{Error, ErrorContext, ErrorKind, Executor, Phase};
+
+///
+/// A root is only ever cleaned with failures injected if this file is in it, marking it as a
+/// fixture tree made to be broken
+pub const FIXTURE_MARKER: &str = ".charts-clean-fixture";
+
+///
+/// How often to inject failures, written `RATE[:SEED]`, ie `0.2` or `0.2:42`.  The same seed
+/// injects the same failures into the same removals, so a failure found can be replayed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChaosSpec {
+    /// The chance each removal fails, from 0 to 1
+    pub rate: f64,
+    /// Taken from the clock if unset
+    pub seed: Option<u64>,
+}
+
+impl FromStr for ChaosSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid chaos {s}, expected RATE[:SEED], ie 0.2:42");
+        let (rate, seed) = match s.split_once(':') {
+            Some((rate, seed)) => (rate, Some(seed.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let rate = rate.parse().ok().filter(|rate| (0.0..=1.0).contains(rate));
+        Ok(ChaosSpec {
+            rate: rate.ok_or_else(invalid)?,
+            seed,
+        })
+    }
+}
+
+///
+/// Where an injected failure strikes a removal.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Fault {
+    /// Before the executor touches the file, as if it couldn't be opened
+    Before,
+    /// Once the executor's moved or deleted the file, but before it's journaled, as if the
+    /// process died in between
+    After,
+}
+
+///
+/// Fails a random share of another executor's removals, and its finish, with injected IO
+/// errors, to exercise how a run copes: the errors it reports, what the journal holds for a
+/// file that was removed when its removal failed, and what's checkpointed for the next run.
+/// Only built with the `chaos` feature, for `--chaos`, and refused for any root without a
+/// [`FIXTURE_MARKER`].
+pub struct ChaosExecutor {
+    executor: Box<dyn Executor>,
+    rate: f64,
+    /// An xorshift generator's state
+    state: Mutex<u64>,
+}
+
+impl ChaosExecutor {
+    pub fn new(executor: Box<dyn Executor>, spec: &ChaosSpec, root: &Path) -> Result<Self, Error> {
+        if !root.join(FIXTURE_MARKER).exists() {
+            let msg = format!(
+                "Refusing to inject failures into {}, it has no {FIXTURE_MARKER}",
+                root.display()
+            );
+            return Err(Error::validate(msg).with_path(root));
+        }
+        let seed = spec.seed.unwrap_or_else(|| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            now.as_nanos() as u64
+        });
+        warn!(
+            "Injecting failures into {:.0}% of removals, seed {seed}",
+            spec.rate * 100.0
+        );
+        Ok(ChaosExecutor {
+            executor,
+            rate: spec.rate,
+            // Zero would stay zero
+            state: Mutex::new(seed.max(1)),
+        })
+    }
+
+    ///
+    /// The next draw, from 0 up to 1
+    fn draw(&self) -> f64 {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn fault(&self) -> Option<Fault> {
+        if self.draw() >= self.rate {
+            return None;
+        }
+        Some(if self.draw() < 0.5 {
+            Fault::Before
+        } else {
+            Fault::After
+        })
+    }
+}
+
+fn injected(what: &str, path: &Path) -> Result<Option<PathBuf>, Error> {
+    warn!("Injecting a failure {what} {}", path.display());
+    let msg = format!("Injected failure {what} the file");
+    Err(std::io::Error::other(msg)).context(Phase::Delete, path)
+}
+
+impl Executor for ChaosExecutor {
+    fn name(&self) -> &'static str {
+        self.executor.name()
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        self.remove_superseded(root, path, None)
+    }
+
+    fn remove_superseded(
+        &self,
+        root: &Path,
+        path: &Path,
+        by: Option<&Path>,
+    ) -> Result<Option<PathBuf>, Error> {
+        match self.fault() {
+            Some(Fault::Before) => injected("before removing", path),
+            Some(Fault::After) => {
+                self.executor.remove_superseded(root, path, by)?;
+                injected("after removing", path)
+            }
+            None => self.executor.remove_superseded(root, path, by),
+        }
+    }
+
+    fn changes_files(&self) -> bool {
+        self.executor.changes_files()
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        self.executor.finish()?;
+        if self.draw() < self.rate {
+            warn!("Injecting a failure finishing the {} executor", self.name());
+            let e = std::io::Error::other("Injected failure finishing the removals");
+            return Err(Error::new(ErrorKind::IOError(e), Phase::Delete));
+        }
+        Ok(())
+    }
+}
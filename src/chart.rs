@@ -0,0 +1,420 @@
+use std::cmp::Ordering;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use irox_time::datetime::UTCDateTime;
+use irox_time::format::{FormatError, FormatErrorType};
+use irox_time::gregorian::Date;
+use irox_time::Time;
+
+use crate::timestamp::{parse_timestamp, UtcOffset};
+use crate::{Digest, Error, ErrorContext, Interner, Phase};
+
+///
+/// The identity of a chart, independent of which edition of it a file holds.  All the files
+/// sharing a `ChartId` are versions of the same chart, and only the newest is kept.  The name and
+/// source are shared, see [`Interner`], so a chart with many editions holds them once.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ChartId {
+    source: Option<Arc<str>>,
+    name: Arc<str>,
+    scale: Option<u32>,
+}
+
+impl ChartId {
+    pub fn new(source: Option<Arc<str>>, name: Arc<str>, scale: Option<u32>) -> ChartId {
+        ChartId {
+            source,
+            name,
+            scale,
+        }
+    }
+
+    ///
+    /// The product/series code of the publisher, ie `TM` for USGS US Topo
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    ///
+    /// The name of the chart, ie `OK_Tulsa`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    ///
+    /// The scale denominator, ie `24000` for a `24k` chart
+    pub fn scale(&self) -> Option<u32> {
+        self.scale
+    }
+}
+
+impl Display for ChartId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(scale) = self.scale {
+            write!(f, "@1:{scale}")?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, "[{source}]")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Matches charts by identity, written the way they're displayed: `NAME[@1:SCALE][[SOURCE]]`.
+/// The scale and source can be left out to match every chart of the name.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ChartPattern {
+    pub name: Arc<str>,
+    pub scale: Option<u32>,
+    pub source: Option<Arc<str>>,
+}
+
+impl ChartPattern {
+    pub fn matches(&self, id: &ChartId) -> bool {
+        *self.name == *id.name()
+            && self.scale.is_none_or(|s| id.scale() == Some(s))
+            && self
+                .source
+                .as_deref()
+                .is_none_or(|s| id.source() == Some(s))
+    }
+}
+
+impl FromStr for ChartPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chart = s.trim();
+        let (rest, source) = match chart.strip_suffix(']').and_then(|c| c.rsplit_once('[')) {
+            Some((rest, source)) => (rest, Some(Arc::from(source))),
+            None => (chart, None),
+        };
+        let (name, scale) = match rest.split_once("@1:") {
+            Some((name, scale)) => {
+                let scale = scale
+                    .parse()
+                    .map_err(|_| format!("Invalid scale: {scale}"))?;
+                (name, Some(scale))
+            }
+            None => (rest, None),
+        };
+        if name.is_empty() {
+            return Err(format!("No chart name: {s}"));
+        }
+        Ok(ChartPattern {
+            name: Arc::from(name),
+            scale,
+            source,
+        })
+    }
+}
+
+impl Display for ChartPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(scale) = self.scale {
+            write!(f, "@1:{scale}")?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, "[{source}]")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// A specific edition of a chart.  Versions are ordered by their UTC timestamp, then by their
+/// edition number.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ChartVersion {
+    timestamp: UTCDateTime,
+    edition: Option<u32>,
+}
+
+impl ChartVersion {
+    pub fn new(timestamp: UTCDateTime, edition: Option<u32>) -> ChartVersion {
+        ChartVersion { timestamp, edition }
+    }
+
+    ///
+    /// The publication time, normalized to UTC
+    pub fn timestamp(&self) -> UTCDateTime {
+        self.timestamp
+    }
+
+    pub fn date(&self) -> Date {
+        self.timestamp.get_date()
+    }
+
+    pub fn time(&self) -> Time {
+        self.timestamp.get_time()
+    }
+
+    pub fn edition(&self) -> Option<u32> {
+        self.edition
+    }
+}
+
+impl Display for ChartVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.timestamp)?;
+        if let Some(edition) = self.edition {
+            write!(f, "/ed{edition}")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Parses a scale token, ie `24k` or `1-24000`
+pub(crate) fn parse_scale(token: &str) -> Option<u32> {
+    if let Some(denom) = token.strip_prefix("1-") {
+        return denom.parse().ok();
+    }
+    let thousands = token.strip_suffix(['k', 'K'])?;
+    thousands.parse::<u32>().ok()?.checked_mul(1000)
+}
+
+///
+/// Parses an edition token, ie `ed3`
+pub(crate) fn parse_edition(token: &str) -> Option<u32> {
+    let (prefix, num) = token.split_at_checked(2)?;
+    if !prefix.eq_ignore_ascii_case("ed") {
+        return None;
+    }
+    num.parse().ok()
+}
+
+///
+/// A chart file found on disk, identified by its [`ChartId`].  Equality, ordering and hashing
+/// only consider the identity, so a set of `FoundFile`s holds one version per chart.
+///
+/// A file is kept as a path relative to the root it was scanned from.  The root is shared with
+/// every other file of the scan, and the directory with the other files found in it, so only its
+/// name is its own.
+#[derive(Debug)]
+pub struct FoundFile {
+    id: ChartId,
+    version: ChartVersion,
+    root: Arc<Path>,
+    dir: Arc<Path>,
+    file_name: Box<OsStr>,
+    size: u64,
+    digest: Option<Digest>,
+}
+
+impl FoundFile {
+    pub fn new(id: ChartId, version: ChartVersion, full_path: PathBuf) -> FoundFile {
+        let dir = full_path.parent().unwrap_or(Path::new(""));
+        let file_name = full_path.file_name().unwrap_or_default();
+        FoundFile::in_dir(
+            id,
+            version,
+            Arc::from(Path::new("")),
+            Arc::from(dir),
+            file_name.into(),
+        )
+    }
+
+    ///
+    /// A file named `file_name` in the directory `dir` under `root`, both shared
+    pub fn in_dir(
+        id: ChartId,
+        version: ChartVersion,
+        root: Arc<Path>,
+        dir: Arc<Path>,
+        file_name: OsString,
+    ) -> FoundFile {
+        FoundFile {
+            id,
+            version,
+            root,
+            dir,
+            file_name: file_name.into_boxed_os_str(),
+            size: 0,
+            digest: None,
+        }
+    }
+
+    ///
+    /// Parses a file named like `<name>[_<scale>][_ed<N>]_<date>[T<time>]_<product|time>_<suffix>`
+    /// ie `OK_Tulsa_20230126_TM_geo.pdf`.  Times without a zone designator are in `source_tz`.
+    pub fn parse(full_path: PathBuf, source_tz: UtcOffset) -> Result<FoundFile, Error> {
+        let (root, dir) = (
+            Arc::from(Path::new("")),
+            full_path.parent().unwrap_or(Path::new("")),
+        );
+        let file_name = full_path.file_name().unwrap_or_default().to_os_string();
+        FoundFile::parse_in(
+            root,
+            Arc::from(dir),
+            file_name,
+            source_tz,
+            &Interner::default(),
+        )
+    }
+
+    ///
+    /// Parses a file in the directory `dir` under `root` as [`FoundFile::parse`] does, sharing
+    /// its chart name and source with every other file parsed with the same interner.
+    pub fn parse_in(
+        root: Arc<Path>,
+        dir: Arc<Path>,
+        file_name: OsString,
+        source_tz: UtcOffset,
+        interner: &Interner,
+    ) -> Result<FoundFile, Error> {
+        let full_path = || root.join(&dir).join(&file_name);
+        let name_str = file_name.to_string_lossy().to_string();
+        let tokens: Vec<&str> = name_str.split('_').collect();
+        let [name @ .., date, product, _suffix] = tokens.as_slice() else {
+            let e = FormatError::new(FormatErrorType::Other, "Unrecognized file name".to_string());
+            return Err(e).context(Phase::Parse, &full_path());
+        };
+        let mut name = name.to_vec();
+        let edition = name.last().and_then(|t| parse_edition(t));
+        if edition.is_some() {
+            name.pop();
+        }
+        let scale = name.last().and_then(|t| parse_scale(t));
+        if scale.is_some() {
+            name.pop();
+        }
+        if name.is_empty() {
+            let e = FormatError::new(FormatErrorType::Other, "Missing chart name".to_string());
+            return Err(e).context(Phase::Parse, &full_path());
+        }
+        let timestamp =
+            parse_timestamp(date, Some(product), source_tz).context(Phase::Parse, &full_path())?;
+        let source = (!crate::timestamp::is_time_token(product)).then(|| interner.intern(product));
+        let id = ChartId::new(source, interner.intern(&name.join("_")), scale);
+        let version = ChartVersion::new(timestamp, edition);
+
+        Ok(FoundFile::in_dir(id, version, root, dir, file_name))
+    }
+
+    ///
+    /// Sets the size of the file on disk, in bytes
+    #[must_use]
+    pub fn with_size(mut self, size: u64) -> FoundFile {
+        self.size = size;
+        self
+    }
+
+    ///
+    /// Files it under another chart, ie the one an alias says it was renamed to
+    #[must_use]
+    pub fn with_id(mut self, id: ChartId) -> FoundFile {
+        self.id = id;
+        self
+    }
+
+    ///
+    /// Sets the hash of the file's contents
+    #[must_use]
+    pub fn with_digest(mut self, digest: Digest) -> FoundFile {
+        self.digest = Some(digest);
+        self
+    }
+
+    pub fn id(&self) -> &ChartId {
+        &self.id
+    }
+
+    pub fn version(&self) -> &ChartVersion {
+        &self.version
+    }
+
+    pub fn full_path(&self) -> PathBuf {
+        self.root.join(self.relative_path())
+    }
+
+    ///
+    /// The root the file was scanned from, or an empty path if it wasn't found by a scan
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    ///
+    /// The path of the file relative to its [`FoundFile::root`]
+    pub fn relative_path(&self) -> PathBuf {
+        self.dir.join(&*self.file_name)
+    }
+
+    ///
+    /// The directory the file is in, relative to its [`FoundFile::root`]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        &self.file_name
+    }
+
+    ///
+    /// Orders files by their full paths, without joining them
+    pub fn cmp_path(&self, other: &FoundFile) -> Ordering {
+        self.path_components().cmp(other.path_components())
+    }
+
+    fn path_components(&self) -> impl Iterator<Item = Component<'_>> {
+        let name = std::iter::once(Component::Normal(&self.file_name));
+        self.root
+            .components()
+            .chain(self.dir.components())
+            .chain(name)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    ///
+    /// The hash of the file's contents, if it was hashed when it was found
+    pub fn digest(&self) -> Option<Digest> {
+        self.digest
+    }
+
+    pub fn into_full_path(self) -> PathBuf {
+        self.full_path()
+    }
+}
+
+impl Display for FoundFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.id, self.version)
+    }
+}
+
+impl PartialEq for FoundFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.id.eq(&other.id)
+    }
+}
+
+impl Eq for FoundFile {}
+
+impl PartialOrd for FoundFile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FoundFile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl Hash for FoundFile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use crate::{Error, ErrorContext, Phase};
+
+///
+/// A removal a run didn't get to, by its path relative to the profile's root.
+#[derive(Debug, Clone)]
+pub struct CheckpointEntry {
+    pub path: PathBuf,
+    /// The file's size when it was planned, so a file that changed since isn't removed
+    pub size: u64,
+    pub superseded_by: Option<PathBuf>,
+}
+
+///
+/// The removals a time-boxed run (`--max-runtime`) ran out of time for, left for the next run of
+/// the profile to carry on with instead of scanning again.  Each is checked before it's removed:
+/// a file whose size changed, or whose superseding file is gone, is left alone.
+///
+/// Stored as a tab separated file, most valuable removal first:
+/// `remove\t<path>\t<size>\t<superseded by>`, the last field empty for withdrawn charts.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    pub entries: Vec<CheckpointEntry>,
+}
+
+impl Checkpoint {
+    ///
+    /// Loads the checkpoint at the path, or an empty one if there isn't one.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Checkpoint, Error> {
+        let mut checkpoint = Checkpoint {
+            path: path.into(),
+            entries: Vec::new(),
+        };
+        let contents = match std::fs::read_to_string(&checkpoint.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(checkpoint),
+            Err(e) => return Err(e).context(Phase::Setup, &checkpoint.path),
+        };
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let ["remove", path, size, by] = fields.as_slice() else {
+                continue;
+            };
+            let Ok(size) = size.parse() else {
+                continue;
+            };
+            checkpoint.entries.push(CheckpointEntry {
+                path: PathBuf::from(path),
+                size,
+                superseded_by: Some(by).filter(|by| !by.is_empty()).map(PathBuf::from),
+            });
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///
+    /// Writes the checkpoint out, or removes its file once there's nothing left in it
+    pub fn save(&self) -> Result<(), Error> {
+        if self.entries.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(e).context(Phase::Setup, &self.path)
+                }
+                _ => Ok(()),
+            };
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = String::from("# charts-clean checkpoint v1\n");
+        for e in &self.entries {
+            let by = e.superseded_by.as_ref().map(|by| by.display().to_string());
+            let _ = writeln!(
+                out,
+                "remove\t{}\t{}\t{}",
+                e.path.display(),
+                e.size,
+                by.unwrap_or_default()
+            );
+        }
+        std::fs::write(&self.path, out).context(Phase::Setup, &self.path)
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use irox_log::log::{debug, warn};
+
+use crate::hash::hash_file;
+use crate::{Digest, Error, ErrorContext, ErrorKind, FoundFile, Phase, Throttle};
+
+///
+/// The recorded state of a kept file.
+#[derive(Debug, Clone)]
+pub struct ChecksumEntry {
+    pub size: u64,
+    /// Modification time, seconds since the epoch
+    pub modified: u64,
+    pub digest: Digest,
+    /// When the contents were last read back and matched, seconds since the epoch
+    pub verified: u64,
+}
+
+///
+/// The outcome of a [`ChecksumDb::scrub`].
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub checked: usize,
+    pub checked_bytes: u64,
+    /// Files that changed size or modification time, and were re-hashed rather than checked
+    pub updated: usize,
+    /// Files that have gone missing since they were recorded
+    pub missing: usize,
+    /// Files whose contents changed without their size or modification time changing
+    pub corrupt: usize,
+    pub errors: Vec<Error>,
+}
+
+///
+/// A persistent record of the checksum of every kept file, for detecting silent corruption.  A
+/// file whose contents no longer match, although its size and modification time haven't changed,
+/// has rotted rather than been replaced.
+///
+/// Stored as a tab separated file, one entry per line:
+/// `<path>\t<size>\t<modified>\t<sha1>\t<verified>`
+pub struct ChecksumDb {
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, ChecksumEntry>,
+}
+
+fn modified(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn is_not_found(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::IOError(e) if e.kind() == std::io::ErrorKind::NotFound)
+}
+
+impl ChecksumEntry {
+    ///
+    /// True if the file still has the recorded size and modification time
+    pub fn matches(&self, meta: &std::fs::Metadata) -> bool {
+        meta.len() == self.size && modified(meta) == self.modified
+    }
+}
+
+impl ChecksumDb {
+    ///
+    /// Loads the database at the provided path, or starts an empty one if it doesn't exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<ChecksumDb, Error> {
+        let path = path.into();
+        let mut db = ChecksumDb {
+            path,
+            entries: BTreeMap::new(),
+        };
+        let contents = match std::fs::read_to_string(&db.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(db),
+            Err(e) => return Err(e).context(Phase::Setup, &db.path),
+        };
+        for line in contents.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [path, size, modified, digest, verified] = fields.as_slice() else {
+                continue;
+            };
+            let Some(digest) = Digest::from_hex(digest) else {
+                continue;
+            };
+            db.entries.insert(
+                PathBuf::from(path),
+                ChecksumEntry {
+                    size: size.parse().unwrap_or_default(),
+                    modified: modified.parse().unwrap_or_default(),
+                    digest,
+                    verified: verified.parse().unwrap_or_default(),
+                },
+            );
+        }
+        Ok(db)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = String::from("# charts-clean checksums\n");
+        for (path, e) in &self.entries {
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}",
+                path.display(),
+                e.size,
+                e.modified,
+                e.digest,
+                e.verified
+            );
+        }
+        std::fs::write(&self.path, out).context(Phase::Setup, &self.path)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&PathBuf, &ChecksumEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ChecksumEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///
+    /// Records the kept files of a run over `root`.  Only files that are new, or have changed
+    /// size or modification time, are hashed.  Entries under `root` that are no longer kept are
+    /// forgotten.
+    pub fn record(&mut self, root: &Path, kept: &[&FoundFile], now: u64) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let mut recorded = BTreeMap::new();
+        for file in kept {
+            let path = &file.full_path();
+            let meta = match std::fs::metadata(path).context(Phase::Scan, path) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            let (size, modified) = (meta.len(), modified(&meta));
+            let entry = match self.entries.remove(path) {
+                Some(entry) if entry.size == size && entry.modified == modified => entry,
+                _ => {
+                    debug!("Hashing {}", path.display());
+                    match hash_file(path).context(Phase::Scan, path) {
+                        Ok(digest) => ChecksumEntry {
+                            size,
+                            modified,
+                            digest,
+                            verified: now,
+                        },
+                        Err(e) => {
+                            errors.push(e);
+                            continue;
+                        }
+                    }
+                }
+            };
+            recorded.insert(path.to_path_buf(), entry);
+        }
+        self.entries.retain(|path, _| !path.starts_with(root));
+        self.entries.append(&mut recorded);
+        errors
+    }
+
+    ///
+    /// Re-reads every recorded file and compares it against its checksum.  Files that were
+    /// legitimately modified (a new size or modification time) are re-hashed, and missing files
+    /// are forgotten.
+    pub fn scrub(&mut self, throttle: &Throttle, now: u64) -> ScrubReport {
+        let mut report = ScrubReport::default();
+        let mut missing = Vec::new();
+        for (path, entry) in &mut self.entries {
+            let meta = match std::fs::metadata(path).context(Phase::Scrub, path) {
+                Ok(meta) => meta,
+                Err(e) if is_not_found(&e) => {
+                    warn!("{} has gone missing", path.display());
+                    missing.push(path.clone());
+                    continue;
+                }
+                Err(e) => {
+                    report.errors.push(e);
+                    continue;
+                }
+            };
+            throttle.request();
+            throttle.transfer(meta.len());
+            let digest = match hash_file(path).context(Phase::Scrub, path) {
+                Ok(digest) => digest,
+                Err(e) => {
+                    report.errors.push(e);
+                    continue;
+                }
+            };
+            report.checked += 1;
+            report.checked_bytes += meta.len();
+            if meta.len() != entry.size || modified(&meta) != entry.modified {
+                debug!("{} was modified, updating its checksum", path.display());
+                entry.size = meta.len();
+                entry.modified = modified(&meta);
+                entry.digest = digest;
+                entry.verified = now;
+                report.updated += 1;
+            } else if digest != entry.digest {
+                report.corrupt += 1;
+                let msg = format!(
+                    "contents changed: expected {}, found {digest}",
+                    entry.digest
+                );
+                let e = Error::new(ErrorKind::VerifyError(msg), Phase::Scrub).with_path(path);
+                report.errors.push(e);
+            } else {
+                entry.verified = now;
+            }
+        }
+        report.missing = missing.len();
+        for path in missing {
+            self.entries.remove(&path);
+        }
+        report
+    }
+}
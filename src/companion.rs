@@ -0,0 +1,116 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+///
+/// A file name pattern with one `*` standing for the name the files of a bundle share, ie
+/// `*.img` or `*.img.aux.xml`.  Matched ignoring case.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NamePattern {
+    prefix: String,
+    suffix: String,
+}
+
+impl NamePattern {
+    ///
+    /// The part of the name the `*` matched, if the name matches
+    pub fn stem<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let end = name.len().checked_sub(self.suffix.len())?;
+        let (prefix, suffix) = (name.get(..self.prefix.len())?, name.get(end..)?);
+        if end < self.prefix.len()
+            || !prefix.eq_ignore_ascii_case(&self.prefix)
+            || !suffix.eq_ignore_ascii_case(&self.suffix)
+        {
+            return None;
+        }
+        name.get(self.prefix.len()..end)
+            .filter(|stem| !stem.is_empty())
+    }
+
+    ///
+    /// The name matching the pattern with the stem
+    pub fn name(&self, stem: &str) -> String {
+        format!("{}{stem}{}", self.prefix, self.suffix)
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once('*') {
+            Some((prefix, suffix)) if !suffix.contains('*') && !s.contains('/') => {
+                Ok(NamePattern {
+                    prefix: prefix.to_string(),
+                    suffix: suffix.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Invalid file pattern {s}, expected one *, ie *.hdr"
+            )),
+        }
+    }
+}
+
+impl Display for NamePattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}*{}", self.prefix, self.suffix)
+    }
+}
+
+///
+/// A rule that files matching the companion patterns belong with the file in the same directory
+/// matching the primary pattern with the same stem, ie a raster's `.hdr` header, or the `.map`,
+/// `.id` and `.dat` files of a MapInfo `.tab`.  Written `PRIMARY: COMPANION, ...`, ie
+/// `*.img: *.hdr, *.img.aux.xml` or `*.tab: *.map, *.id, *.dat`.
+///
+/// A companion shares its primary's fate: it's kept, removed, deferred or held back along with
+/// it, and never planned as a chart of its own.  One whose primary isn't there is planned as any
+/// other file is.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompanionRule {
+    pub primary: NamePattern,
+    pub companions: Vec<NamePattern>,
+}
+
+impl CompanionRule {
+    ///
+    /// The names the primary of a file named `name` could have, by the rules, none if the name
+    /// doesn't match any rule's companions
+    pub fn primaries(rules: &[CompanionRule], name: &str) -> Vec<String> {
+        rules
+            .iter()
+            .flat_map(|rule| {
+                let stems = rule.companions.iter().filter_map(|c| c.stem(name));
+                stems.map(|stem| rule.primary.name(stem))
+            })
+            .collect()
+    }
+}
+
+impl FromStr for CompanionRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((primary, companions)) = s.split_once(':') else {
+            return Err(format!(
+                "Invalid companion rule {s}, expected PRIMARY: COMPANION, ..."
+            ));
+        };
+        let companions = companions
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<NamePattern>, String>>()?;
+        Ok(CompanionRule {
+            primary: primary.parse()?,
+            companions,
+        })
+    }
+}
+
+impl Display for CompanionRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let companions: Vec<String> = self.companions.iter().map(ToString::to_string).collect();
+        write!(f, "{}: {}", self.primary, companions.join(", "))
+    }
+}
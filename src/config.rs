@@ -0,0 +1,504 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{
+    parse_duration, parse_size, ChartAliases, CompanionRule, ConflictPolicy, Error, ErrorContext,
+    ErrorKind, ExecutorKind, ExportLayout, FixedFields, GroupKey, Phase, Policy, Preset,
+    Provenance, RemovalPriority, SizeRange, UtcOffset, Volume, WithdrawnAction,
+};
+
+///
+/// A chart collection to clean: a root directory and how to treat the files under it.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub root: PathBuf,
+    pub source_tz: UtcOffset,
+    /// Identities to file under others, ie charts renamed between editions
+    pub aliases: ChartAliases,
+    /// Where the fields of names without delimiters are, see [`crate::FixedFields`]
+    pub fixed_fields: Option<FixedFields>,
+    pub quarantine: Option<PathBuf>,
+    /// How superseded files are removed, into the quarantine if there is one and deleted if not
+    /// when unset
+    pub executor: Option<ExecutorKind>,
+    /// How long files stay in the quarantine before they're purged, forever if unset
+    pub quarantine_retention: Option<Duration>,
+    pub verify_copies: bool,
+    /// Where to keep a copy of the newest edition of every chart, if anywhere
+    pub mirror: Option<PathBuf>,
+    /// Keep a checksum of every kept file between runs, for `scrub`
+    pub checksums: bool,
+    pub priority: RemovalPriority,
+    pub max_deletions: Option<usize>,
+    /// Give up on a scan that takes longer than this
+    pub scan_timeout: Option<Duration>,
+    /// How many threads plan the scan, each taking whole top-level directories under the root
+    pub planners: usize,
+    /// Stop removing files once removals have taken this long, leaving the rest for the next run
+    pub removal_timeout: Option<Duration>,
+    /// Roll the report up by these levels, ie source → state → chart, if any
+    pub group_by: Vec<GroupKey>,
+    /// The editions kept by the last good run, which no chart may fall behind
+    pub baseline: Option<PathBuf>,
+    /// A lock file on the archive's share, held while cleaning so that hosts sharing the archive
+    /// take turns
+    pub lock: Option<PathBuf>,
+    /// Break a lock that's been held longer than this, never if unset
+    pub lock_stale_after: Option<Duration>,
+    /// Refuse a plan removing more than this many times the usual number of files, if set
+    pub anomaly_factor: Option<f64>,
+    /// An `http://` URL to POST alerts to, ie an unusually large plan
+    pub webhook: Option<String>,
+    /// What `apply` does about files that changed after its manifest was made
+    pub on_conflict: ConflictPolicy,
+    /// The publisher's list of withdrawn charts, see [`crate::WithdrawnList`]
+    pub withdrawn: Option<PathBuf>,
+    pub withdrawn_action: WithdrawnAction,
+    /// Other applications' indexes of the files they use, see [`crate::ProtectedFiles`]
+    pub protect: Vec<PathBuf>,
+    /// The sizes files of each type are expected to be, see [`crate::SizeRange`]
+    pub size_ranges: Vec<SizeRange>,
+    /// Clear immutable, append-only, read-only or system attributes to remove a file, rather
+    /// than leaving it where it is
+    pub force_attrs: bool,
+    /// Refuse to clean when a directory under the root can't be read, rather than leaving it out
+    pub fail_on_skipped: bool,
+    /// How often a new edition of each chart is expected, see [`crate::find_gaps`]
+    pub edition_interval: Option<Duration>,
+    /// Files that belong with another, sharing its fate, see [`crate::CompanionRule`]
+    pub companions: Vec<CompanionRule>,
+    /// Leave superseded editions in place until they're this old, then remove them, ie move them
+    /// to a cold tier with the `cold:DIR` executor, see [`crate::ColdTier`]
+    pub cold_after: Option<Duration>,
+    /// Where the charts came from and their license, carried into catalogs, manifests, export
+    /// indexes and reports
+    pub provenance: Provenance,
+    /// Keep what each directory held between runs, so the files of unchanged ones aren't parsed
+    /// or hashed again, see [`crate::DirCache`]
+    pub dir_cache: bool,
+}
+
+impl Profile {
+    pub fn new(name: impl Into<String>, root: impl Into<PathBuf>) -> Profile {
+        Profile {
+            name: name.into(),
+            root: root.into(),
+            source_tz: UtcOffset::UTC,
+            aliases: ChartAliases::default(),
+            fixed_fields: None,
+            quarantine: None,
+            executor: None,
+            quarantine_retention: None,
+            verify_copies: false,
+            mirror: None,
+            checksums: false,
+            priority: RemovalPriority::default(),
+            max_deletions: None,
+            scan_timeout: None,
+            planners: 1,
+            removal_timeout: None,
+            group_by: Vec::new(),
+            baseline: None,
+            lock: None,
+            lock_stale_after: None,
+            anomaly_factor: None,
+            webhook: None,
+            on_conflict: ConflictPolicy::default(),
+            withdrawn: None,
+            withdrawn_action: WithdrawnAction::default(),
+            protect: Vec::new(),
+            size_ranges: Vec::new(),
+            force_attrs: false,
+            fail_on_skipped: false,
+            edition_interval: None,
+            companions: Vec::new(),
+            cold_after: None,
+            provenance: Provenance::default(),
+            dir_cache: false,
+        }
+    }
+
+    ///
+    /// The rules this profile's removals are planned by
+    pub fn policy(&self) -> Policy {
+        Policy::default()
+            .with_priority(self.priority)
+            .with_max_deletions(self.max_deletions)
+            .with_size_ranges(self.size_ranges.clone())
+    }
+
+    ///
+    /// Sets one of the profile's config keys, ie `max_deletions` to `5000`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "root" => self.root = PathBuf::from(value),
+            "source_tz" => {
+                self.source_tz =
+                    UtcOffset::parse(value).ok_or_else(|| format!("Invalid timezone: {value}"))?;
+            }
+            "quarantine" => self.quarantine = Some(PathBuf::from(value)),
+            "executor" => self.executor = Some(value.parse()?),
+            "quarantine_retention" => {
+                self.quarantine_retention = Some(
+                    parse_duration(value).ok_or_else(|| format!("Invalid duration: {value}"))?,
+                );
+            }
+            "verify_copies" => {
+                self.verify_copies =
+                    parse_bool(value).ok_or_else(|| format!("Invalid bool: {value}"))?;
+            }
+            "force_attrs" => {
+                self.force_attrs =
+                    parse_bool(value).ok_or_else(|| format!("Invalid bool: {value}"))?;
+            }
+            "fail_on_skipped" => {
+                self.fail_on_skipped =
+                    parse_bool(value).ok_or_else(|| format!("Invalid bool: {value}"))?;
+            }
+            "dir_cache" => {
+                self.dir_cache =
+                    parse_bool(value).ok_or_else(|| format!("Invalid bool: {value}"))?;
+            }
+            "checksums" => {
+                self.checksums =
+                    parse_bool(value).ok_or_else(|| format!("Invalid bool: {value}"))?;
+            }
+            "scan_timeout" => {
+                self.scan_timeout = Some(
+                    parse_duration(value).ok_or_else(|| format!("Invalid duration: {value}"))?,
+                );
+            }
+            "removal_timeout" => {
+                self.removal_timeout = Some(
+                    parse_duration(value).ok_or_else(|| format!("Invalid duration: {value}"))?,
+                );
+            }
+            "origin" => self.provenance.origin = Some(value.to_string()),
+            "license" => self.provenance.license = Some(value.to_string()),
+            "cold_after" => {
+                self.cold_after = Some(
+                    parse_duration(value).ok_or_else(|| format!("Invalid duration: {value}"))?,
+                );
+            }
+            "edition_interval" => {
+                self.edition_interval = Some(
+                    parse_duration(value)
+                        .filter(|interval| !interval.is_zero())
+                        .ok_or_else(|| format!("Invalid duration: {value}"))?,
+                );
+            }
+            "baseline" => self.baseline = Some(PathBuf::from(value)),
+            "lock" => self.lock = Some(PathBuf::from(value)),
+            "anomaly_factor" => {
+                self.anomaly_factor = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|f: &f64| *f > 1.0)
+                        .ok_or_else(|| format!("Invalid factor: {value}"))?,
+                );
+            }
+            "webhook" => self.webhook = Some(value.to_string()),
+            "withdrawn" => self.withdrawn = Some(PathBuf::from(value)),
+            "withdrawn_action" => self.withdrawn_action = value.parse()?,
+            "on_conflict" => self.on_conflict = value.parse()?,
+            "alias" => self.aliases.push(value.parse()?),
+            "fixed_fields" => self.fixed_fields = Some(value.parse()?),
+            "protect" => self.protect.push(PathBuf::from(value)),
+            "size_range" => self.size_ranges.push(value.parse()?),
+            "companion" => self.companions.push(value.parse()?),
+            "lock_stale_after" => {
+                self.lock_stale_after = Some(
+                    parse_duration(value).ok_or_else(|| format!("Invalid duration: {value}"))?,
+                );
+            }
+            "group_by" => self.group_by = GroupKey::parse_list(value)?,
+            "mirror" => self.mirror = Some(PathBuf::from(value)),
+            "priority" => self.priority = value.parse()?,
+            "max_deletions" => {
+                self.max_deletions = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid count: {value}"))?,
+                );
+            }
+            "planners" => {
+                self.planners = value
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("Invalid count: {value}"))?;
+            }
+            _ => return Err(format!("Unknown key: {key}")),
+        }
+        Ok(())
+    }
+}
+
+///
+/// A layout of one profile's kept charts to write out for a chartplotter, see [`crate::Export`].
+#[derive(Debug, Clone)]
+pub struct ExportProfile {
+    pub name: String,
+    /// The name of the profile whose charts are exported
+    pub profile: String,
+    pub layout: ExportLayout,
+    pub dir: PathBuf,
+    /// Split the export into card-sized directories of at most this many bytes
+    pub card_size: Option<u64>,
+    /// Split the export across these volumes instead of writing it to `dir`
+    pub volumes: Vec<Volume>,
+}
+
+impl ExportProfile {
+    pub fn new(name: impl Into<String>, profile: impl Into<String>) -> ExportProfile {
+        ExportProfile {
+            name: name.into(),
+            profile: profile.into(),
+            layout: ExportLayout::default(),
+            dir: PathBuf::new(),
+            card_size: None,
+            volumes: Vec::new(),
+        }
+    }
+}
+
+///
+/// The settings loaded from a config file.  The format is INI-like: `key = value` pairs, with
+/// `#` comments and one `[profile <name>]` section per collection.  Keys before the first
+/// section are global, and profile keys there act as defaults for every profile:
+///
+/// ```text
+/// state_dir = /var/lib/charts-clean
+/// max_bandwidth = 10M
+/// verifying_key = /etc/charts-clean/plan.key.pub
+/// require_signed = true
+/// max_runtime = 30m
+/// verify_copies = true
+/// checksums = true
+///
+/// [profile usgs-topo]
+/// root = /chonko-1/chartdata/USGS-Topo
+/// preset = usgs-topo
+/// origin = USGS National Map, https://apps.nationalmap.gov/downloader
+/// license = Public domain
+/// source_tz = -0600
+/// alias = OK_Tulsa_NE -> OK_Tulsa_North
+/// quarantine = /chonko-1/quarantine/usgs-topo
+/// executor = quarantine
+/// quarantine_retention = 30d
+/// mirror = /media/plotter-card/USGS-Topo
+/// priority = largest
+/// max_deletions = 5000
+/// scan_timeout = 2h
+/// planners = 4
+/// group_by = source, state
+/// baseline = /var/lib/charts-clean/usgs-topo.baseline
+/// lock = /chonko-1/chartdata/USGS-Topo/.charts-clean.lock
+/// lock_stale_after = 12h
+/// anomaly_factor = 5
+/// webhook = http://alerts.local:8080/charts-clean
+/// withdrawn = /etc/charts-clean/usgs-withdrawn.txt
+/// withdrawn_action = flag
+/// on_conflict = skip
+/// protect = /home/pilot/qgis/tulsa-area.qgs
+/// size_range = tif 10M-500M
+/// size_range = pdf 1M-
+/// companion = *.img: *.hdr, *.img.aux.xml
+/// companion = *.tab: *.map, *.id, *.dat
+/// force_attrs = false
+/// fail_on_skipped = true
+/// edition_interval = 7d
+/// dir_cache = true
+///
+/// [profile state-rasters]
+/// root = /chonko-1/chartdata/State-Rasters
+/// fixed_fields = name:0:2, name:2:*, date:-10:8, source:-2:2
+///
+/// [profile usgs-topo-cold]
+/// root = /chonko-1/chartdata/USGS-Topo-Full
+/// executor = cold:/chonko-cold/USGS-Topo-Full
+/// cold_after = 1y
+///
+/// [export plotter]
+/// profile = usgs-topo
+/// layout = garmin
+/// dir = /media/export/plotter
+/// card_size = 32G
+///
+/// [export chart-cards]
+/// profile = usgs-topo
+/// layout = opencpn
+/// volume = /media/card-a, 64G
+/// volume = /media/card-b, 32G
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub state_dir: Option<PathBuf>,
+    pub max_bandwidth: Option<u64>,
+    pub max_requests_per_second: Option<f64>,
+    /// The private key plans are signed with when generated, see [`crate::SigningKey`]
+    pub signing_key: Option<PathBuf>,
+    /// The public key plans are verified with when applied
+    pub verifying_key: Option<PathBuf>,
+    /// Refuse to apply a manifest that isn't signed with the verifying key's signing key
+    pub require_signed: bool,
+    /// Stop the run once it's taken this long, checkpointing the removals it didn't get to
+    pub max_runtime: Option<Duration>,
+    pub profiles: Vec<Profile>,
+    pub exports: Vec<ExportProfile>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        Config::load_with_defaults(path, Profile::new("", ""))
+    }
+
+    ///
+    /// Loads the config file with its profile keys set on top of `defaults`, ie a preset's
+    pub fn load_with_defaults(path: &Path, defaults: Profile) -> Result<Config, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        Config::parse_with_defaults(&text, defaults).map_err(|e| e.with_path(path))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        Config::parse_with_defaults(text, Profile::new("", ""))
+    }
+
+    pub fn parse_with_defaults(text: &str, mut defaults: Profile) -> Result<Config, Error> {
+        let mut config = Config::default();
+        let mut current: Option<Profile> = None;
+        let mut export: Option<ExportProfile> = None;
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| config_error(format!("line {}: {msg}", idx + 1));
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if let Some(done) = current.take() {
+                    config.profiles.push(done);
+                }
+                if let Some(done) = export.take() {
+                    config.exports.push(done);
+                }
+                let section = section.trim();
+                if let Some(name) = section.strip_prefix("export ") {
+                    export = Some(ExportProfile::new(name.trim(), ""));
+                    continue;
+                }
+                let Some(name) = section.strip_prefix("profile ") else {
+                    return Err(err(format!("Unknown section [{section}]")));
+                };
+                let mut profile = defaults.clone();
+                profile.name = name.trim().to_string();
+                current = Some(profile);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(err(format!("Expected key = value: {line}")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if let Some(export) = export.as_mut() {
+                match key {
+                    "profile" => export.profile = value.to_string(),
+                    "layout" => export.layout = value.parse().map_err(err)?,
+                    "dir" => export.dir = PathBuf::from(value),
+                    "card_size" => {
+                        export.card_size = Some(
+                            parse_size(value)
+                                .ok_or_else(|| err(format!("Invalid size: {value}")))?,
+                        );
+                    }
+                    "volume" => export.volumes.push(
+                        Volume::parse(value)
+                            .ok_or_else(|| err(format!("Invalid volume: {value}")))?,
+                    ),
+                    _ => return Err(err(format!("Unknown export key: {key}"))),
+                }
+                continue;
+            }
+            let in_profile = current.is_some();
+            let profile = current.as_mut().unwrap_or(&mut defaults);
+            match key {
+                "state_dir"
+                | "max_bandwidth"
+                | "max_requests_per_second"
+                | "signing_key"
+                | "verifying_key"
+                | "require_signed"
+                | "max_runtime"
+                    if in_profile =>
+                {
+                    return Err(err(format!("{key} is only valid before the first profile")));
+                }
+                "state_dir" => config.state_dir = Some(PathBuf::from(value)),
+                "signing_key" => config.signing_key = Some(PathBuf::from(value)),
+                "verifying_key" => config.verifying_key = Some(PathBuf::from(value)),
+                "require_signed" => {
+                    config.require_signed =
+                        parse_bool(value).ok_or_else(|| err(format!("Invalid bool: {value}")))?;
+                }
+                "max_runtime" => {
+                    config.max_runtime = Some(
+                        parse_duration(value)
+                            .ok_or_else(|| err(format!("Invalid duration: {value}")))?,
+                    );
+                }
+                "max_bandwidth" => {
+                    config.max_bandwidth = Some(
+                        parse_size(value).ok_or_else(|| err(format!("Invalid size: {value}")))?,
+                    );
+                }
+                "max_requests_per_second" => {
+                    config.max_requests_per_second = Some(
+                        value
+                            .parse()
+                            .map_err(|_| err(format!("Invalid request rate: {value}")))?,
+                    );
+                }
+                "preset" => Preset::find(value)?.apply(profile).map_err(err)?,
+                _ => profile.set(key, value).map_err(err)?,
+            }
+        }
+        if let Some(done) = current.take() {
+            config.profiles.push(done);
+        }
+        if let Some(done) = export.take() {
+            config.exports.push(done);
+        }
+        for profile in &config.profiles {
+            if profile.root.as_os_str().is_empty() {
+                let msg = format!("profile {} has no root", profile.name);
+                return Err(config_error(msg));
+            }
+        }
+        for export in &config.exports {
+            if export.dir.as_os_str().is_empty() && export.volumes.is_empty() {
+                let msg = format!("export {} has no dir or volumes", export.name);
+                return Err(config_error(msg));
+            }
+            if !config.profiles.iter().any(|p| p.name == export.profile) {
+                let msg = format!(
+                    "export {} has an unknown profile: {}",
+                    export.name, export.profile
+                );
+                return Err(config_error(msg));
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn config_error(msg: String) -> Error {
+    Error::new(ErrorKind::ConfigError(msg), Phase::Setup)
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
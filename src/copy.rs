@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use irox_log::log::{debug, warn};
+
+use crate::hash::hash_file;
+use crate::{Error, ErrorContext, Phase, Throttle};
+
+///
+/// Copies `src` to `dst`, then reads back the destination to make sure it actually landed: the
+/// size is always compared, and with `verify_hash` the contents of both are hashed and compared.
+/// A destination that fails verification is removed, leaving the source untouched.
+pub fn copy_verified(
+    src: &Path,
+    dst: &Path,
+    verify_hash: bool,
+    throttle: &Throttle,
+) -> Result<u64, Error> {
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent).context(Phase::Copy, parent)?;
+    }
+    throttle.request();
+    let written = if throttle.limits_bandwidth() {
+        copy_throttled(src, dst, throttle)?
+    } else {
+        std::fs::copy(src, dst).context(Phase::Copy, dst)?
+    };
+    if let Err(e) = verify_copy(src, dst, written, verify_hash) {
+        warn!("Removing unverified copy {}", dst.display());
+        let _ = std::fs::remove_file(dst);
+        return Err(e);
+    }
+    Ok(written)
+}
+
+///
+/// Copies in chunks, pacing each against the bandwidth limit
+fn copy_throttled(src: &Path, dst: &Path, throttle: &Throttle) -> Result<u64, Error> {
+    let mut input = File::open(src).context(Phase::Copy, src)?;
+    let mut output = File::create(dst).context(Phase::Copy, dst)?;
+    let mut buf = vec![0u8; 1 << 16];
+    let mut written = 0;
+    loop {
+        let read = input.read(&mut buf).context(Phase::Copy, src)?;
+        if read == 0 {
+            break;
+        }
+        throttle.transfer(read as u64);
+        output
+            .write_all(buf.get(..read).unwrap_or_default())
+            .context(Phase::Copy, dst)?;
+        written += read as u64;
+    }
+    output.sync_all().context(Phase::Copy, dst)?;
+    Ok(written)
+}
+
+fn verify_copy(src: &Path, dst: &Path, written: u64, verify_hash: bool) -> Result<(), Error> {
+    let expected = std::fs::metadata(src).context(Phase::Copy, src)?.len();
+    let actual = std::fs::metadata(dst).context(Phase::Copy, dst)?.len();
+    if expected != written || expected != actual {
+        return Err(Error::verify(format!(
+            "size mismatch copying {}: expected {expected} bytes, found {actual}",
+            src.display()
+        ))
+        .with_path(dst));
+    }
+    if verify_hash {
+        let expected = hash_file(src).context(Phase::Copy, src)?;
+        let actual = hash_file(dst).context(Phase::Copy, dst)?;
+        if expected != actual {
+            return Err(Error::verify(format!(
+                "hash mismatch copying {}: expected {expected}, found {actual}",
+                src.display()
+            ))
+            .with_path(dst));
+        }
+        debug!(
+            "Verified {} -> {} ({expected})",
+            src.display(),
+            dst.display()
+        );
+    }
+    Ok(())
+}
+
+///
+/// Moves `src` to `dst`, renaming where possible and falling back to a verified copy and delete
+/// when they're on different devices.  The source is only removed once the copy is verified, and
+/// an existing destination is never replaced: the move fails, leaving both untouched.
+pub fn move_verified(
+    src: &Path,
+    dst: &Path,
+    verify_hash: bool,
+    throttle: &Throttle,
+) -> Result<(), Error> {
+    if std::fs::symlink_metadata(dst).is_ok() {
+        let msg = format!("{} already exists", dst.display());
+        let e = std::io::Error::new(std::io::ErrorKind::AlreadyExists, msg);
+        return Err(e).context(Phase::Copy, src);
+    }
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent).context(Phase::Copy, parent)?;
+    }
+    throttle.request();
+    match std::fs::rename(src, dst) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            debug!("{} is on a different device, copying", dst.display());
+        }
+        Err(e) => return Err(e).context(Phase::Copy, src),
+    }
+    copy_verified(src, dst, verify_hash, throttle)?;
+    throttle.request();
+    std::fs::remove_file(src).context(Phase::Delete, src)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn moves_a_file() {
+        let dir = TempDir::new("move");
+        let src = dir.write("a/OK_Tulsa_20190126_TM_geo.pdf", b"old");
+        let dst = dir.path().join("b/OK_Tulsa_20190126_TM_geo.pdf");
+        move_verified(&src, &dst, true, &Throttle::unlimited()).unwrap();
+        assert!(!src.exists());
+        assert_eq!(std::fs::read(&dst).unwrap(), b"old");
+    }
+
+    #[test]
+    fn never_moves_over_an_existing_file() {
+        let dir = TempDir::new("move-collision");
+        let src = dir.write("a/OK_Tulsa_20190126_TM_geo.pdf", b"new");
+        let dst = dir.write("b/OK_Tulsa_20190126_TM_geo.pdf", b"earlier");
+        let e = move_verified(&src, &dst, true, &Throttle::unlimited()).unwrap_err();
+        assert!(e.to_string().contains("already exists"), "{e}");
+        assert_eq!(std::fs::read(&src).unwrap(), b"new");
+        assert_eq!(std::fs::read(&dst).unwrap(), b"earlier");
+    }
+}
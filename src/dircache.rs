@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irox_time::datetime::UTCDateTime;
+use irox_time::epoch::UnixTimestamp;
+use irox_tools::sha1::SHA1;
+
+use crate::{ChartId, ChartVersion, Digest, Error, ErrorContext, ErrorKind, Phase, Scan};
+
+const HEADER: &str = "# charts-clean dir cache v2";
+
+///
+/// A chart file as a [`DirCache`] remembers it: what its name parsed as, filed under its alias,
+/// and its size and hash.
+#[derive(Debug, Clone)]
+pub struct CachedFile {
+    pub name: String,
+    pub id: ChartId,
+    pub version: ChartVersion,
+    pub size: u64,
+    pub digest: Option<Digest>,
+}
+
+///
+/// The chart files found in a directory, and the key of the listing they were found in.
+#[derive(Debug, Clone)]
+pub struct CachedDir {
+    pub key: Digest,
+    /// Ordered by name
+    pub files: Vec<CachedFile>,
+}
+
+impl CachedDir {
+    pub fn file(&self, name: &str) -> Option<&CachedFile> {
+        let idx = self
+            .files
+            .binary_search_by(|f| f.name.as_str().cmp(name))
+            .ok()?;
+        self.files.get(idx)
+    }
+}
+
+///
+/// The chart files of each directory under a profile's root as the last scan found them, keyed
+/// by a hash of the names, sizes and modification times of the files in the directory, see
+/// [`DirCache::key`].  A directory whose key hasn't changed since has the same files at the same
+/// sizes, so they're taken from the cache as they were found, parsed and hashed, rather than
+/// parsed again and every file read again, which is what makes a hashed scan of a mostly static
+/// archive slow.  Telling whether a directory changed still takes a stat of each of its files.
+///
+/// What names parse as depends on the scanner's timezone, fixed fields and aliases, see
+/// [`crate::Scanner::parser_key`], so the parsed files are only taken from a cache made by a
+/// scanner parsing the same way.  Files whose names didn't parse aren't cached, and are parsed
+/// again each scan.
+///
+/// Stored in the state dir as a tab separated file, a `#parser\t<key>` line followed by a
+/// `dir\t<key>\t<path>` line for each directory scanned and a
+/// `file\t<size>\t<sha1>\t<source>\t<name>\t<scale>\t<time>\t<edition>\t<file name>` line for
+/// each of its charts.
+#[derive(Debug, Default)]
+pub struct DirCache {
+    parser: Option<Digest>,
+    dirs: BTreeMap<PathBuf, CachedDir>,
+}
+
+impl DirCache {
+    ///
+    /// The key of a directory holding files with these names, sizes and modification times: any
+    /// file added, removed, renamed or rewritten changes it.  A SHA1, so that it's the same
+    /// whichever build of charts-clean works it out.
+    pub fn key(mut files: Vec<(OsString, u64, Option<SystemTime>)>) -> Digest {
+        files.sort();
+        let mut hasher = SHA1::default();
+        for (name, size, modified) in files {
+            let name = name.as_encoded_bytes();
+            hasher.write(&(name.len() as u64).to_le_bytes());
+            hasher.write(name);
+            hasher.write(&size.to_le_bytes());
+            // Tagged, so that no modification time hashes like a time before the epoch
+            let (tag, since) = match modified.map(|m| m.duration_since(UNIX_EPOCH)) {
+                Some(Ok(since)) => (1u8, since),
+                Some(Err(before)) => (2, before.duration()),
+                None => (0, Default::default()),
+            };
+            hasher.write(&[tag]);
+            hasher.write(&since.as_secs().to_le_bytes());
+            hasher.write(&since.subsec_nanos().to_le_bytes());
+        }
+        Digest::from(hasher.finish())
+    }
+
+    ///
+    /// The key of the scanner that made the cache, see [`crate::Scanner::parser_key`]
+    pub fn parser(&self) -> Option<Digest> {
+        self.parser
+    }
+
+    ///
+    /// The cached files of the directory, relative to the root, if its key is still this one
+    pub fn get(&self, dir: &Path, key: Digest) -> Option<&CachedDir> {
+        self.dirs.get(dir).filter(|cached| cached.key == key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    ///
+    /// How many of the directories the scan listed are unchanged since the cache was made
+    pub fn unchanged(&self, scan: &Scan) -> usize {
+        let unchanged = scan
+            .dir_keys
+            .iter()
+            .filter(|(dir, key)| self.get(dir, **key).is_some());
+        unchanged.count()
+    }
+
+    ///
+    /// The cache of what the scan found, by the scanner with the `parser` key, keeping the cached
+    /// hashes of files it didn't hash in directories that haven't changed, ie when a scan that
+    /// doesn't hash follows one that does
+    pub fn updated(&self, scan: &Scan, parser: Digest) -> DirCache {
+        // Directories without charts too, so that they count as unchanged
+        let mut dirs: BTreeMap<PathBuf, CachedDir> = scan
+            .dir_keys
+            .iter()
+            .map(|(dir, &key)| {
+                (
+                    dir.clone(),
+                    CachedDir {
+                        key,
+                        files: Vec::new(),
+                    },
+                )
+            })
+            .collect();
+        let files = scan.to_keep.iter().chain(&scan.to_remove);
+        for file in files.chain(scan.companions.values().flatten()) {
+            let Some(dir) = dirs.get_mut(file.dir()) else {
+                continue;
+            };
+            let name = file.file_name().to_string_lossy().into_owned();
+            let cached = self
+                .get(file.dir(), dir.key)
+                .and_then(|dir| dir.file(&name));
+            let digest = file.digest().or_else(|| cached.and_then(|f| f.digest));
+            dir.files.push(CachedFile {
+                name,
+                id: file.id().clone(),
+                version: *file.version(),
+                size: file.size(),
+                digest,
+            });
+        }
+        for dir in dirs.values_mut() {
+            dir.files.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        DirCache {
+            parser: Some(parser),
+            dirs,
+        }
+    }
+
+    ///
+    /// Loads the cache at the path, an empty one if there isn't one yet
+    pub fn load(path: &Path) -> Result<DirCache, Error> {
+        let mut cache = DirCache::default();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cache),
+            Err(e) => return Err(e).context(Phase::Setup, path),
+        };
+        // One made by an earlier version has other keys, and is started over
+        if text.lines().next() != Some(HEADER) {
+            return Ok(cache);
+        }
+        let mut current: Option<PathBuf> = None;
+        for (idx, line) in text.lines().enumerate() {
+            let err = |msg: &str| {
+                let msg = format!("line {}: {msg}", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Setup).with_path(path)
+            };
+            if let Some(parser) = line.strip_prefix("#parser\t") {
+                cache.parser = Some(Digest::from_hex(parser).ok_or_else(|| err("Invalid key"))?);
+                continue;
+            }
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["dir", key, dir] => {
+                    let key = Digest::from_hex(key).ok_or_else(|| err("Invalid key"))?;
+                    let files = Vec::new();
+                    cache
+                        .dirs
+                        .insert(PathBuf::from(dir), CachedDir { key, files });
+                    current = Some(PathBuf::from(dir));
+                }
+                ["file", size, digest, source, chart, scale, time, edition, name] => {
+                    let Some(dir) = current.as_ref().and_then(|dir| cache.dirs.get_mut(dir)) else {
+                        return Err(err("File outside of a directory"));
+                    };
+                    let digest = match *digest {
+                        "" => None,
+                        hex => Some(Digest::from_hex(hex).ok_or_else(|| err("Invalid hash"))?),
+                    };
+                    let optional = |v: &str| (!v.is_empty()).then(|| v.parse::<u32>()).transpose();
+                    let id = ChartId::new(
+                        (!source.is_empty()).then(|| Arc::from(*source)),
+                        Arc::from(*chart),
+                        optional(scale).map_err(|_| err("Invalid scale"))?,
+                    );
+                    let time: f64 = time.parse().map_err(|_| err("Invalid time"))?;
+                    let time = UTCDateTime::from(UnixTimestamp::from_seconds_f64(time));
+                    let edition = optional(edition).map_err(|_| err("Invalid edition"))?;
+                    dir.files.push(CachedFile {
+                        name: name.to_string(),
+                        id,
+                        version: ChartVersion::new(time, edition),
+                        size: size.parse().map_err(|_| err("Invalid size"))?,
+                        digest,
+                    });
+                }
+                _ => return Err(err("Unrecognized entry")),
+            }
+        }
+        for dir in cache.dirs.values_mut() {
+            dir.files.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(cache)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = format!("{HEADER}\n");
+        if let Some(parser) = self.parser {
+            let _ = writeln!(out, "#parser\t{parser}");
+        }
+        for (dir, cached) in &self.dirs {
+            let _ = writeln!(out, "dir\t{}\t{}", cached.key, dir.display());
+            for f in &cached.files {
+                let digest = f.digest.map(|d| d.to_hex()).unwrap_or_default();
+                let scale = f.id.scale().map(|s| s.to_string()).unwrap_or_default();
+                let time = UnixTimestamp::from(f.version.timestamp())
+                    .get_offset()
+                    .as_seconds_f64();
+                let edition = f
+                    .version
+                    .edition()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "file\t{}\t{digest}\t{}\t{}\t{scale}\t{time}\t{edition}\t{}",
+                    f.size,
+                    f.id.source().unwrap_or_default(),
+                    f.id.name(),
+                    f.name
+                );
+            }
+        }
+        std::fs::write(path, out).context(Phase::Setup, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::TempDir;
+
+    const KEY: &str = "0123456789abcdef0123456789abcdef01234567";
+    const HASH: &str = "89abcdef0123456789abcdef0123456789abcdef";
+
+    fn entries(names: &[&str]) -> Vec<(OsString, u64, Option<SystemTime>)> {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        names
+            .iter()
+            .map(|name| (OsString::from(name), 100, Some(modified)))
+            .collect()
+    }
+
+    #[test]
+    fn key_is_the_sha1_of_the_listing() {
+        // Nothing listed hashes nothing at all
+        assert_eq!(
+            DirCache::key(Vec::new()).to_hex(),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+        let key = DirCache::key(entries(&["a.pdf", "b.pdf"]));
+        assert_eq!(key, DirCache::key(entries(&["b.pdf", "a.pdf"])));
+        assert_ne!(key, DirCache::key(entries(&["a.pdf"])));
+        assert_ne!(key, DirCache::key(entries(&["a.pdf", "c.pdf"])));
+        let mut resized = entries(&["a.pdf", "b.pdf"]);
+        resized[1].1 = 101;
+        assert_ne!(key, DirCache::key(resized));
+        let mut touched = entries(&["a.pdf", "b.pdf"]);
+        touched[1].2 = None;
+        assert_ne!(key, DirCache::key(touched));
+    }
+
+    #[test]
+    fn loads_a_v2_cache() {
+        let dir = TempDir::new("dircache");
+        let tulsa = "USGS\tOK_Tulsa\t24000\t1577836800\t2\tOK_Tulsa_20200101_TM_geo.pdf";
+        let ada = "\tOK_Ada\t\t1577836800\t\tOK_Ada_20200101_TM_geo.pdf";
+        let lines = [
+            HEADER.to_string(),
+            format!("#parser\t{KEY}"),
+            format!("dir\t{KEY}\tOK"),
+            format!("file\t100\t{HASH}\t{tulsa}"),
+            format!("file\t50\t\t{ada}"),
+        ];
+        let text = lines.join("\n");
+        let path = dir.write("cache.tsv", text.as_bytes());
+        let cache = DirCache::load(&path).unwrap();
+        let key = Digest::from_hex(KEY).unwrap();
+        assert_eq!(cache.parser(), Some(key));
+        let cached = cache.get(Path::new("OK"), key).unwrap();
+        assert_eq!(cached.files.len(), 2);
+        let ada = cached.file("OK_Ada_20200101_TM_geo.pdf").unwrap();
+        assert_eq!((ada.size, ada.digest), (50, None));
+        assert_eq!((ada.id.source(), ada.id.scale()), (None, None));
+        let tulsa = cached.file("OK_Tulsa_20200101_TM_geo.pdf").unwrap();
+        assert_eq!(tulsa.digest, Digest::from_hex(HASH));
+        assert_eq!(tulsa.id.source(), Some("USGS"));
+        assert_eq!(tulsa.id.scale(), Some(24000));
+        assert_eq!(tulsa.version.edition(), Some(2));
+        assert!(cache.get(Path::new("OK"), Digest::default()).is_none());
+
+        let saved = dir.path().join("saved.tsv");
+        cache.save(&saved).unwrap();
+        let reloaded = DirCache::load(&saved).unwrap();
+        let files = &reloaded.get(Path::new("OK"), key).unwrap().files;
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["OK_Ada_20200101_TM_geo.pdf", "OK_Tulsa_20200101_TM_geo.pdf"]
+        );
+        assert_eq!(files[1].version, tulsa.version);
+    }
+
+    #[test]
+    fn starts_over_from_an_older_cache() {
+        let dir = TempDir::new("dircache-v1");
+        let text = format!("# charts-clean dir cache v1\ndir\t{KEY}\tOK\n");
+        let cache = DirCache::load(&dir.write("cache.tsv", text.as_bytes())).unwrap();
+        assert!(cache.is_empty() && cache.parser().is_none());
+    }
+
+    #[test]
+    fn rejects_a_damaged_cache() {
+        let dir = TempDir::new("dircache-damaged");
+        let orphan = format!("{HEADER}\nfile\t1\t\t\tOK_Ada\t\t0\t\tOK_Ada.pdf\n");
+        let bad_key = format!("{HEADER}\ndir\tnot-a-key\tOK\n");
+        let bad_size =
+            format!("{HEADER}\ndir\t{KEY}\tOK\nfile\tbig\t\t\tOK_Ada\t\t0\t\tOK_Ada.pdf\n");
+        for (text, expected) in [
+            (orphan, "line 2: File outside of a directory"),
+            (bad_key, "line 2: Invalid key"),
+            (bad_size, "line 3: Invalid size"),
+        ] {
+            let path = dir.write("cache.tsv", text.as_bytes());
+            let e = DirCache::load(&path).unwrap_err();
+            assert!(e.to_string().contains(expected), "{e}");
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use irox_log::log::{debug, info};
+
+use crate::{CancelToken, Error, ErrorContext, ErrorKind, Phase, Throttle};
+
+const HEADER: &str = "# charts-clean partial v1";
+
+///
+/// How long to wait to connect, and for each read or write
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+///
+/// The suffix of a download that hasn't finished yet
+pub const PARTIAL_SUFFIX: &str = ".partial";
+
+///
+/// The suffix of the bookkeeping kept beside a download that hasn't finished, see
+/// [`Downloader::fetch`]
+pub const PARTIAL_INFO_SUFFIX: &str = ".partial.tsv";
+
+///
+/// How much of a file is asked for at once, unless set with [`Downloader::with_chunk_size`]
+pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+///
+/// Whether the file is a download that hasn't finished, or its bookkeeping, so that a scan
+/// doesn't take one for a new edition
+pub(crate) fn is_partial(name: &OsStr) -> bool {
+    let name = name.as_encoded_bytes();
+    name.ends_with(PARTIAL_SUFFIX.as_bytes()) || name.ends_with(PARTIAL_INFO_SUFFIX.as_bytes())
+}
+
+fn with_suffix(dest: &Path, suffix: &str) -> PathBuf {
+    let mut path = dest.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+///
+/// Connects to the host of a plain `http://` URL, giving up on the connection and on each read
+/// or write after `timeout`.  Returns the stream along with the URL's authority and path.
+pub(crate) fn connect(url: &str, timeout: Duration) -> Result<(TcpStream, String, String), String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err("only http:// URLs are supported".to_string());
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => rest.split_at(idx),
+        None => (rest, "/"),
+    };
+    let host_port = match authority.contains(':') {
+        true => authority.to_string(),
+        false => format!("{authority}:80"),
+    };
+    let addr = host_port
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| "no address".to_string())?;
+    let stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    stream
+        .set_write_timeout(Some(timeout))
+        .map_err(|e| e.to_string())?;
+    Ok((stream, authority.to_string(), path.to_string()))
+}
+
+///
+/// What's known of a download that hasn't finished.
+#[derive(Debug, Clone, Default)]
+struct PartialInfo {
+    url: String,
+    /// The full size, once the server's said
+    size: Option<u64>,
+    /// The ETag, or failing that the Last-Modified, of what's being downloaded, so that a file
+    /// replaced on the server since isn't finished with the new one's bytes
+    validator: Option<String>,
+}
+
+impl PartialInfo {
+    fn new(url: &str) -> PartialInfo {
+        PartialInfo {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Loads the info at the path, if there is one that can be read
+    fn load(path: &Path) -> Result<Option<PartialInfo>, Error> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(Phase::Fetch, path),
+        };
+        let mut info = PartialInfo::default();
+        for line in text.lines() {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            match line.split_once('\t') {
+                Some(("url", url)) => info.url = url.to_string(),
+                Some(("size", size)) => match size.parse() {
+                    Ok(size) => info.size = Some(size),
+                    Err(_) => return Ok(None),
+                },
+                Some(("validator", validator)) => info.validator = Some(validator.to_string()),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(info))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut out = format!("{HEADER}\nurl\t{}\n", self.url);
+        if let Some(size) = self.size {
+            let _ = writeln!(out, "size\t{size}");
+        }
+        if let Some(validator) = &self.validator {
+            let _ = writeln!(out, "validator\t{validator}");
+        }
+        std::fs::write(path, out).context(Phase::Fetch, path)
+    }
+}
+
+///
+/// The status line and headers of a response, and the connection its body is to be read from.
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: BufReader<TcpStream>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        let mut headers = self.headers.iter();
+        headers
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn content_length(&self) -> Result<u64, String> {
+        let encoding = self.header("Transfer-Encoding");
+        if encoding.is_some_and(|te| !te.eq_ignore_ascii_case("identity")) {
+            return Err("chunked responses aren't supported".to_string());
+        }
+        let length = self.header("Content-Length").ok_or("no Content-Length")?;
+        length
+            .parse()
+            .map_err(|_| format!("invalid Content-Length {length}"))
+    }
+
+    ///
+    /// The first byte and the full size of a `206`, from its `Content-Range: bytes START-END/SIZE`
+    fn content_range(&self) -> Result<(u64, u64), String> {
+        let range = self.header("Content-Range").ok_or("no Content-Range")?;
+        let invalid = || format!("invalid Content-Range {range}");
+        let range_size = range.strip_prefix("bytes ").and_then(|r| r.split_once('/'));
+        let (span, size) = range_size.ok_or_else(invalid)?;
+        let start = span
+            .split_once('-')
+            .map(|(start, _)| start)
+            .ok_or_else(invalid)?;
+        Ok((
+            start.parse().map_err(|_| invalid())?,
+            size.parse().map_err(|_| invalid())?,
+        ))
+    }
+
+    fn validator(&self) -> Option<String> {
+        let etag = self.header("ETag").filter(|etag| !etag.starts_with("W/"));
+        etag.or_else(|| self.header("Last-Modified"))
+            .map(str::to_string)
+    }
+}
+
+///
+/// A finished download.
+#[derive(Debug, Clone)]
+pub struct Download {
+    pub path: PathBuf,
+    pub size: u64,
+    /// How much of it an earlier, interrupted, fetch had already downloaded
+    pub resumed_from: u64,
+}
+
+///
+/// Downloads files from plain `http://` URLs a chunk at a time with ranged requests, so that
+/// an interrupted download of a multi-gigabyte bundle carries on from where it stopped instead
+/// of starting again.  The bytes so far are kept in `<dest>.partial`, synced after every chunk,
+/// beside a `<dest>.partial.tsv` recording the URL, the full size and the server's validator
+/// for the file, and only renamed to `dest` once it's all there.  A server that ignores ranges,
+/// or has a different file at the URL since, is downloaded again from the start.
+pub struct Downloader {
+    chunk_size: u64,
+    throttle: Arc<Throttle>,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Downloader::new()
+    }
+}
+
+impl Downloader {
+    pub fn new() -> Downloader {
+        Downloader {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            throttle: Arc::new(Throttle::unlimited()),
+        }
+    }
+
+    #[must_use]
+    pub fn with_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    ///
+    /// Paces the requests and the bytes downloaded
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    ///
+    /// Downloads the URL to `dest`, carrying on from a partial download an earlier fetch of
+    /// the same URL left.  The token is checked between chunks, leaving the partial download to
+    /// be carried on with.
+    pub fn fetch(&self, url: &str, dest: &Path, cancel: &CancelToken) -> Result<Download, Error> {
+        let err = |msg: String| {
+            let e = std::io::Error::other(format!("{url}: {msg}"));
+            Error::new(ErrorKind::IOError(e), Phase::Fetch).with_path(dest)
+        };
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Fetch, parent)?;
+        }
+        let partial = with_suffix(dest, PARTIAL_SUFFIX);
+        let info_path = with_suffix(dest, PARTIAL_INFO_SUFFIX);
+        // A partial download of another URL, or without its info, can't be carried on with
+        let mut info = match PartialInfo::load(&info_path)? {
+            Some(info) if info.url == url => info,
+            _ => {
+                discard(&partial)?;
+                PartialInfo::new(url)
+            }
+        };
+        let mut offset = match std::fs::metadata(&partial) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e).context(Phase::Fetch, &partial),
+        };
+        let resumed_from = offset;
+        if offset > 0 {
+            info!("Resuming {url} from byte {offset}");
+        }
+        let mut restarted = false;
+        while info.size != Some(offset) {
+            cancel.check(Phase::Fetch).map_err(|e| e.with_path(dest))?;
+            let mut restart = |reason: &str| {
+                if restarted {
+                    return Err(err(format!("{reason}, again after starting over")));
+                }
+                debug!("Downloading {url} again from the start: {reason}");
+                restarted = true;
+                discard(&partial)?;
+                Ok(PartialInfo::new(url))
+            };
+            if info.size.is_some_and(|size| offset > size) {
+                info = restart("the partial download is larger than the file")?;
+                offset = 0;
+                continue;
+            }
+            let mut end = offset + self.chunk_size - 1;
+            if let Some(size) = info.size {
+                end = end.min(size - 1);
+            }
+            let response = self
+                .request(url, offset, end, info.validator.as_deref())
+                .map_err(err)?;
+            let append = match response.status {
+                206 => {
+                    let (start, size) = response.content_range().map_err(err)?;
+                    if info.size.is_some_and(|known| known != size) {
+                        info = restart("the file's size changed")?;
+                        offset = 0;
+                        continue;
+                    }
+                    if start != offset {
+                        return Err(err(format!("asked for byte {offset}, sent from {start}")));
+                    }
+                    if info.size.is_none() {
+                        info.size = Some(size);
+                        info.validator = response.validator();
+                    }
+                    true
+                }
+                // The server ignores ranges, or the file's changed since the partial download
+                200 => {
+                    if offset > 0 {
+                        debug!("{url} was sent whole, downloading it from the start");
+                    }
+                    info.size = Some(response.content_length().map_err(err)?);
+                    info.validator = response.validator();
+                    offset = 0;
+                    false
+                }
+                416 => {
+                    info = restart("the server couldn't send the rest")?;
+                    offset = 0;
+                    continue;
+                }
+                status => return Err(err(format!("unexpected response {status}"))),
+            };
+            let length = response.content_length().map_err(err)?;
+            let written = self.write_body(response.body, &partial, append, length);
+            // Whatever arrived is kept, even if the connection broke off
+            info.save(&info_path)?;
+            let written = written?;
+            offset += written;
+            if written < length {
+                let received = offset - resumed_from;
+                return Err(err(format!(
+                    "the connection broke off after {received} bytes"
+                )));
+            }
+            // Asking again would only get nothing again
+            if written == 0 && info.size != Some(offset) {
+                return Err(err(format!("nothing was sent from byte {offset}")));
+            }
+        }
+        std::fs::rename(&partial, dest).context(Phase::Fetch, dest)?;
+        discard(&info_path)?;
+        Ok(Download {
+            path: dest.to_path_buf(),
+            size: offset,
+            resumed_from,
+        })
+    }
+
+    ///
+    /// Asks for bytes `start` to `end` of the URL, inclusive, unless the file's changed from
+    /// the one the validator's of
+    fn request(
+        &self,
+        url: &str,
+        start: u64,
+        end: u64,
+        validator: Option<&str>,
+    ) -> Result<Response, String> {
+        let (mut stream, authority, path) = connect(url, TIMEOUT)?;
+        let mut request =
+            format!("GET {path} HTTP/1.1\r\nHost: {authority}\r\nRange: bytes={start}-{end}\r\n");
+        if let Some(validator) = validator {
+            let _ = write!(request, "If-Range: {validator}\r\n");
+        }
+        request.push_str("Connection: close\r\nUser-Agent: charts-clean\r\n\r\n");
+        self.throttle.request();
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let mut body = BufReader::new(stream);
+        let mut line = String::new();
+        body.read_line(&mut line).map_err(|e| e.to_string())?;
+        let status = line.split(' ').nth(1).and_then(|code| code.parse().ok());
+        let Some(status) = status else {
+            return Err(format!("unexpected response: {}", line.trim_end()));
+        };
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            body.read_line(&mut line).map_err(|e| e.to_string())?;
+            let Some((key, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    ///
+    /// Writes up to `length` bytes of the body to the partial download, appending to it or
+    /// starting it over, and syncs it.  Returns how many were written.
+    fn write_body(
+        &self,
+        body: impl Read,
+        partial: &Path,
+        append: bool,
+        length: u64,
+    ) -> Result<u64, Error> {
+        let mut output = match append {
+            true => OpenOptions::new().create(true).append(true).open(partial),
+            false => File::create(partial),
+        }
+        .context(Phase::Fetch, partial)?;
+        let mut body = body.take(length);
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut written = 0;
+        loop {
+            // A read that fails is a connection that broke off, the rest comes with the next
+            let read = body.read(&mut buf).unwrap_or_default();
+            if read == 0 {
+                break;
+            }
+            self.throttle.transfer(read as u64);
+            output
+                .write_all(&buf[..read])
+                .context(Phase::Fetch, partial)?;
+            written += read as u64;
+        }
+        output.sync_data().context(Phase::Fetch, partial)?;
+        Ok(written)
+    }
+}
+
+fn discard(path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).context(Phase::Fetch, path),
+        _ => Ok(()),
+    }
+}
+
+///
+/// A chart to fetch, and where to put it.
+#[derive(Debug, Clone)]
+pub struct FetchEntry {
+    pub url: String,
+    /// The directory under the root it goes in
+    pub dir: PathBuf,
+}
+
+impl FetchEntry {
+    ///
+    /// Where it goes under the root: its directory, and the last segment of its URL
+    pub fn relative_path(&self) -> Option<PathBuf> {
+        let path = self.url.split(['?', '#']).next().unwrap_or_default();
+        let name = path
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty() && *name != "..")?;
+        Some(self.dir.join(name))
+    }
+}
+
+///
+/// The charts `fetch` downloads into an archive, a `URL [DIR]` line for each, `DIR` relative to
+/// the root and the root itself if it's left out.  Blank lines and lines starting with `#` are
+/// ignored.
+#[derive(Debug, Clone, Default)]
+pub struct FetchList {
+    pub entries: Vec<FetchEntry>,
+}
+
+impl FetchList {
+    pub fn load(path: &Path) -> Result<FetchList, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        let mut list = FetchList::default();
+        for (idx, line) in text.lines().enumerate() {
+            let err = |msg: &str| Error::usage(format!("line {}: {msg}", idx + 1)).with_path(path);
+            let line = line.trim();
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let (url, dir) = match line.split_once(char::is_whitespace) {
+                Some((url, dir)) => (url, PathBuf::from(dir.trim())),
+                None => (line, PathBuf::new()),
+            };
+            if !url.starts_with("http://") {
+                return Err(err("Only http:// URLs are supported"));
+            }
+            if !dir
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+            {
+                return Err(err("The directory must be relative to the root, inside it"));
+            }
+            let entry = FetchEntry {
+                url: url.to_string(),
+                dir,
+            };
+            if entry.relative_path().is_none() {
+                return Err(err("The URL doesn't end in a file name"));
+            }
+            list.entries.push(entry);
+        }
+        Ok(list)
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use irox_time::format::FormatError;
+
+///
+/// The stage of a run an [`Error`] occurred in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Phase {
+    Setup,
+    Scan,
+    Parse,
+    Copy,
+    Validate,
+    Scrub,
+    Delete,
+    Fetch,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Setup => write!(f, "setup"),
+            Phase::Scan => write!(f, "scan"),
+            Phase::Parse => write!(f, "parse"),
+            Phase::Copy => write!(f, "copy"),
+            Phase::Validate => write!(f, "validate"),
+            Phase::Scrub => write!(f, "scrub"),
+            Phase::Delete => write!(f, "delete"),
+            Phase::Fetch => write!(f, "fetch"),
+        }
+    }
+}
+
+///
+/// A date or time that didn't parse, wrapped as irox's [`FormatError`] isn't an error itself
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct TimeFormatError(pub FormatError);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ErrorKind {
+    #[error("IOError: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("FormatError: {0}")]
+    FormatError(#[from] TimeFormatError),
+    #[error("UsageError: {0}")]
+    UsageError(String),
+    #[error("ConfigError: {0}")]
+    ConfigError(String),
+    #[error("ManifestError: {0}")]
+    ManifestError(String),
+    #[error("VerifyError: {0}")]
+    VerifyError(String),
+    /// The work was cancelled, or ran out of time, before it finished
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+    /// Another run holds the lock on the archive
+    #[error("Locked: {0}")]
+    Locked(String),
+}
+
+///
+/// An error, along with the phase of the run and the file it occurred on.
+#[derive(Debug, thiserror::Error)]
+#[error("{phase} failed{}: {kind}", on_path(path.as_deref()))]
+pub struct Error {
+    #[source]
+    kind: ErrorKind,
+    phase: Phase,
+    path: Option<PathBuf>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, phase: Phase) -> Error {
+        Error {
+            kind,
+            phase,
+            path: None,
+        }
+    }
+
+    pub fn usage(msg: impl Into<String>) -> Error {
+        Error::new(ErrorKind::UsageError(msg.into()), Phase::Setup)
+    }
+
+    pub fn verify(msg: impl Into<String>) -> Error {
+        Error::new(ErrorKind::VerifyError(msg.into()), Phase::Copy)
+    }
+
+    ///
+    /// A precondition of applying a manifest that doesn't hold
+    pub fn validate(msg: impl Into<String>) -> Error {
+        Error::new(ErrorKind::VerifyError(msg.into()), Phase::Validate)
+    }
+
+    #[must_use]
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Error {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+fn on_path(path: Option<&Path>) -> String {
+    path.map(|path| format!(" on {}", path.display()))
+        .unwrap_or_default()
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Error::new(value.into(), Phase::Setup)
+    }
+}
+
+impl From<FormatError> for Error {
+    fn from(value: FormatError) -> Self {
+        Error::new(TimeFormatError(value).into(), Phase::Setup)
+    }
+}
+
+///
+/// Attaches the phase and offending path to the error side of a result.
+pub trait ErrorContext<T> {
+    fn context(self, phase: Phase, path: &Path) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ErrorContext<T> for Result<T, E> {
+    fn context(self, phase: Phase, path: &Path) -> Result<T, Error> {
+        self.map_err(|e| {
+            let mut e = e.into();
+            e.phase = phase;
+            e.with_path(path)
+        })
+    }
+}
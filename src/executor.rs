@@ -0,0 +1,915 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irox_log::log::info;
+use irox_time::datetime::UTCDateTime;
+use irox_time::format::iso8601::BASIC_CALENDAR_DATE;
+
+use crate::copy::move_verified;
+use crate::{Error, ErrorContext, Phase, Quarantine, Throttle};
+
+///
+/// Carries out the removal of superseded files.  The planner decides what goes, an executor
+/// decides what "goes" means: deleted, moved somewhere, or just written down for later.
+pub trait Executor: Send + Sync {
+    ///
+    /// Short name of the behavior, recorded in the journal with every removal
+    fn name(&self) -> &'static str;
+
+    ///
+    /// Removes the file at `path` under `root`, returning where it went if it still exists
+    /// somewhere
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error>;
+
+    ///
+    /// Removes the file at `path`, superseded by the kept file at `by`.  Only executors that
+    /// place files relative to their replacement need more than [`Executor::remove`].
+    fn remove_superseded(
+        &self,
+        root: &Path,
+        path: &Path,
+        _by: Option<&Path>,
+    ) -> Result<Option<PathBuf>, Error> {
+        self.remove(root, path)
+    }
+
+    ///
+    /// False if the executor leaves the files where they are, so its removals aren't journaled
+    fn changes_files(&self) -> bool {
+        true
+    }
+
+    ///
+    /// Called once every removal has been made, to flush or close anything held open
+    fn finish(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+fn relative<'a>(root: &Path, path: &'a Path) -> &'a Path {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative.strip_prefix("/").unwrap_or(relative)
+}
+
+///
+/// Deletes files outright.
+#[derive(Default)]
+pub struct DeleteExecutor {
+    throttle: Arc<Throttle>,
+}
+
+impl DeleteExecutor {
+    pub fn new(throttle: Arc<Throttle>) -> DeleteExecutor {
+        DeleteExecutor { throttle }
+    }
+}
+
+impl Executor for DeleteExecutor {
+    fn name(&self) -> &'static str {
+        "delete"
+    }
+
+    fn remove(&self, _root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        info!("Will remove {}", path.display());
+        self.throttle.request();
+        std::fs::remove_file(path).context(Phase::Delete, path)?;
+        Ok(None)
+    }
+}
+
+impl Executor for Quarantine {
+    fn name(&self) -> &'static str {
+        "quarantine"
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        info!("Will quarantine {}", path.display());
+        self.hold(root, path).map(Some)
+    }
+}
+
+///
+/// Moves files into a freedesktop.org trash directory (`~/.local/share/Trash` by default), with
+/// the `.trashinfo` a desktop needs to restore them.
+pub struct TrashExecutor {
+    dir: PathBuf,
+    throttle: Arc<Throttle>,
+}
+
+impl TrashExecutor {
+    pub fn new(dir: impl Into<PathBuf>, throttle: Arc<Throttle>) -> TrashExecutor {
+        TrashExecutor {
+            dir: dir.into(),
+            throttle,
+        }
+    }
+
+    ///
+    /// `$XDG_DATA_HOME/Trash`, falling back to `~/.local/share/Trash`
+    pub fn default_dir() -> PathBuf {
+        if let Some(data) = std::env::var_os("XDG_DATA_HOME") {
+            return PathBuf::from(data).join("Trash");
+        }
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home).join(".local/share/Trash");
+        }
+        PathBuf::from(".Trash")
+    }
+}
+
+impl Executor for TrashExecutor {
+    fn name(&self) -> &'static str {
+        "trash"
+    }
+
+    fn remove(&self, _root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        info!("Will trash {}", path.display());
+        let files = self.dir.join("files");
+        let infos = self.dir.join("info");
+        std::fs::create_dir_all(&infos).context(Phase::Setup, &infos)?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        // Claim a name by creating its .trashinfo, as the spec asks
+        let mut suffix = 1;
+        let (dest, info_path, mut info) = loop {
+            let candidate = match suffix {
+                1 => name.to_string(),
+                n => format!("{name}.{n}"),
+            };
+            let info = infos.join(format!("{candidate}.trashinfo"));
+            match OpenOptions::new().write(true).create_new(true).open(&info) {
+                Ok(file) => break (files.join(candidate), info, file),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => suffix += 1,
+                Err(e) => return Err(e).context(Phase::Copy, &info),
+            }
+        };
+        let original = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let now = UTCDateTime::now();
+        let (h, m, s) = now.get_time().as_hms();
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}T{h:02}:{m:02}:{s:02}\n",
+            original.display(),
+            now.get_date()
+        );
+        let moved = info
+            .write_all(contents.as_bytes())
+            .context(Phase::Copy, &dest)
+            .and_then(|()| move_verified(path, &dest, false, &self.throttle));
+        if let Err(e) = moved {
+            // Release the name, a .trashinfo without its file would list a file that isn't there
+            drop(info);
+            let _ = std::fs::remove_file(&info_path);
+            return Err(e);
+        }
+        Ok(Some(dest))
+    }
+}
+
+///
+/// The directory superseded editions are kept in by the [`OldDirExecutor`].  Scans skip it.
+pub const OLD_DIR: &str = ".old";
+
+///
+/// Moves superseded files into an [`OLD_DIR`] next to the file that replaced them, keeping each
+/// chart's history alongside it instead of in a central quarantine.  A file already kept there
+/// under the same name is never replaced; the removal fails instead.
+pub struct OldDirExecutor {
+    verify: bool,
+    throttle: Arc<Throttle>,
+}
+
+impl OldDirExecutor {
+    pub fn new(throttle: Arc<Throttle>) -> OldDirExecutor {
+        OldDirExecutor {
+            verify: false,
+            throttle,
+        }
+    }
+
+    ///
+    /// Re-reads files that had to be copied (across filesystems) before removing the original
+    #[must_use]
+    pub fn with_verify(mut self, verify: bool) -> OldDirExecutor {
+        self.verify = verify;
+        self
+    }
+}
+
+impl Executor for OldDirExecutor {
+    fn name(&self) -> &'static str {
+        "old-dir"
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        self.remove_superseded(root, path, None)
+    }
+
+    fn remove_superseded(
+        &self,
+        _root: &Path,
+        path: &Path,
+        by: Option<&Path>,
+    ) -> Result<Option<PathBuf>, Error> {
+        let next_to = by.unwrap_or(path);
+        let dir = next_to.parent().unwrap_or(Path::new(".")).join(OLD_DIR);
+        let dest = dir.join(path.file_name().unwrap_or_default());
+        info!("Will move {} to {}", path.display(), dest.display());
+        std::fs::create_dir_all(&dir).context(Phase::Copy, &dir)?;
+        move_verified(path, &dest, self.verify, &self.throttle)?;
+        Ok(Some(dest))
+    }
+}
+
+///
+/// Appended to a file's name for its [`MarkerExecutor`] marker
+pub const MARKER_SUFFIX: &str = ".superseded";
+
+///
+/// Leaves superseded files where they are, writing a `<file>.superseded` marker next to each
+/// for a downstream sync or cleanup to act on, where this tool can't remove anything itself.
+/// The marker gives the path under the root of the file that superseded it, ie
+/// `superseded_by = OK/OK_Tulsa_20230126_TM_geo.pdf`, or says the chart was `withdrawn`.
+/// Markers are skipped by scans.
+#[derive(Default)]
+pub struct MarkerExecutor;
+
+impl MarkerExecutor {
+    pub fn marker_path(path: &Path) -> PathBuf {
+        let mut marker = path.as_os_str().to_os_string();
+        marker.push(MARKER_SUFFIX);
+        PathBuf::from(marker)
+    }
+}
+
+impl Executor for MarkerExecutor {
+    fn name(&self) -> &'static str {
+        "marker"
+    }
+
+    fn changes_files(&self) -> bool {
+        false
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        self.remove_superseded(root, path, None)
+    }
+
+    fn remove_superseded(
+        &self,
+        root: &Path,
+        path: &Path,
+        by: Option<&Path>,
+    ) -> Result<Option<PathBuf>, Error> {
+        let marker = MarkerExecutor::marker_path(path);
+        info!("Will mark {} as superseded", path.display());
+        let text = match by {
+            Some(by) => format!("superseded_by = {}\n", relative(root, by).display()),
+            None => "withdrawn = true\n".to_string(),
+        };
+        std::fs::write(&marker, text).context(Phase::Delete, &marker)?;
+        // The file is still there, for whatever acts on the marker to deal with
+        Ok(None)
+    }
+}
+
+///
+/// Hard-links files into a history directory, as `<dir>/<path relative to the scan root>`,
+/// before unlinking the original.  No data is copied, so the directory must be on the same
+/// filesystem as the archive.
+pub struct HardlinkExecutor {
+    dir: PathBuf,
+    throttle: Arc<Throttle>,
+}
+
+impl HardlinkExecutor {
+    pub fn new(dir: impl Into<PathBuf>, throttle: Arc<Throttle>) -> HardlinkExecutor {
+        HardlinkExecutor {
+            dir: dir.into(),
+            throttle,
+        }
+    }
+}
+
+impl Executor for HardlinkExecutor {
+    fn name(&self) -> &'static str {
+        "hardlink"
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        let dest = self.dir.join(relative(root, path));
+        info!("Will link {} to {}", path.display(), dest.display());
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Copy, parent)?;
+        }
+        self.throttle.request();
+        std::fs::hard_link(path, &dest).context(Phase::Copy, &dest)?;
+        std::fs::remove_file(path).context(Phase::Delete, path)?;
+        Ok(Some(dest))
+    }
+}
+
+///
+/// Appends files to a tar archive, `<dir>/<YYYYMMDD>-<time>.tar` for each run, before deleting
+/// them.
+///
+/// An archive left unfinished by an interrupted run is picked up by the next one instead of
+/// starting another: its complete entries are kept, a partially written last entry is cut off,
+/// and files that were archived but not yet deleted, matched by size and hash, are deleted
+/// without being archived again.
+pub struct ArchiveExecutor {
+    path: PathBuf,
+    verify: bool,
+    throttle: Arc<Throttle>,
+    tar: Mutex<Option<OpenTar>>,
+}
+
+///
+/// The archive being appended to, and what's in it
+struct OpenTar {
+    file: File,
+    path: PathBuf,
+    entries: BTreeMap<String, TarEntry>,
+}
+
+impl ArchiveExecutor {
+    pub fn new(dir: &Path, throttle: Arc<Throttle>) -> ArchiveExecutor {
+        let today = UTCDateTime::now().get_date().format(&BASIC_CALENDAR_DATE);
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        ArchiveExecutor {
+            path: dir.join(format!("{today}-{secs}.tar")),
+            verify: false,
+            throttle,
+            tar: Mutex::new(None),
+        }
+    }
+
+    ///
+    /// Read the archive back and compare each entry before deleting its file
+    #[must_use]
+    pub fn with_verify(mut self, verify: bool) -> ArchiveExecutor {
+        self.verify = verify;
+        self
+    }
+
+    ///
+    /// Where this run's archive is, unless it resumes an unfinished one
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    ///
+    /// The newest unfinished archive in the directory, cut back to its last complete entry, or
+    /// a new one
+    fn open(&self) -> Result<OpenTar, Error> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir).context(Phase::Setup, dir)?;
+        let mut archives: Vec<PathBuf> = std::fs::read_dir(dir)
+            .context(Phase::Setup, dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tar"))
+            .collect();
+        archives.sort();
+        for path in archives.into_iter().rev() {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .context(Phase::Copy, &path)?;
+            let index = read_tar_index(&mut file).context(Phase::Copy, &path)?;
+            if index.finished {
+                continue;
+            }
+            file.set_len(index.end).context(Phase::Copy, &path)?;
+            info!(
+                "Resuming the unfinished archive {} after {} complete entries",
+                path.display(),
+                index.entries.len()
+            );
+            return Ok(OpenTar {
+                file,
+                path,
+                entries: index.entries,
+            });
+        }
+        let file = OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .write(true)
+            .open(&self.path)
+            .context(Phase::Copy, &self.path)?;
+        Ok(OpenTar {
+            file,
+            path: self.path.clone(),
+            entries: BTreeMap::new(),
+        })
+    }
+}
+
+impl OpenTar {
+    ///
+    /// Whether the archive already has a complete entry for the file, the same size and hash
+    fn has(&mut self, name: &str, src: &Path) -> std::io::Result<bool> {
+        use std::io::{Seek, SeekFrom};
+
+        let Some(entry) = self.entries.get(name) else {
+            return Ok(false);
+        };
+        if std::fs::metadata(src)?.len() != entry.size {
+            return Ok(false);
+        }
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        let archived = crate::hash::hash_reader(&mut (&self.file).take(entry.size))?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(archived == crate::hash::hash_file(src)?)
+    }
+}
+
+impl Executor for ArchiveExecutor {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        let mut tar = self.tar.lock().unwrap_or_else(PoisonError::into_inner);
+        if tar.is_none() {
+            *tar = Some(self.open()?);
+        }
+        let Some(tar) = tar.as_mut() else {
+            return Ok(None);
+        };
+        info!("Will archive {} to {}", path.display(), tar.path.display());
+        let name = relative(root, path);
+        let key = name.to_string_lossy().to_string();
+        if tar.has(&key, path).context(Phase::Copy, path)? {
+            info!("{} is already in {}", key, tar.path.display());
+        } else {
+            let end = tar.file.metadata().context(Phase::Copy, &tar.path)?.len();
+            match append_tar_entry(&mut tar.file, name, path, self.verify, &self.throttle) {
+                Ok(entry) => {
+                    tar.entries.insert(key, entry);
+                }
+                Err(e) => {
+                    // Drop the partial entry so the next one lands where it belongs
+                    let _ = tar.file.set_len(end);
+                    return Err(e).context(Phase::Copy, path);
+                }
+            }
+        }
+        self.throttle.request();
+        std::fs::remove_file(path).context(Phase::Delete, path)?;
+        Ok(Some(tar.path.join(name)))
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        let mut tar = self.tar.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(tar) = tar.as_mut() {
+            tar.file
+                .write_all(&[0u8; 1024])
+                .and_then(|()| tar.file.sync_all())
+                .context(Phase::Copy, &tar.path)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Where an archived file's contents start in the archive, and how long they are
+#[derive(Debug, Copy, Clone)]
+struct TarEntry {
+    offset: u64,
+    size: u64,
+}
+
+///
+/// The complete entries of an archive, by name
+struct TarIndex {
+    entries: BTreeMap<String, TarEntry>,
+    /// Where the last complete entry ends
+    end: u64,
+    /// Whether the archive ends with its end-of-archive blocks
+    finished: bool,
+}
+
+///
+/// Reads the headers of an archive up to its end, or up to the first entry that's incomplete or
+/// damaged
+fn read_tar_index(tar: &mut File) -> std::io::Result<TarIndex> {
+    use std::io::{Seek, SeekFrom};
+
+    let len = tar.metadata()?.len();
+    let mut index = TarIndex {
+        entries: BTreeMap::new(),
+        end: 0,
+        finished: false,
+    };
+    let mut header = [0u8; 512];
+    loop {
+        if index.end + 512 > len {
+            return Ok(index);
+        }
+        tar.seek(SeekFrom::Start(index.end))?;
+        tar.read_exact(&mut header)?;
+        if header.iter().all(|b| *b == 0) {
+            index.finished = true;
+            return Ok(index);
+        }
+        let stored = parse_octal(header.get(148..156).unwrap_or_default());
+        let checksum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if (148..156).contains(&i) {
+                    u64::from(b' ')
+                } else {
+                    u64::from(*b)
+                }
+            })
+            .sum();
+        let Some(size) = parse_octal(header.get(124..136).unwrap_or_default()) else {
+            return Ok(index);
+        };
+        let data_end = index.end + 512 + size.div_ceil(512) * 512;
+        if stored != Some(checksum) || data_end > len {
+            return Ok(index);
+        }
+        let field = |range: std::ops::Range<usize>| {
+            let field = header.get(range).unwrap_or_default();
+            let field = field.split(|b| *b == 0).next().unwrap_or_default();
+            String::from_utf8_lossy(field).to_string()
+        };
+        let (prefix, name) = (field(345..500), field(0..100));
+        let name = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let entry = TarEntry {
+            offset: index.end + 512,
+            size,
+        };
+        index.entries.insert(name, entry);
+        index.end = data_end;
+    }
+}
+
+///
+/// Parses a tar header's octal number, padded with spaces or NULs
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let digits = String::from_utf8_lossy(field);
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).ok()
+}
+
+///
+/// A 512 byte ustar header block
+struct TarHeader([u8; 512]);
+
+impl TarHeader {
+    fn set(&mut self, offset: usize, len: usize, value: &[u8]) -> std::io::Result<()> {
+        let field = self.0.get_mut(offset..offset + len);
+        let Some(dst) = field.and_then(|f| f.get_mut(..value.len())) else {
+            return Err(std::io::Error::other("name too long for a tar header"));
+        };
+        dst.copy_from_slice(value);
+        Ok(())
+    }
+
+    ///
+    /// Sets a zero-padded octal number, leaving the last byte of the field as the terminator
+    fn set_octal(&mut self, offset: usize, len: usize, value: u64) -> std::io::Result<()> {
+        let digits = len - 1;
+        self.set(offset, len, format!("{value:0digits$o}").as_bytes())
+    }
+}
+
+///
+/// Writes a ustar header and the contents of `src` to the end of the archive
+fn append_tar_entry(
+    tar: &mut File,
+    name: &Path,
+    src: &Path,
+    verify: bool,
+    throttle: &Throttle,
+) -> std::io::Result<TarEntry> {
+    use std::io::{Seek, SeekFrom};
+
+    let meta = std::fs::metadata(src)?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let name = name.to_string_lossy();
+    let (prefix, name) = match name.len() {
+        0..=100 => ("", name.as_ref()),
+        _ => name
+            .char_indices()
+            .filter(|(i, c)| *c == '/' && *i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| {
+                (
+                    name.get(..i).unwrap_or_default(),
+                    name.get(i + 1..).unwrap_or_default(),
+                )
+            })
+            .next()
+            .ok_or_else(|| std::io::Error::other("name too long for a tar header"))?,
+    };
+    let mut header = TarHeader([0u8; 512]);
+    header.set(0, 100, name.as_bytes())?;
+    header.set_octal(100, 8, 0o644)?;
+    header.set_octal(108, 8, 0)?;
+    header.set_octal(116, 8, 0)?;
+    header.set_octal(124, 12, meta.len())?;
+    header.set_octal(136, 12, mtime)?;
+    // The checksum is computed with its own field as spaces
+    header.set(148, 8, b"        ")?;
+    header.set(156, 1, b"0")?;
+    header.set(257, 8, b"ustar\x0000")?;
+    header.set(345, 155, prefix.as_bytes())?;
+    let checksum: u64 = header.0.iter().map(|b| u64::from(*b)).sum();
+    header.set(148, 8, format!("{checksum:06o}\0 ").as_bytes())?;
+
+    let start = tar.seek(SeekFrom::End(0))?;
+    tar.write_all(&header.0)?;
+    let mut input = File::open(src)?;
+    let mut buf = vec![0u8; 1 << 16];
+    let mut written = 0u64;
+    loop {
+        let read = input.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        throttle.transfer(read as u64);
+        tar.write_all(buf.get(..read).unwrap_or_default())?;
+        written += read as u64;
+    }
+    let padding = (512 - written % 512) % 512;
+    tar.write_all(&vec![0u8; padding as usize])?;
+    tar.sync_data()?;
+    if written != meta.len() {
+        return Err(std::io::Error::other("file changed size while archiving"));
+    }
+    if verify {
+        tar.seek(SeekFrom::Start(start + 512))?;
+        let archived = crate::hash::hash_reader(&mut (&*tar).take(written))?;
+        if archived != crate::hash::hash_file(src)? {
+            return Err(std::io::Error::other("archived copy doesn't match"));
+        }
+        tar.seek(SeekFrom::End(0))?;
+    }
+    Ok(TarEntry {
+        offset: start + 512,
+        size: written,
+    })
+}
+
+///
+/// Acts on nothing, writing a shell script of the removals instead, for review or for running
+/// somewhere else.
+pub struct ScriptExecutor {
+    path: PathBuf,
+    script: Mutex<Option<File>>,
+}
+
+impl ScriptExecutor {
+    pub fn new(path: impl Into<PathBuf>) -> ScriptExecutor {
+        ScriptExecutor {
+            path: path.into(),
+            script: Mutex::new(None),
+        }
+    }
+}
+
+///
+/// Quotes a path for a POSIX shell
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+impl Executor for ScriptExecutor {
+    fn name(&self) -> &'static str {
+        "script"
+    }
+
+    fn changes_files(&self) -> bool {
+        false
+    }
+
+    fn remove(&self, _root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        let mut script = self.script.lock().unwrap_or_else(PoisonError::into_inner);
+        if script.is_none() {
+            let mut file = File::create(&self.path).context(Phase::Setup, &self.path)?;
+            file.write_all(b"#!/bin/sh\n# Removals written by charts-clean\nset -e\n")
+                .context(Phase::Setup, &self.path)?;
+            *script = Some(file);
+        }
+        let Some(script) = script.as_mut() else {
+            return Ok(None);
+        };
+        writeln!(script, "rm -- {}", shell_quote(path)).context(Phase::Setup, &self.path)?;
+        // The file is still there, but it's been dealt with as far as this run is concerned
+        Ok(None)
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        let mut script = self.script.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(script) = script.as_mut() {
+            script.sync_all().context(Phase::Setup, &self.path)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// How a profile removes superseded files, and where to.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub enum ExecutorKind {
+    #[default]
+    Delete,
+    /// Into the profile's quarantine
+    Quarantine,
+    /// Into a freedesktop.org trash, the user's own if no directory is given
+    Trash(Option<PathBuf>),
+    Hardlink(PathBuf),
+    /// Into a tar archive in the directory
+    Archive(PathBuf),
+    /// Written to a shell script at the path
+    Script(PathBuf),
+    /// Into an `.old` directory next to the kept file
+    OldDir,
+    /// Into a content addressed store in the directory
+    Store(PathBuf),
+    /// Into a cold tier in the directory, see [`crate::ColdTier`]
+    Cold(PathBuf),
+    /// Left in place, with a marker next to it for something downstream to act on
+    Marker,
+}
+
+impl FromStr for ExecutorKind {
+    type Err = String;
+
+    ///
+    /// Parses `delete`, `quarantine`, `trash[:DIR]`, `hardlink:DIR`, `archive:DIR`,
+    /// `script:FILE`, `old-dir`, `store:DIR`, `cold:DIR` or `marker`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(PathBuf::from(arg))),
+            None => (s, None),
+        };
+        let need = |arg: Option<PathBuf>| {
+            arg.ok_or_else(|| format!("{name} needs a path, ie {name}:PATH"))
+        };
+        match name {
+            "delete" => Ok(ExecutorKind::Delete),
+            "quarantine" => Ok(ExecutorKind::Quarantine),
+            "trash" => Ok(ExecutorKind::Trash(arg)),
+            "hardlink" => Ok(ExecutorKind::Hardlink(need(arg)?)),
+            "archive" => Ok(ExecutorKind::Archive(need(arg)?)),
+            "script" => Ok(ExecutorKind::Script(need(arg)?)),
+            "old-dir" => Ok(ExecutorKind::OldDir),
+            "store" => Ok(ExecutorKind::Store(need(arg)?)),
+            "cold" => Ok(ExecutorKind::Cold(need(arg)?)),
+            "marker" => Ok(ExecutorKind::Marker),
+            "cloud-delete" => {
+                Err("cloud-delete isn't supported, there are no cloud backends".to_string())
+            }
+            _ => Err(format!(
+                "Unknown executor {s}, expected delete, quarantine, trash, hardlink, archive, \
+                 script, old-dir, store, cold or marker"
+            )),
+        }
+    }
+}
+
+impl Display for ExecutorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutorKind::Delete => write!(f, "delete"),
+            ExecutorKind::Quarantine => write!(f, "quarantine"),
+            ExecutorKind::Trash(None) => write!(f, "trash"),
+            ExecutorKind::Trash(Some(dir)) => write!(f, "trash:{}", dir.display()),
+            ExecutorKind::Hardlink(dir) => write!(f, "hardlink:{}", dir.display()),
+            ExecutorKind::Archive(dir) => write!(f, "archive:{}", dir.display()),
+            ExecutorKind::Script(path) => write!(f, "script:{}", path.display()),
+            ExecutorKind::OldDir => write!(f, "old-dir"),
+            ExecutorKind::Store(dir) => write!(f, "store:{}", dir.display()),
+            ExecutorKind::Cold(dir) => write!(f, "cold:{}", dir.display()),
+            ExecutorKind::Marker => write!(f, "marker"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn old_dir_never_replaces_a_kept_file() {
+        let dir = TempDir::new("old-dir");
+        let old = dir.write("a/OK_Tulsa_20190126_TM_geo.pdf", b"new");
+        let kept = dir.write("a/.old/OK_Tulsa_20190126_TM_geo.pdf", b"earlier");
+        let by = dir.write("a/OK_Tulsa_20230126_TM_geo.pdf", b"newest");
+        let executor = OldDirExecutor::new(Arc::new(Throttle::unlimited()));
+        assert!(executor
+            .remove_superseded(dir.path(), &old, Some(&by))
+            .is_err());
+        assert_eq!(std::fs::read(&old).unwrap(), b"new");
+        assert_eq!(std::fs::read(&kept).unwrap(), b"earlier");
+    }
+
+    fn archive(dir: &TempDir) -> ArchiveExecutor {
+        ArchiveExecutor::new(&dir.path().join("archive"), Arc::new(Throttle::unlimited()))
+    }
+
+    fn archived(executor: &ArchiveExecutor) -> PathBuf {
+        let tar = executor.tar.lock().unwrap();
+        tar.as_ref().unwrap().path.clone()
+    }
+
+    fn index(path: &Path) -> TarIndex {
+        read_tar_index(&mut File::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn archive_resumes_after_the_last_complete_entry() {
+        use std::io::{Seek, SeekFrom};
+
+        let dir = TempDir::new("archive-resume");
+        let root = dir.path().join("root");
+        let first = dir.write("root/OK/OK_Tulsa_20190126_TM_geo.pdf", &[1; 1000]);
+        let torn = dir.write("root/OK/OK_Tulsa_20200126_TM_geo.pdf", &[2; 3000]);
+        let second = dir.write("root/OK/OK_Tulsa_20210126_TM_geo.pdf", &[3; 2000]);
+        let interrupted = archive(&dir);
+        interrupted.remove(&root, &first).unwrap();
+        let path = archived(&interrupted);
+        let complete = std::fs::metadata(&path).unwrap().len();
+        // Interrupted partway through writing the next entry
+        {
+            let mut tar = OpenOptions::new().append(true).open(&path).unwrap();
+            let name = Path::new("OK/OK_Tulsa_20200126_TM_geo.pdf");
+            append_tar_entry(&mut tar, name, &torn, false, &Throttle::unlimited()).unwrap();
+            tar.set_len(complete + 512 + 1000).unwrap();
+        }
+        drop(interrupted);
+        let index = self::index(&path);
+        assert_eq!(
+            (index.end, index.entries.len(), index.finished),
+            (complete, 1, false)
+        );
+
+        let resumed = archive(&dir);
+        resumed.remove(&root, &second).unwrap();
+        resumed.finish().unwrap();
+        assert_eq!(archived(&resumed), path);
+        let tars = std::fs::read_dir(dir.path().join("archive"))
+            .unwrap()
+            .count();
+        assert_eq!(tars, 1);
+        let index = self::index(&path);
+        let names: Vec<&str> = index.entries.keys().map(String::as_str).collect();
+        let expected = [
+            "OK/OK_Tulsa_20190126_TM_geo.pdf",
+            "OK/OK_Tulsa_20210126_TM_geo.pdf",
+        ];
+        assert_eq!(names, expected);
+        assert!(index.finished);
+        let entry = index.entries["OK/OK_Tulsa_20210126_TM_geo.pdf"];
+        assert_eq!((entry.offset, entry.size), (complete + 512, 2000));
+        let mut tar = File::open(&path).unwrap();
+        let mut contents = Vec::new();
+        tar.seek(SeekFrom::Start(entry.offset)).unwrap();
+        tar.take(entry.size).read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, [3; 2000]);
+        assert!(torn.exists() && !first.exists() && !second.exists());
+    }
+
+    #[test]
+    fn archive_deletes_a_file_it_already_holds() {
+        let dir = TempDir::new("archive-held");
+        let root = dir.path().join("root");
+        let path = dir.write("root/OK/OK_Tulsa_20190126_TM_geo.pdf", &[1; 1000]);
+        let interrupted = archive(&dir);
+        interrupted.remove(&root, &path).unwrap();
+        let tar = archived(&interrupted);
+        drop(interrupted);
+        // Archived, but the run stopped before the file was deleted
+        dir.write("root/OK/OK_Tulsa_20190126_TM_geo.pdf", &[1; 1000]);
+        let len = std::fs::metadata(&tar).unwrap().len();
+
+        let resumed = archive(&dir);
+        resumed.remove(&root, &path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(std::fs::metadata(&tar).unwrap().len(), len);
+        assert_eq!(index(&tar).entries.len(), 1);
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt::{Display, Formatter, Write as _};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use irox_log::log::{debug, info};
+
+use crate::copy::copy_verified;
+use crate::{
+    format_size, parse_size, ChartId, Error, ErrorContext, FoundFile, Phase, Provenance, Scan,
+    Throttle,
+};
+
+/// The largest file FAT32 can hold, 4GiB less a byte.
+pub const FAT32_MAX_FILE_SIZE: u64 = u32::MAX as u64;
+
+/// The name of the index written to the top of every export volume.
+pub const INDEX_FILE: &str = "charts-index.tsv";
+
+///
+/// The folder structure a chartplotter or chart app expects to find its charts in.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ExportLayout {
+    /// The archive's own tree, OpenCPN will pick up charts anywhere under a chart directory
+    #[default]
+    OpenCpn,
+    /// Every chart flat under `Garmin/CustomMaps`, where Garmin units look on a card
+    Garmin,
+    /// One folder per chart under `Charts`, like a Navionics card
+    Navionics,
+}
+
+impl ExportLayout {
+    ///
+    /// Where a kept file lands on a card, with every component made FAT32-safe
+    pub fn place(&self, file: &FoundFile) -> PathBuf {
+        let name = file.file_name();
+        let name = fat32_safe(&name.to_string_lossy());
+        match self {
+            ExportLayout::OpenCpn => file
+                .relative_path()
+                .components()
+                .filter_map(|c| match c {
+                    Component::Normal(c) => Some(fat32_safe(&c.to_string_lossy())),
+                    _ => None,
+                })
+                .collect(),
+            ExportLayout::Garmin => Path::new("Garmin").join("CustomMaps").join(name),
+            ExportLayout::Navionics => Path::new("Charts")
+                .join(fat32_safe(file.id().name()))
+                .join(name),
+        }
+    }
+}
+
+impl FromStr for ExportLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opencpn" => Ok(ExportLayout::OpenCpn),
+            "garmin" => Ok(ExportLayout::Garmin),
+            "navionics" => Ok(ExportLayout::Navionics),
+            _ => Err(format!(
+                "Unknown layout {s}, expected opencpn, garmin or navionics"
+            )),
+        }
+    }
+}
+
+impl Display for ExportLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportLayout::OpenCpn => write!(f, "opencpn"),
+            ExportLayout::Garmin => write!(f, "garmin"),
+            ExportLayout::Navionics => write!(f, "navionics"),
+        }
+    }
+}
+
+///
+/// Replaces the characters FAT32 doesn't allow in a file name, and the trailing dots and spaces
+/// it silently drops, with `_`.  Names are cut to 255 characters.
+pub fn fat32_safe(name: &str) -> String {
+    let mut safe: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(255)
+        .collect();
+    let kept = safe.trim_end_matches(['.', ' ']).len();
+    let dropped = safe.len() - kept;
+    safe.truncate(kept);
+    safe.extend(std::iter::repeat_n('_', dropped));
+    if safe.is_empty() {
+        safe.push('_');
+    }
+    safe
+}
+
+///
+/// A destination volume of an export, and how much of the export it may hold.
+#[derive(Debug, Clone)]
+pub struct Volume {
+    pub dir: PathBuf,
+    pub cap: Option<u64>,
+}
+
+impl Volume {
+    pub fn new(dir: impl Into<PathBuf>, cap: Option<u64>) -> Volume {
+        Volume {
+            dir: dir.into(),
+            cap,
+        }
+    }
+
+    ///
+    /// Parses `DIR` or `DIR,SIZE`, ie `/media/card-a,32G`
+    pub fn parse(value: &str) -> Option<Volume> {
+        match value.rsplit_once(',') {
+            Some((dir, cap)) => Some(Volume::new(dir.trim(), Some(parse_size(cap.trim())?))),
+            None => Some(Volume::new(value.trim(), None)),
+        }
+    }
+}
+
+///
+/// What was (or in a dry-run, would have been) written to one card or volume of an export.
+#[derive(Debug, Clone, Default)]
+pub struct CardReport {
+    pub dir: PathBuf,
+    pub cap: Option<u64>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+///
+/// A kept file and where it goes in an export.
+#[derive(Debug, Clone)]
+pub struct PlacedFile {
+    pub chart: ChartId,
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    /// The index of the card or volume it's on
+    pub card: usize,
+    pub size: u64,
+}
+
+///
+/// Where every kept file goes in an [`Export`], before anything is copied.
+#[derive(Debug, Default)]
+pub struct ExportPlan {
+    pub provenance: Provenance,
+    pub cards: Vec<CardReport>,
+    pub files: Vec<PlacedFile>,
+    pub errors: Vec<Error>,
+}
+
+impl ExportPlan {
+    ///
+    /// A tab separated listing of which chart went where, one line per file:
+    /// `<chart>\t<volume>\t<path on the volume>\t<size>`, after the profile's provenance
+    pub fn index(&self) -> String {
+        let mut out = String::from("# charts-clean export index\n");
+        for line in self.provenance.header_lines() {
+            let _ = writeln!(out, "{line}");
+        }
+        for file in &self.files {
+            let Some(card) = self.cards.get(file.card) else {
+                continue;
+            };
+            let path = file.dest.strip_prefix(&card.dir).unwrap_or(&file.dest);
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{}\t{}",
+                file.chart,
+                card.dir.display(),
+                path.display(),
+                file.size
+            );
+        }
+        out
+    }
+}
+
+///
+/// The outcome of an [`Export`].
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    pub cards: Vec<CardReport>,
+    pub copied: usize,
+    pub copied_bytes: u64,
+    /// Files already on the card with the right size
+    pub unchanged: usize,
+    pub errors: Vec<Error>,
+}
+
+///
+/// Lays the kept files of a scan out in a chartplotter's folder structure.  The files can be
+/// split across several destination volumes, each with its own size cap, or with a card size
+/// across `<dir>/card-01`, `<dir>/card-02`... each holding no more than that, so each can be
+/// copied to its own card.  Every volume gets an [index](ExportPlan::index) of where each chart
+/// went.
+pub struct Export {
+    layout: ExportLayout,
+    dir: PathBuf,
+    volumes: Vec<Volume>,
+    card_size: Option<u64>,
+    verify_copies: bool,
+    provenance: Provenance,
+    throttle: Arc<Throttle>,
+}
+
+impl Export {
+    pub fn new(layout: ExportLayout, dir: impl Into<PathBuf>) -> Export {
+        Export {
+            layout,
+            dir: dir.into(),
+            volumes: Vec::new(),
+            card_size: None,
+            verify_copies: false,
+            provenance: Provenance::default(),
+            throttle: Arc::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_card_size(mut self, card_size: Option<u64>) -> Export {
+        self.card_size = card_size;
+        self
+    }
+
+    ///
+    /// Exports to these volumes, filled in order, instead of the export directory
+    #[must_use]
+    pub fn with_volumes(mut self, volumes: Vec<Volume>) -> Export {
+        self.volumes = volumes;
+        self
+    }
+
+    #[must_use]
+    pub fn with_verify_copies(mut self, verify_copies: bool) -> Export {
+        self.verify_copies = verify_copies;
+        self
+    }
+
+    ///
+    /// Records where the charts came from in every volume's index
+    #[must_use]
+    pub fn with_provenance(mut self, provenance: Provenance) -> Export {
+        self.provenance = provenance;
+        self
+    }
+
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Export {
+        self.throttle = throttle;
+        self
+    }
+
+    ///
+    /// Assigns each kept file a destination, in path order, on the first card or volume with
+    /// room for it.  Files too big for FAT32, or for any of the volumes, are reported as errors
+    /// and left out.
+    pub fn plan(&self, scan: &Scan) -> ExportPlan {
+        let mut plan = ExportPlan {
+            provenance: self.provenance.clone(),
+            ..Default::default()
+        };
+        if !self.volumes.is_empty() {
+            plan.cards = self
+                .volumes
+                .iter()
+                .map(|v| CardReport {
+                    dir: v.dir.clone(),
+                    cap: v.cap,
+                    ..Default::default()
+                })
+                .collect();
+        } else if self.card_size.is_none() {
+            plan.cards.push(CardReport {
+                dir: self.dir.clone(),
+                ..Default::default()
+            });
+        }
+        let mut files: Vec<&FoundFile> = scan.to_keep.iter().collect();
+        files.sort_by(|a, b| a.cmp_path(b));
+        for file in files {
+            let src = &file.full_path();
+            let size = file.size();
+            if size > FAT32_MAX_FILE_SIZE {
+                let msg = format!("{} is too big for FAT32", format_size(size));
+                plan.errors.push(Error::verify(msg).with_path(src));
+                continue;
+            }
+            let fits = |c: &CardReport| c.cap.is_none_or(|cap| c.bytes + size <= cap);
+            let card_idx = match plan.cards.iter().position(fits) {
+                Some(card) => card,
+                None => match self.card_size {
+                    Some(card_size) if self.volumes.is_empty() && size <= card_size => {
+                        let dir = self.dir.join(format!("card-{:02}", plan.cards.len() + 1));
+                        plan.cards.push(CardReport {
+                            dir,
+                            cap: Some(card_size),
+                            ..Default::default()
+                        });
+                        plan.cards.len() - 1
+                    }
+                    _ => {
+                        let msg = format!("no room for {} on any volume", format_size(size));
+                        plan.errors.push(Error::verify(msg).with_path(src));
+                        continue;
+                    }
+                },
+            };
+            let Some(card) = plan.cards.get_mut(card_idx) else {
+                continue;
+            };
+            plan.files.push(PlacedFile {
+                chart: file.id().clone(),
+                src: src.to_path_buf(),
+                dest: card.dir.join(self.layout.place(file)),
+                card: card_idx,
+                size,
+            });
+            card.files += 1;
+            card.bytes += size;
+        }
+        plan
+    }
+
+    ///
+    /// Copies the kept files of a scan into the export, and writes the index to every
+    /// volume.  Files already there with the right size are left alone, so re-running an export
+    /// only copies what changed.
+    pub fn run(&self, scan: &Scan, dry_run: bool) -> ExportReport {
+        let plan = self.plan(scan);
+        let index = plan.index();
+        let mut report = ExportReport::default();
+        for file in &plan.files {
+            let (src, dest) = (&file.src, &file.dest);
+            if std::fs::metadata(dest).is_ok_and(|m| m.len() == file.size) {
+                debug!("{} is up to date", dest.display());
+                report.unchanged += 1;
+                continue;
+            }
+            if dry_run {
+                info!("Would export {} to {}", src.display(), dest.display());
+            } else {
+                info!("Exporting {} to {}", src.display(), dest.display());
+                if let Err(e) = copy_verified(src, dest, self.verify_copies, &self.throttle) {
+                    report.errors.push(e);
+                    continue;
+                }
+            }
+            report.copied += 1;
+            report.copied_bytes += file.size;
+        }
+        if !dry_run {
+            for card in plan.cards.iter().filter(|c| c.files > 0) {
+                let path = card.dir.join(INDEX_FILE);
+                if let Err(e) = std::fs::write(&path, &index).context(Phase::Copy, &path) {
+                    report.errors.push(e);
+                }
+            }
+        }
+        report.cards = plan.cards;
+        report.errors.extend(plan.errors);
+        report
+    }
+}
@@ -0,0 +1,255 @@
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use irox_time::format::{FormatError, FormatErrorType};
+
+use crate::chart::{parse_edition, parse_scale};
+use crate::timestamp::parse_timestamp;
+use crate::{ChartId, ChartVersion, Error, ErrorContext, FoundFile, Interner, Phase, UtcOffset};
+
+///
+/// What a [`FixedField`] of a name holds.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FieldKind {
+    /// Part of the chart's name, the parts joined with `_` in order, ie `OK` and `TULSA`
+    Name,
+    /// The publication date, `YYYYMMDD`
+    Date,
+    /// The publication time, `HHMM[SS][Z|±HHMM]`
+    Time,
+    /// The product/series code, ie `TM`
+    Source,
+    /// The scale, ie `24k` or `24000`
+    Scale,
+    /// The edition, ie `ed3` or `3`
+    Edition,
+}
+
+impl FromStr for FieldKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().to_ascii_lowercase().as_str() {
+            "name" => FieldKind::Name,
+            "date" => FieldKind::Date,
+            "time" => FieldKind::Time,
+            "source" => FieldKind::Source,
+            "scale" => FieldKind::Scale,
+            "edition" => FieldKind::Edition,
+            _ => {
+                return Err(format!(
+                    "Unknown name field {s}, expected name, date, time, source, scale or edition"
+                ))
+            }
+        })
+    }
+}
+
+impl Display for FieldKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            FieldKind::Name => "name",
+            FieldKind::Date => "date",
+            FieldKind::Time => "time",
+            FieldKind::Source => "source",
+            FieldKind::Scale => "scale",
+            FieldKind::Edition => "edition",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+///
+/// A field at a fixed position in a name, written `KIND:OFFSET:LENGTH`.  The offset counts
+/// characters from the start of the name's stem, the part before its first `.`, or back from its
+/// end if it's negative.  A length of `*` runs up to the next field, or the end of the stem.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FixedField {
+    pub kind: FieldKind,
+    pub offset: isize,
+    /// None for `*`
+    pub len: Option<usize>,
+}
+
+impl FromStr for FixedField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("Invalid name field {s}, expected KIND:OFFSET:LENGTH");
+        let mut parts = s.split(':');
+        let (Some(kind), Some(offset), Some(len), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let len = match len.trim() {
+            "*" => None,
+            len => Some(
+                len.parse()
+                    .ok()
+                    .filter(|len| *len > 0)
+                    .ok_or_else(invalid)?,
+            ),
+        };
+        Ok(FixedField {
+            kind: kind.parse()?,
+            offset: offset.trim().parse().map_err(|_| invalid())?,
+            len,
+        })
+    }
+}
+
+impl Display for FixedField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:", self.kind, self.offset)?;
+        match self.len {
+            Some(len) => write!(f, "{len}"),
+            None => write!(f, "*"),
+        }
+    }
+}
+
+///
+/// How to read the chart name, date and source out of file names that have no delimiters, ie
+/// `OKTULSA20230101TM.tif`, by position rather than by splitting on `_`: for that one,
+/// `name:0:2, name:2:*, date:-10:8, source:-2:2` files it as `OK_TULSA[TM]`.  A profile with
+/// `fixed_fields` parses every name this way, instead of as [`FoundFile::parse`] does.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FixedFields {
+    fields: Vec<FixedField>,
+}
+
+impl FixedFields {
+    pub fn fields(&self) -> &[FixedField] {
+        &self.fields
+    }
+
+    ///
+    /// Parses the file at `full_path` by the fields
+    pub fn parse(&self, full_path: &Path, source_tz: UtcOffset) -> Result<FoundFile, Error> {
+        let (root, dir) = (
+            Arc::from(Path::new("")),
+            full_path.parent().unwrap_or(Path::new("")),
+        );
+        let file_name = full_path.file_name().unwrap_or_default().to_os_string();
+        self.parse_in(
+            root,
+            Arc::from(dir),
+            file_name,
+            source_tz,
+            &Interner::default(),
+        )
+    }
+
+    ///
+    /// Parses a file in the directory `dir` under `root` by the fields, as
+    /// [`FoundFile::parse_in`] does by its delimiters
+    pub fn parse_in(
+        &self,
+        root: Arc<Path>,
+        dir: Arc<Path>,
+        file_name: OsString,
+        source_tz: UtcOffset,
+        interner: &Interner,
+    ) -> Result<FoundFile, Error> {
+        let full_path = || root.join(&dir).join(&file_name);
+        let err = |msg: &str| {
+            let e = FormatError::new(FormatErrorType::Other, msg.to_string());
+            Err(e).context(Phase::Parse, &full_path())
+        };
+        let name_str = file_name.to_string_lossy().to_string();
+        let stem: Vec<char> = name_str
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .collect();
+        let starts: Vec<Option<usize>> = self.fields.iter().map(|f| start(f, stem.len())).collect();
+        let (mut name, mut date, mut time, mut source) = (Vec::new(), None, None, None);
+        let (mut scale, mut edition) = (None, None);
+        for (field, start) in self.fields.iter().zip(&starts) {
+            let Some(start) = *start else {
+                return err("Shorter than its fixed fields");
+            };
+            let end = match field.len {
+                Some(len) => Some(start + len),
+                None => starts
+                    .iter()
+                    .flatten()
+                    .filter(|s| **s > start)
+                    .min()
+                    .copied(),
+            }
+            .unwrap_or(stem.len());
+            let Some(chars) = stem.get(start..end).filter(|chars| !chars.is_empty()) else {
+                return err("Shorter than its fixed fields");
+            };
+            let value: String = chars.iter().collect();
+            match field.kind {
+                FieldKind::Name => name.push(value),
+                FieldKind::Date => date = Some(value),
+                FieldKind::Time => time = Some(value),
+                FieldKind::Source => source = Some(value),
+                FieldKind::Scale => {
+                    let Some(parsed) = parse_scale(&value).or_else(|| value.parse().ok()) else {
+                        return err("Unrecognized scale");
+                    };
+                    scale = Some(parsed);
+                }
+                FieldKind::Edition => {
+                    let Some(parsed) = parse_edition(&value).or_else(|| value.parse().ok()) else {
+                        return err("Unrecognized edition");
+                    };
+                    edition = Some(parsed);
+                }
+            }
+        }
+        let Some(date) = date else {
+            return err("Missing date");
+        };
+        let timestamp = parse_timestamp(&date, time.as_deref(), source_tz)
+            .context(Phase::Parse, &full_path())?;
+        let source = source.map(|source| interner.intern(&source));
+        let id = ChartId::new(source, interner.intern(&name.join("_")), scale);
+        let version = ChartVersion::new(timestamp, edition);
+        Ok(FoundFile::in_dir(id, version, root, dir, file_name))
+    }
+}
+
+///
+/// Where the field starts in a stem of `len` characters, if it's long enough to have it
+fn start(field: &FixedField, len: usize) -> Option<usize> {
+    match usize::try_from(field.offset) {
+        Ok(offset) => (offset < len).then_some(offset),
+        Err(_) => len.checked_sub(field.offset.unsigned_abs()),
+    }
+}
+
+impl FromStr for FixedFields {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<FixedField>, String>>()?;
+        let count = |kind| fields.iter().filter(|f| f.kind == kind).count();
+        if count(FieldKind::Name) == 0 || count(FieldKind::Date) != 1 {
+            return Err(format!(
+                "Invalid name fields {s}, expected one date and a name"
+            ));
+        }
+        Ok(FixedFields { fields })
+    }
+}
+
+impl Display for FixedFields {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<String> = self.fields.iter().map(ToString::to_string).collect();
+        write!(f, "{}", fields.join(", "))
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use irox_time::epoch::UnixTimestamp;
+
+use crate::{format_duration, ChartId, ChartVersion, FoundFile};
+
+///
+/// What's missing between two consecutive versions of a chart.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GapKind {
+    /// The editions between these two, exclusive, weren't found
+    Editions(u32, u32),
+    /// The versions are further apart than the profile's `edition_interval`
+    Overdue(Duration),
+}
+
+///
+/// A hole in the run of a chart's versions, ie a jump from edition 3 to edition 6, which may be
+/// editions that failed to download.  Worth a look before the versions either side of it are
+/// cleaned away.
+#[derive(Debug, Clone)]
+pub struct EditionGap {
+    pub id: ChartId,
+    pub before: ChartVersion,
+    pub after: ChartVersion,
+    pub kind: GapKind,
+}
+
+impl Display for EditionGap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (id, before, after) = (&self.id, self.before, self.after);
+        match self.kind {
+            GapKind::Editions(from, to) if to == from + 2 => {
+                write!(
+                    f,
+                    "{id} is missing edition {} between {before} and {after}",
+                    from + 1
+                )
+            }
+            GapKind::Editions(from, to) => write!(
+                f,
+                "{id} is missing editions {}-{} between {before} and {after}",
+                from + 1,
+                to - 1
+            ),
+            GapKind::Overdue(expected) => {
+                // To the day, or the minute for gaps shorter than that
+                let secs = seconds_between(&before, &after);
+                let unit = if secs >= 86400 { 86400 } else { 60 };
+                write!(
+                    f,
+                    "{id} has no version between {before} and {after}, {} apart where one is \
+                     expected every {}",
+                    format_duration(Duration::from_secs(secs / unit * unit)),
+                    format_duration(expected)
+                )
+            }
+        }
+    }
+}
+
+///
+/// Finds the gaps between consecutive versions of each chart among the files, ie the kept and
+/// superseded files of a scan.  Skipped edition numbers are always gaps.  With an `interval`,
+/// versions more than half again that far apart are a gap too, ie more than ten and a half days
+/// for weekly editions.
+pub fn find_gaps<'a>(
+    files: impl IntoIterator<Item = &'a FoundFile>,
+    interval: Option<Duration>,
+) -> Vec<EditionGap> {
+    let mut charts: BTreeMap<&ChartId, Vec<ChartVersion>> = BTreeMap::new();
+    for file in files {
+        charts.entry(file.id()).or_default().push(*file.version());
+    }
+    let mut gaps = Vec::new();
+    for (id, mut versions) in charts {
+        versions.sort();
+        versions.dedup();
+        for pair in versions.windows(2) {
+            let [before, after] = [pair[0], pair[1]];
+            let kind = match (before.edition(), after.edition(), interval) {
+                (Some(from), Some(to), _) if to > from + 1 => GapKind::Editions(from, to),
+                (_, _, Some(interval))
+                    if seconds_between(&before, &after) as f64 > interval.as_secs_f64() * 1.5 =>
+                {
+                    GapKind::Overdue(interval)
+                }
+                _ => continue,
+            };
+            gaps.push(EditionGap {
+                id: id.clone(),
+                before,
+                after,
+                kind,
+            });
+        }
+    }
+    gaps
+}
+
+fn seconds_between(before: &ChartVersion, after: &ChartVersion) -> u64 {
+    let seconds = |v: &ChartVersion| {
+        UnixTimestamp::from(v.timestamp())
+            .get_offset()
+            .as_seconds_f64()
+    };
+    (seconds(after) - seconds(before)).max(0.).round() as u64
+}
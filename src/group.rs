@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::Component;
+use std::str::FromStr;
+
+use crate::{CleanPlan, FoundFile};
+
+///
+/// A level of a grouped report, derived from a file's identity or its path.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GroupKey {
+    /// The publisher's product code, ie `TM`
+    Source,
+    /// The leading token of the chart name, the state for USGS topos (`OK` of `OK_Tulsa`)
+    State,
+    Scale,
+    /// The full identity of the chart
+    Chart,
+    /// The Nth directory under the root, counting from 1
+    Dir(usize),
+}
+
+impl GroupKey {
+    ///
+    /// The group the file falls in at this level
+    pub fn group(&self, file: &FoundFile) -> String {
+        let id = file.id();
+        match self {
+            GroupKey::Source => id.source().unwrap_or("-").to_string(),
+            GroupKey::State => id.name().split('_').next().unwrap_or_default().to_string(),
+            GroupKey::Scale => id
+                .scale()
+                .map_or_else(|| "-".to_string(), |s| format!("1:{s}")),
+            GroupKey::Chart => id.to_string(),
+            GroupKey::Dir(n) => file
+                .dir()
+                .components()
+                .filter_map(|c| match c {
+                    Component::Normal(c) => Some(c),
+                    _ => None,
+                })
+                .nth(n.saturating_sub(1))
+                .map_or_else(|| ".".to_string(), |c| c.to_string_lossy().to_string()),
+        }
+    }
+
+    ///
+    /// Parses a comma separated list of keys, ie `source,state,chart`
+    pub fn parse_list(value: &str) -> Result<Vec<GroupKey>, String> {
+        value.split(',').map(|k| k.trim().parse()).collect()
+    }
+}
+
+impl FromStr for GroupKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "source" => Ok(GroupKey::Source),
+            "state" => Ok(GroupKey::State),
+            "scale" => Ok(GroupKey::Scale),
+            "chart" => Ok(GroupKey::Chart),
+            "dir" => Ok(GroupKey::Dir(1)),
+            _ => match s.strip_prefix("dir").and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => Ok(GroupKey::Dir(n)),
+                _ => Err(format!(
+                    "Unknown group {s}, expected source, state, scale, chart, dir or dirN"
+                )),
+            },
+        }
+    }
+}
+
+impl Display for GroupKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupKey::Source => write!(f, "source"),
+            GroupKey::State => write!(f, "state"),
+            GroupKey::Scale => write!(f, "scale"),
+            GroupKey::Chart => write!(f, "chart"),
+            GroupKey::Dir(1) => write!(f, "dir"),
+            GroupKey::Dir(n) => write!(f, "dir{n}"),
+        }
+    }
+}
+
+///
+/// The rollup of one group of a plan, and of the groups under it.
+#[derive(Debug, Default)]
+pub struct Group {
+    /// Distinct charts, one per kept file
+    pub charts: usize,
+    pub kept_bytes: u64,
+    pub removed: usize,
+    pub removed_bytes: u64,
+    pub children: BTreeMap<String, Group>,
+}
+
+///
+/// A plan rolled up by a hierarchy of [`GroupKey`]s, ie source → state → chart.
+#[derive(Debug, Default)]
+pub struct GroupReport {
+    pub keys: Vec<GroupKey>,
+    pub total: Group,
+}
+
+impl GroupReport {
+    pub fn new(keys: &[GroupKey], plan: &CleanPlan) -> GroupReport {
+        let mut report = GroupReport {
+            keys: keys.to_vec(),
+            total: Group::default(),
+        };
+        for file in &plan.keep {
+            report.add(file, |g| {
+                g.charts += 1;
+                g.kept_bytes += file.size();
+            });
+        }
+        let removed = plan
+            .remove
+            .iter()
+            .map(|r| r.file)
+            .chain(plan.retired.iter().copied());
+        for file in removed {
+            report.add(file, |g| {
+                g.removed += 1;
+                g.removed_bytes += file.size();
+            });
+        }
+        report
+    }
+
+    fn add(&mut self, file: &FoundFile, mut count: impl FnMut(&mut Group)) {
+        let mut group = &mut self.total;
+        count(group);
+        for key in &self.keys {
+            group = group.children.entry(key.group(file)).or_default();
+            count(group);
+        }
+    }
+
+    ///
+    /// Every group, depth first, with its depth (1 for the top level) and name
+    pub fn walk(&self) -> Vec<(usize, &str, &Group)> {
+        fn walk<'a>(group: &'a Group, depth: usize, out: &mut Vec<(usize, &'a str, &'a Group)>) {
+            for (name, child) in &group.children {
+                out.push((depth, name, child));
+                walk(child, depth + 1, out);
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.total, 1, &mut out);
+        out
+    }
+}
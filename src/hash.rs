@@ -0,0 +1,62 @@
+use std::io::Read;
+use std::path::Path;
+
+use irox_tools::hex::to_hex_str_lower;
+use irox_tools::sha1::SHA1;
+
+///
+/// SHA1 content hash of a file.  Used to detect corruption and identical copies, not for
+/// anything security related.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Digest([u8; 20]);
+
+impl Digest {
+    ///
+    /// Parses a lowercase or uppercase hex string back into a digest
+    pub fn from_hex(hex: &str) -> Option<Digest> {
+        let bytes = irox_tools::hex::from_hex_str(hex).ok()?;
+        let bytes: [u8; 20] = bytes.as_ref().try_into().ok()?;
+        Some(Digest(bytes))
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex_str_lower(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+}
+
+impl From<[u8; 20]> for Digest {
+    fn from(value: [u8; 20]) -> Self {
+        Digest(value)
+    }
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+///
+/// Reads the entire file, returning the hash of its contents
+pub fn hash_file(path: &Path) -> std::io::Result<Digest> {
+    hash_reader(&mut std::fs::File::open(path)?)
+}
+
+///
+/// Reads everything left in the reader, returning the hash of it
+pub fn hash_reader(file: &mut impl Read) -> std::io::Result<Digest> {
+    let mut hasher = SHA1::default();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(buf.get(..read).unwrap_or_default());
+    }
+    Ok(Digest(hasher.finish()))
+}
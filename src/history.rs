@@ -0,0 +1,167 @@
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{format_size, CleanPlan, Error, ErrorContext, ErrorKind, Phase};
+
+const HEADER: &str = "# charts-clean history v1";
+///
+/// How many of the most recent runs make up the norm
+const WINDOW: usize = 20;
+///
+/// Runs needed before there's a norm to compare against
+const MIN_RUNS: usize = 3;
+///
+/// Plans removing fewer files than this are never anomalous, however quiet the archive has been
+const MIN_ANOMALY: usize = 25;
+
+///
+/// How much one run of a profile planned to remove.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RunVolume {
+    /// Seconds since the epoch
+    pub time: u64,
+    /// Removals planned, including any the deletion cap deferred and withdrawn charts retired
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl RunVolume {
+    pub fn from_plan(plan: &CleanPlan, time: u64) -> RunVolume {
+        let deferred: u64 = plan.deferred.iter().map(|f| f.size()).sum();
+        RunVolume {
+            time,
+            files: plan.remove.len() + plan.deferred.len() + plan.retired.len(),
+            bytes: plan.removed_bytes() + deferred + plan.retired_bytes(),
+        }
+    }
+}
+
+///
+/// A plan removing far more than the profile's runs usually do, which tends to mean the
+/// publisher changed its naming rather than that there's a lot to clean.
+#[derive(Debug, Copy, Clone)]
+pub struct Anomaly {
+    pub planned: RunVolume,
+    /// The median number of files removed by recent runs
+    pub usual: usize,
+    pub factor: f64,
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "planned to remove {} files ({}), more than {} times the usual {}",
+            self.planned.files,
+            format_size(self.planned.bytes),
+            self.factor,
+            self.usual
+        )
+    }
+}
+
+///
+/// The removal volume of a profile's past runs, one line per run:
+/// `<time>\t<files>\t<bytes>`
+#[derive(Debug)]
+pub struct RemovalHistory {
+    path: PathBuf,
+    runs: Vec<RunVolume>,
+}
+
+impl RemovalHistory {
+    ///
+    /// Loads the history at the path, or an empty one if there isn't one yet
+    pub fn load(path: impl Into<PathBuf>) -> Result<RemovalHistory, Error> {
+        let path = path.into();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).context(Phase::Setup, &path),
+        };
+        let mut runs = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let err = || {
+                let msg = format!("line {}: Unrecognized entry", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Setup).with_path(&path)
+            };
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [time, files, bytes] = fields.as_slice() else {
+                return Err(err());
+            };
+            runs.push(RunVolume {
+                time: time.parse().map_err(|_| err())?,
+                files: files.parse().map_err(|_| err())?,
+                bytes: bytes.parse().map_err(|_| err())?,
+            });
+        }
+        Ok(RemovalHistory { path, runs })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn runs(&self) -> &[RunVolume] {
+        &self.runs
+    }
+
+    ///
+    /// The median number of files removed by the most recent runs, if there have been enough
+    pub fn usual(&self) -> Option<usize> {
+        if self.runs.len() < MIN_RUNS {
+            return None;
+        }
+        let mut recent: Vec<usize> = self
+            .runs
+            .iter()
+            .rev()
+            .take(WINDOW)
+            .map(|r| r.files)
+            .collect();
+        recent.sort_unstable();
+        recent.get(recent.len() / 2).copied()
+    }
+
+    ///
+    /// The anomaly, if the planned volume is more than `factor` times the usual
+    pub fn check(&self, planned: RunVolume, factor: f64) -> Option<Anomaly> {
+        let usual = self.usual()?;
+        let limit = usual.max(1) as f64 * factor;
+        (planned.files >= MIN_ANOMALY && planned.files as f64 > limit).then_some(Anomaly {
+            planned,
+            usual,
+            factor,
+        })
+    }
+
+    ///
+    /// Appends a run, flushed before returning
+    pub fn record(&mut self, volume: RunVolume) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut line = String::new();
+        if !self.path.exists() {
+            line.push_str(HEADER);
+            line.push('\n');
+        }
+        line.push_str(&format!(
+            "{}\t{}\t{}\n",
+            volume.time, volume.files, volume.bytes
+        ));
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .context(Phase::Setup, &self.path)?;
+        self.runs.push(volume);
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, PoisonError};
+
+///
+/// Hands out one shared copy of each distinct string, so that the thousands of files of a large
+/// archive sharing a chart name or product code all point at the same allocation.  Shared between
+/// threads; interning takes a lock.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: Mutex<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(interned) = strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        strings.insert(interned.clone());
+        interned
+    }
+
+    ///
+    /// How many distinct strings have been interned
+    pub fn len(&self) -> usize {
+        self.strings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
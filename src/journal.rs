@@ -0,0 +1,80 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Error, ErrorContext, Phase, RunId};
+
+///
+/// An append-only record of every file a run removed or moved, shared by every profile of the
+/// run.  Each line is tagged with the run that made the change, and the action is the name of the
+/// [`crate::Executor`] that made it:
+/// `<time>\t<run>\t<profile>\t<action>\t<path>\t<destination>`
+pub struct Journal {
+    path: PathBuf,
+    run: RunId,
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    ///
+    /// A journal appending to the file at `path`, which is created on the first entry
+    pub fn new(path: impl Into<PathBuf>, run: RunId) -> Journal {
+        Journal {
+            path: path.into(),
+            run,
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn run(&self) -> RunId {
+        self.run
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    ///
+    /// Appends an entry, flushed before returning so it survives the run being killed
+    pub fn record(
+        &self,
+        profile: &str,
+        action: &str,
+        path: &Path,
+        dest: Option<&Path>,
+    ) -> Result<(), Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let dest = dest.map(|d| d.display().to_string()).unwrap_or_default();
+        let line = format!(
+            "{now}\t{}\t{profile}\t{action}\t{}\t{dest}\n",
+            self.run,
+            path.display()
+        );
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+            }
+            let opened = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .context(Phase::Setup, &self.path)?;
+            *file = Some(opened);
+        }
+        let Some(file) = file.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(line.as_bytes())
+            .and_then(|()| file.flush())
+            .context(Phase::Setup, &self.path)
+    }
+}
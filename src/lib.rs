@@ -0,0 +1,119 @@
+//!
+//! Finds superseded editions of chart products (USGS topos, NOAA ENCs, FAA sectionals...) in an
+//! archive, keeping only the newest version of each chart.
+
+pub use alias::*;
+pub use analysis::*;
+pub use attrs::*;
+pub use audit::*;
+pub use baseline::*;
+pub use cancel::*;
+pub use catalog::*;
+#[cfg(feature = "chaos")]
+pub use chaos::*;
+pub use chart::*;
+pub use checkpoint::*;
+pub use checksum::*;
+pub use companion::*;
+pub use config::*;
+pub use copy::*;
+pub use dircache::*;
+pub use download::*;
+pub use error::*;
+pub use executor::*;
+pub use export::*;
+pub use fixed::*;
+pub use gap::*;
+pub use group::*;
+pub use hash::*;
+pub use history::*;
+pub use intern::*;
+pub use journal::*;
+pub use lock::*;
+pub use manifest::*;
+pub use mirror::*;
+pub use mount::*;
+pub use plan::*;
+pub use plandiff::*;
+pub use preset::*;
+pub use priority::*;
+pub use progress::*;
+pub use protect::*;
+pub use provenance::*;
+pub use quarantine::*;
+pub use reclaim::*;
+pub use record::*;
+pub use report::*;
+pub use run::*;
+pub use sanity::*;
+pub use scan::*;
+pub use signing::*;
+pub use skiplist::*;
+pub use skipped::*;
+pub use store::*;
+pub use tenant::*;
+pub use throttle::*;
+pub use tier::*;
+pub use timestamp::*;
+pub use units::*;
+pub use webhook::*;
+pub use withdrawn::*;
+
+mod alias;
+mod analysis;
+mod attrs;
+mod audit;
+mod baseline;
+mod cancel;
+mod catalog;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod chart;
+mod checkpoint;
+mod checksum;
+mod companion;
+mod config;
+mod copy;
+mod dircache;
+mod download;
+mod error;
+mod executor;
+mod export;
+mod fixed;
+mod gap;
+mod group;
+mod hash;
+mod history;
+mod intern;
+mod journal;
+mod lock;
+mod manifest;
+mod mirror;
+mod mount;
+mod plan;
+mod plandiff;
+mod preset;
+mod priority;
+mod progress;
+mod protect;
+mod provenance;
+mod quarantine;
+mod reclaim;
+mod record;
+mod report;
+mod run;
+mod sanity;
+mod scan;
+mod signing;
+mod skiplist;
+mod skipped;
+mod store;
+mod tenant;
+#[cfg(test)]
+mod testing;
+mod throttle;
+mod tier;
+mod timestamp;
+mod units;
+mod webhook;
+mod withdrawn;
@@ -0,0 +1,289 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use irox_log::log::{info, warn};
+
+use crate::{Error, ErrorContext, ErrorKind, Phase, RunId};
+
+const HEADER: &str = "# charts-clean lock v1";
+
+///
+/// Who holds a [`RunLock`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LockHolder {
+    pub host: String,
+    pub pid: u32,
+    pub run: String,
+    /// When the lock was taken, seconds since the epoch
+    pub acquired: u64,
+}
+
+impl LockHolder {
+    fn parse(text: &str) -> Option<LockHolder> {
+        let mut holder = LockHolder {
+            host: String::new(),
+            pid: 0,
+            run: String::new(),
+            acquired: 0,
+        };
+        for line in text.lines() {
+            let Some((key, value)) = line.strip_prefix('#').and_then(|l| l.split_once('\t')) else {
+                continue;
+            };
+            match key {
+                "host" => holder.host = value.to_string(),
+                "pid" => holder.pid = value.parse().ok()?,
+                "run" => holder.run = value.to_string(),
+                "acquired" => holder.acquired = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        (!holder.run.is_empty()).then_some(holder)
+    }
+
+    fn contents(&self) -> String {
+        format!(
+            "{HEADER}\n#host\t{}\n#pid\t{}\n#run\t{}\n#acquired\t{}\n",
+            self.host, self.pid, self.run, self.acquired
+        )
+    }
+
+    ///
+    /// True if the holder is a process on this host that's no longer running.  Holders on other
+    /// hosts can't be checked, and only go stale with age.
+    fn is_dead(&self) -> bool {
+        self.host == hostname() && !Path::new("/proc").join(self.pid.to_string()).exists()
+    }
+}
+
+impl std::fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "run {} (pid {} on {})", self.run, self.pid, self.host)
+    }
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+///
+/// An exclusive lock on an archive shared between hosts, ie over NFS, so that two hosts never
+/// plan and execute against the same files at once.  The lock is a file on the share, created
+/// with `O_EXCL` and removed when the lock is dropped.
+///
+/// A lock left behind by a crashed run is broken if its holder was on this host and is no longer
+/// running, or if it's older than `stale_after`.  One that can't be read, ie left empty by a
+/// crash, is broken once it was last written longer ago than `stale_after`.  Set that comfortably
+/// above the longest a run can take, which the scan and removal timeouts bound.
+#[derive(Debug)]
+pub struct RunLock {
+    path: PathBuf,
+    holder: LockHolder,
+}
+
+impl RunLock {
+    pub fn acquire(
+        path: &Path,
+        run: RunId,
+        stale_after: Option<Duration>,
+    ) -> Result<RunLock, Error> {
+        let holder = LockHolder {
+            host: hostname(),
+            pid: std::process::id(),
+            run: run.to_string(),
+            acquired: now(),
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        // A few attempts, in case another host breaks the same stale lock at the same time
+        for _ in 0..3 {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    file.write_all(holder.contents().as_bytes())
+                        .and_then(|()| file.sync_all())
+                        .context(Phase::Setup, path)?;
+                    info!("Locked {}", path.display());
+                    return Ok(RunLock {
+                        path: path.to_path_buf(),
+                        holder,
+                    });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e).context(Phase::Setup, path),
+            }
+            let current = RunLock::holder(path)?;
+            let age = match &current {
+                Some(current) => Duration::from_secs(now().saturating_sub(current.acquired)),
+                None => match modified_age(path)? {
+                    Some(age) => age,
+                    None => continue,
+                },
+            };
+            let dead = current.as_ref().is_some_and(LockHolder::is_dead);
+            if !dead && stale_after.is_none_or(|stale| age < stale) {
+                let msg = match &current {
+                    Some(current) => format!("{} is held by {current}", path.display()),
+                    None => format!("{} is held, but can't be read", path.display()),
+                };
+                return Err(Error::new(ErrorKind::Locked(msg), Phase::Setup));
+            }
+            match &current {
+                Some(current) => warn!("Breaking stale lock {} held by {current}", path.display()),
+                None => warn!(
+                    "Breaking stale lock {}, which can't be read",
+                    path.display()
+                ),
+            }
+            break_lock(path, current.as_ref(), run)?;
+        }
+        let msg = format!("{} kept changing hands, giving up", path.display());
+        Err(Error::new(ErrorKind::Locked(msg), Phase::Setup))
+    }
+
+    ///
+    /// Who holds the lock at the path, if anyone
+    pub fn holder(path: &Path) -> Result<Option<LockHolder>, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(LockHolder::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context(Phase::Setup, path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+///
+/// How long ago the file at the path was last written, if it's still there
+fn modified_age(path: &Path) -> Result<Option<Duration>, Error> {
+    match std::fs::metadata(path).and_then(|meta| meta.modified()) {
+        Ok(modified) => Ok(Some(
+            SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(Phase::Setup, path),
+    }
+}
+
+///
+/// Moves the stale lock aside, putting it back if it turns out another host replaced it with a
+/// live one in the meantime.  A stale lock that couldn't be read has no holder.
+fn break_lock(path: &Path, stale: Option<&LockHolder>, run: RunId) -> Result<(), Error> {
+    let aside = path.with_extension(format!("stale-{run}"));
+    match std::fs::rename(path, &aside) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context(Phase::Setup, path),
+    }
+    if RunLock::holder(&aside)?.as_ref() != stale {
+        let _ = std::fs::hard_link(&aside, path);
+    }
+    std::fs::remove_file(&aside).context(Phase::Setup, &aside)
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // Only remove the lock if it's still ours, in case it was broken as stale
+        if RunLock::holder(&self.path).ok().flatten().as_ref() == Some(&self.holder) {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                warn!("Unable to unlock {}: {e}", self.path.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const HOUR: Option<Duration> = Some(Duration::from_secs(3600));
+
+    fn held_by(dir: &TempDir, holder: &LockHolder) -> PathBuf {
+        dir.write("archive.lock", holder.contents().as_bytes())
+    }
+
+    fn holder(host: &str, pid: u32, acquired: u64) -> LockHolder {
+        LockHolder {
+            host: host.to_string(),
+            pid,
+            run: RunId::new().to_string(),
+            acquired,
+        }
+    }
+
+    #[test]
+    fn one_holder_at_a_time() {
+        let dir = TempDir::new("lock");
+        let path = dir.path().join("archive.lock");
+        let first = RunLock::acquire(&path, RunId::new(), HOUR).unwrap();
+        let e = RunLock::acquire(&path, RunId::new(), HOUR).unwrap_err();
+        assert!(e.to_string().contains("is held by run"), "{e}");
+        assert_eq!(
+            RunLock::holder(&path).unwrap().as_ref(),
+            Some(&first.holder)
+        );
+        drop(first);
+        assert!(!path.exists());
+        let second = RunLock::acquire(&path, RunId::new(), HOUR).unwrap();
+        assert_eq!(RunLock::holder(&path).unwrap(), Some(second.holder.clone()));
+    }
+
+    #[test]
+    fn breaks_a_lock_whose_holder_is_gone() {
+        let dir = TempDir::new("lock-dead");
+        // No process has the largest pid, so the holder isn't running
+        let path = held_by(&dir, &holder(&hostname(), u32::MAX, now()));
+        let lock = RunLock::acquire(&path, RunId::new(), None).unwrap();
+        assert_eq!(RunLock::holder(&path).unwrap(), Some(lock.holder.clone()));
+    }
+
+    #[test]
+    fn breaks_a_lock_from_another_host_once_stale() {
+        let dir = TempDir::new("lock-stale");
+        let path = held_by(&dir, &holder("elsewhere", 1, now()));
+        assert!(RunLock::acquire(&path, RunId::new(), HOUR).is_err());
+        let path = held_by(&dir, &holder("elsewhere", 1, now() - 7200));
+        assert!(RunLock::acquire(&path, RunId::new(), None).is_err());
+        let lock = RunLock::acquire(&path, RunId::new(), HOUR).unwrap();
+        assert_eq!(RunLock::holder(&path).unwrap(), Some(lock.holder.clone()));
+    }
+
+    #[test]
+    fn breaks_an_unreadable_lock_once_stale() {
+        let dir = TempDir::new("lock-empty");
+        let path = dir.write("archive.lock", b"");
+        let e = RunLock::acquire(&path, RunId::new(), HOUR).unwrap_err();
+        assert!(e.to_string().contains("can't be read"), "{e}");
+        assert!(RunLock::acquire(&path, RunId::new(), Some(Duration::ZERO)).is_ok());
+    }
+
+    #[test]
+    fn leaves_a_lock_taken_over_by_another_run() {
+        let dir = TempDir::new("lock-taken");
+        let path = dir.path().join("archive.lock");
+        let lock = RunLock::acquire(&path, RunId::new(), HOUR).unwrap();
+        let other = holder("elsewhere", 1, now());
+        std::fs::write(&path, other.contents()).unwrap();
+        drop(lock);
+        assert_eq!(RunLock::holder(&path).unwrap(), Some(other));
+    }
+}
@@ -1,150 +1,2530 @@
-use std::cmp::Ordering;
-use std::collections::BTreeSet;
-use std::fmt::{Display, Formatter};
-use std::fs::DirEntry;
-use std::hash::{Hash, Hasher};
-use std::path::PathBuf;
-
-use irox_log::log::{debug, error, info};
-use irox_time::format::{FormatError, FormatParser};
-use irox_time::format::iso8601::BASIC_CALENDAR_DATE;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use irox_log::log::{error, info, warn};
+use irox_time::datetime::UTCDateTime;
 use irox_time::gregorian::Date;
 
-#[derive(Debug)]
-pub enum Error {
-    IOError(std::io::Error),
-    FormatError(irox_time::format::FormatError),
+use charts_clean::{
+    audit, execute, execute_removal, find_gaps, format_attrs, format_size, init_logging,
+    json_string, locked_files, parse_duration, parse_size, post_webhook, read_only_reason,
+    AgeBucket, AgeHistogram, ArchiveExecutor, Baseline, BlobStore, CancelToken, Catalog,
+    CatalogQuery, ChartAlias, ChartId, ChartVersion, Checkpoint, ChecksumDb, CleanPlan, ColdTier,
+    CompanionRule, Config, ConflictPolicy, DeleteExecutor, Digest, DirCache, Downloader,
+    DryRunExecutor, Error, ErrorContext, ErrorKind, Executor, ExecutorKind, Export, ExportLayout,
+    ExportProfile, FamilyReport, FetchList, FileAttr, FixedFields, ForceAttrs, FoundFile, GroupKey,
+    GroupReport, HardlinkExecutor, Journal, LogBuffer, LogContext, Manifest, MarkerExecutor,
+    Mirror, NoProgress, OldDirExecutor, Phase, PlanDiff, Policy, Preset, Profile, ProfileReport,
+    ProtectedFiles, Quarantine, QuarantinedFile, Reclaimed, Recording, RemovalHistory,
+    RemovalPriority, Report, RunId, RunLock, RunVolume, Scan, Scanner, ScriptExecutor, SigningKey,
+    SizeRange, SkipList, SkipReason, SkippedFiles, StoredFile, Tenant, Throttle, TieredFile,
+    TrashExecutor, UtcOffset, VerifyingKey, Volume, WithdrawnAction, WithdrawnList,
+    DEFAULT_CHUNK_SIZE,
+};
+#[cfg(feature = "chaos")]
+use charts_clean::{ChaosExecutor, ChaosSpec};
+
+const DEFAULT_ROOT: &str = "/chonko-1/chartdata/USGS-Topo/28-JAN-2023";
+///
+/// Families listed by `analyze` without `--top`
+const DEFAULT_TOP: usize = 10;
+
+pub enum Command {
+    Clean,
+    /// Download the charts in a list that are newer than the archive's
+    Fetch(PathBuf),
+    Quarantine(QuarantineCommand),
+    /// Write a manifest of what a clean would remove, without removing anything
+    Plan,
+    /// Validate and execute a manifest written by `plan`
+    Apply(PathBuf),
+    /// Show what's changed on disk since a manifest was written, against a plan made now
+    DiffPlan(PathBuf),
+    /// Lay the kept charts out for a chartplotter
+    Export,
+    /// Re-read the kept charts and check them against their recorded checksums
+    Scrub,
+    /// Describe the archive, without planning or removing anything
+    Analyze,
+    /// Compare the archive against its baseline, without planning or removing anything
+    Audit,
+    Store(StoreCommand),
+    Cold(ColdCommand),
+    /// Write out the effective policy of each profile, for review
+    Policy,
+    /// Make a key pair to sign plans with, see [`SigningKey`]
+    Keygen,
+    /// Search the catalogs the last cleans wrote, without scanning
+    Query,
+    /// Clean the archives of a tenants file on their schedules, see [`Tenant`]
+    Serve(PathBuf),
+}
+
+pub enum QuarantineCommand {
+    /// Summarize what's held, by day and chart
+    List,
+    /// List the held versions of one chart
+    Show(String),
+    /// Remove held files, filtered by `--older-than` and `--chart`
+    Purge,
 }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Error::IOError(e) => write!(f, "IOError: {e}"),
-            Error::FormatError(e) => write!(f, "FormatError: {e}"),
+pub enum StoreCommand {
+    /// List what's stored, and where it came from
+    List,
+    /// Copy the blob with the hash, or a unique prefix of it, out to `--output`
+    Restore(String),
+}
+
+pub enum ColdCommand {
+    /// List what's been moved to the cold tiers, and where from
+    List,
+    /// Copy the file moved from the path, or the only one with that file name, out to `--output`
+    Restore(PathBuf),
+}
+
+///
+/// The command line.  Profile settings given here override the config file for every profile.
+#[derive(Default)]
+pub struct Options {
+    config: Option<PathBuf>,
+    only_profiles: Vec<String>,
+    only_exports: Vec<String>,
+    root: Option<PathBuf>,
+    source_tz: Option<UtcOffset>,
+    aliases: Vec<ChartAlias>,
+    fixed_fields: Option<FixedFields>,
+    /// Failures to inject into the removals of a fixture tree, see [`ChaosExecutor`]
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosSpec>,
+    /// A built-in preset's name or a preset file, applied under the config file and the other
+    /// settings given here
+    preset: Option<String>,
+    state_dir: Option<PathBuf>,
+    quarantine: Option<PathBuf>,
+    executor: Option<ExecutorKind>,
+    quarantine_retention: Option<Duration>,
+    verify_copies: bool,
+    checksums: bool,
+    mirror: Option<PathBuf>,
+    dry_run: bool,
+    priority: Option<RemovalPriority>,
+    max_deletions: Option<usize>,
+    scan_timeout: Option<Duration>,
+    planners: Option<usize>,
+    removal_timeout: Option<Duration>,
+    max_runtime: Option<Duration>,
+    max_requests_per_second: Option<f64>,
+    max_bandwidth: Option<u64>,
+    chunk_size: Option<u64>,
+    older_than: Option<Duration>,
+    chart: Option<String>,
+    /// `query` filters, along with `older_than` and `chart`
+    state: Option<String>,
+    source: Option<String>,
+    current: bool,
+    output: Option<PathBuf>,
+    layout: Option<ExportLayout>,
+    card_size: Option<u64>,
+    volumes: Vec<Volume>,
+    signing_key: Option<PathBuf>,
+    verifying_key: Option<PathBuf>,
+    require_signed: bool,
+    group_by: Option<Vec<GroupKey>>,
+    baseline: Option<PathBuf>,
+    lock: Option<PathBuf>,
+    lock_stale_after: Option<Duration>,
+    anomaly_factor: Option<f64>,
+    webhook: Option<String>,
+    allow_anomaly: bool,
+    buffer_logs: bool,
+    /// Clean each of `serve`'s tenants once, then exit
+    once: bool,
+    withdrawn: Option<PathBuf>,
+    withdrawn_action: Option<WithdrawnAction>,
+    protect: Vec<PathBuf>,
+    size_ranges: Vec<SizeRange>,
+    force_attrs: bool,
+    fail_on_skipped: bool,
+    dir_cache: bool,
+    edition_interval: Option<Duration>,
+    cold_after: Option<Duration>,
+    origin: Option<String>,
+    license: Option<String>,
+    companions: Vec<CompanionRule>,
+    /// Directories of recorded scans, written to or planned from instead of scanning
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    on_conflict: Option<ConflictPolicy>,
+    bucket: AgeBucket,
+    top: Option<usize>,
+}
+
+impl Options {
+    ///
+    /// Merges the config file (if any) with the command line into the profiles to run
+    fn to_config(&self) -> Result<Config, Error> {
+        // The preset goes under everything else, the config file's keys included
+        let preset = self.preset.as_deref().map(Preset::find).transpose()?;
+        let with_preset = |mut profile: Profile| -> Result<Profile, Error> {
+            if let Some(preset) = &preset {
+                preset.apply(&mut profile).map_err(Error::usage)?;
+            }
+            Ok(profile)
+        };
+        let mut config = match &self.config {
+            Some(path) => {
+                if self.root.is_some() {
+                    return Err(Error::usage("ROOT can't be combined with --config"));
+                }
+                Config::load_with_defaults(path, with_preset(Profile::new("", ""))?)?
+            }
+            None => {
+                let root = self
+                    .root
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(DEFAULT_ROOT));
+                Config {
+                    profiles: vec![with_preset(Profile::new("default", root))?],
+                    ..Default::default()
+                }
+            }
+        };
+        if !self.only_profiles.is_empty() {
+            for name in &self.only_profiles {
+                if !config.profiles.iter().any(|p| &p.name == name) {
+                    return Err(Error::usage(format!("Unknown profile: {name}")));
+                }
+            }
+            config
+                .profiles
+                .retain(|p| self.only_profiles.contains(&p.name));
+        }
+        for profile in &mut config.profiles {
+            if let Some(tz) = self.source_tz {
+                profile.source_tz = tz;
+            }
+            for alias in &self.aliases {
+                profile.aliases.push(alias.clone());
+            }
+            if self.fixed_fields.is_some() {
+                profile.fixed_fields.clone_from(&self.fixed_fields);
+            }
+            if let Some(quarantine) = &self.quarantine {
+                profile.quarantine = Some(quarantine.clone());
+            }
+            if self.executor.is_some() {
+                profile.executor.clone_from(&self.executor);
+            }
+            if self.quarantine_retention.is_some() {
+                profile.quarantine_retention = self.quarantine_retention;
+            }
+            profile.verify_copies |= self.verify_copies;
+            profile.checksums |= self.checksums;
+            if let Some(mirror) = &self.mirror {
+                profile.mirror = Some(mirror.clone());
+            }
+            if let Some(priority) = self.priority {
+                profile.priority = priority;
+            }
+            if self.max_deletions.is_some() {
+                profile.max_deletions = self.max_deletions;
+            }
+            if self.scan_timeout.is_some() {
+                profile.scan_timeout = self.scan_timeout;
+            }
+            if let Some(planners) = self.planners {
+                profile.planners = planners;
+            }
+            if self.removal_timeout.is_some() {
+                profile.removal_timeout = self.removal_timeout;
+            }
+            if self.baseline.is_some() {
+                profile.baseline.clone_from(&self.baseline);
+            }
+            if self.lock.is_some() {
+                profile.lock.clone_from(&self.lock);
+            }
+            if self.lock_stale_after.is_some() {
+                profile.lock_stale_after = self.lock_stale_after;
+            }
+            if self.anomaly_factor.is_some() {
+                profile.anomaly_factor = self.anomaly_factor;
+            }
+            if self.webhook.is_some() {
+                profile.webhook.clone_from(&self.webhook);
+            }
+            if self.withdrawn.is_some() {
+                profile.withdrawn.clone_from(&self.withdrawn);
+            }
+            if let Some(action) = self.withdrawn_action {
+                profile.withdrawn_action = action;
+            }
+            profile.protect.extend(self.protect.iter().cloned());
+            profile.size_ranges.extend(self.size_ranges.iter().cloned());
+            profile.companions.extend(self.companions.iter().cloned());
+            profile.force_attrs |= self.force_attrs;
+            profile.fail_on_skipped |= self.fail_on_skipped;
+            profile.dir_cache |= self.dir_cache;
+            if self.edition_interval.is_some() {
+                profile.edition_interval = self.edition_interval;
+            }
+            if self.cold_after.is_some() {
+                profile.cold_after = self.cold_after;
+            }
+            if self.origin.is_some() {
+                profile.provenance.origin.clone_from(&self.origin);
+            }
+            if self.license.is_some() {
+                profile.provenance.license.clone_from(&self.license);
+            }
+            if let Some(policy) = self.on_conflict {
+                profile.on_conflict = policy;
+            }
+            if let Some(group_by) = &self.group_by {
+                profile.group_by.clone_from(group_by);
+            }
+        }
+        if self.state_dir.is_some() {
+            config.state_dir.clone_from(&self.state_dir);
+        }
+        if self.signing_key.is_some() {
+            config.signing_key.clone_from(&self.signing_key);
+        }
+        if self.verifying_key.is_some() {
+            config.verifying_key.clone_from(&self.verifying_key);
         }
+        config.require_signed |= self.require_signed;
+        if self.max_bandwidth.is_some() {
+            config.max_bandwidth = self.max_bandwidth;
+        }
+        if self.max_requests_per_second.is_some() {
+            config.max_requests_per_second = self.max_requests_per_second;
+        }
+        if self.max_runtime.is_some() {
+            config.max_runtime = self.max_runtime;
+        }
+        Ok(config)
+    }
+}
+
+///
+/// `$XDG_STATE_HOME/charts-clean`, falling back to `~/.local/state/charts-clean`
+fn default_state_dir() -> PathBuf {
+    if let Some(state) = std::env::var_os("XDG_STATE_HOME") {
+        return PathBuf::from(state).join("charts-clean");
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        return PathBuf::from(home).join(".local/state/charts-clean");
     }
+    PathBuf::from(".charts-clean")
 }
 
-impl std::error::Error for Error {}
+fn next_value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, Error> {
+    args.next()
+        .ok_or_else(|| Error::usage(format!("{name} requires a value")))
+}
 
-impl From<std::io::Error> for Error {
-    fn from(value: std::io::Error) -> Self {
-        Error::IOError(value)
+///
+/// Parses the command line:
+/// `charts-clean fetch LIST [ROOT | --config FILE --profile NAME] [--chunk-size 64M] [--dry-run]
+///     [--max-bandwidth SIZE]`
+/// `charts-clean plan --output FILE [--signing-key FILE] [--record DIR | --replay DIR]
+///     [ROOT | --config FILE --profile NAME]`
+/// `charts-clean export [--export NAME]... --config FILE | [--layout opencpn|garmin|navionics]
+///     [--card-size SIZE] --output DIR | --volume DIR[,SIZE]...] [ROOT]`
+/// `charts-clean analyze [--bucket month|quarter|year] [--top N] [--record DIR | --replay DIR]
+///     [--config FILE [--profile NAME]... | ROOT]`
+/// `charts-clean audit [--config FILE [--profile NAME]... | --baseline FILE ROOT]
+///     [--state-dir DIR]`
+/// `charts-clean scrub [--config FILE [--profile NAME]... | ROOT] [--state-dir DIR]`
+/// `charts-clean apply MANIFEST [ROOT | --config FILE] [--quarantine DIR] [--dry-run]
+///     [--verifying-key FILE [--require-signed]] [--lock FILE [--lock-stale-after 12h]]
+///     [--on-conflict abort|skip|replan]`
+/// `charts-clean diff-plan MANIFEST [ROOT | --config FILE]`
+/// `charts-clean keygen --output FILE`, writing the signing key to FILE and the verifying key
+///     to FILE.pub
+/// `charts-clean store list|restore HASH --output PATH [--executor store:DIR | --config FILE]`
+/// `charts-clean cold list|restore PATH --output PATH [--executor cold:DIR | --config FILE]`
+/// `charts-clean quarantine list|show CHART|purge [--older-than 30d] [--chart NAME] [--dry-run]
+///     [--quarantine DIR | --config FILE [--profile NAME]...]`
+/// `charts-clean policy [--output FILE] [--config FILE [--profile NAME]...] [--preset NAME|FILE]
+///     [any of the profile settings below] [ROOT]`
+/// `charts-clean serve TENANTS [--once] [--state-dir DIR] [--dry-run] [--allow-anomaly]`
+/// `charts-clean query [--state OK] [--source TM] [--chart NAME] [--older-than 2y] [--current]
+///     [--config FILE [--profile NAME]... | ROOT] [--state-dir DIR]`
+/// `charts-clean [--config FILE [--profile NAME]...] [--preset NAME|FILE] [--source-tz +HHMM]
+///     [--alias 'FROM -> TO']... [--fixed-fields 'KIND:OFFSET:LENGTH, ...']
+///     [--state-dir DIR] [--buffer-logs]
+///     [--dry-run] [--quarantine DIR [--verify-copies] [--quarantine-retention 30d]] [--mirror DIR]
+///     [--executor delete|quarantine|trash[:DIR]|hardlink:DIR|archive:DIR|script:FILE|old-dir
+///         |store:DIR|cold:DIR|marker]
+///     [--checksums] [--priority largest|oldest|path] [--max-deletions N] [--max-bandwidth SIZE]
+///     [--max-requests-per-second N]
+///     [--scan-timeout 2h] [--planners N] [--removal-timeout 1h] [--max-runtime 30m]
+///     [--group-by source,state,chart]
+///     [--baseline FILE] [--lock FILE [--lock-stale-after 12h]]
+///     [--anomaly-factor 5 [--allow-anomaly]] [--webhook URL]
+///     [--withdrawn FILE [--withdrawn-action flag|remove]] [--protect FILE]...
+///     [--size-range 'EXT MIN-MAX']... [--companion 'PRIMARY: COMPANION, ...']...
+///     [--force-attrs] [--fail-on-skipped] [--edition-interval 7d] [--cold-after 1y] [--dir-cache]
+///     [--origin TEXT] [--license TEXT]
+///     [--record DIR | --replay DIR] [ROOT]`
+///
+/// `--record DIR` writes what each profile's scan found to `DIR/PROFILE.tsv`, and `--replay DIR`
+/// plans from those recordings instead of scanning, as a dry run that reads nothing under the
+/// roots, so policies can be tried out against an archive that's elsewhere or offline.  Only a
+/// `plan` reads files, to hash those recorded without hashes, ie by anything but `plan --record`.
+fn parse_args() -> Result<(Command, Options), Error> {
+    let mut opts = Options::default();
+    let mut args = std::env::args().skip(1).peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("fetch") => {
+            args.next();
+            Command::Fetch(PathBuf::from(next_value(&mut args, "fetch")?))
+        }
+        Some("quarantine") => {
+            args.next();
+            let command = match next_value(&mut args, "quarantine")?.as_str() {
+                "list" => QuarantineCommand::List,
+                "show" => QuarantineCommand::Show(next_value(&mut args, "quarantine show")?),
+                "purge" => QuarantineCommand::Purge,
+                other => {
+                    return Err(Error::usage(format!("Unknown quarantine command: {other}")));
+                }
+            };
+            Command::Quarantine(command)
+        }
+        Some("plan") => {
+            args.next();
+            Command::Plan
+        }
+        Some("apply") => {
+            args.next();
+            Command::Apply(PathBuf::from(next_value(&mut args, "apply")?))
+        }
+        Some("diff-plan") => {
+            args.next();
+            Command::DiffPlan(PathBuf::from(next_value(&mut args, "diff-plan")?))
+        }
+        Some("scrub") => {
+            args.next();
+            Command::Scrub
+        }
+        Some("export") => {
+            args.next();
+            Command::Export
+        }
+        Some("analyze") => {
+            args.next();
+            Command::Analyze
+        }
+        Some("audit") => {
+            args.next();
+            Command::Audit
+        }
+        Some("policy") => {
+            args.next();
+            Command::Policy
+        }
+        Some("keygen") => {
+            args.next();
+            Command::Keygen
+        }
+        Some("query") => {
+            args.next();
+            Command::Query
+        }
+        Some("serve") => {
+            args.next();
+            Command::Serve(PathBuf::from(next_value(&mut args, "serve")?))
+        }
+        Some("store") => {
+            args.next();
+            let command = match next_value(&mut args, "store")?.as_str() {
+                "list" => StoreCommand::List,
+                "restore" => StoreCommand::Restore(next_value(&mut args, "store restore")?),
+                other => return Err(Error::usage(format!("Unknown store command: {other}"))),
+            };
+            Command::Store(command)
+        }
+        Some("cold") => {
+            args.next();
+            let command = match next_value(&mut args, "cold")?.as_str() {
+                "list" => ColdCommand::List,
+                "restore" => {
+                    ColdCommand::Restore(PathBuf::from(next_value(&mut args, "cold restore")?))
+                }
+                other => return Err(Error::usage(format!("Unknown cold command: {other}"))),
+            };
+            Command::Cold(command)
+        }
+        _ => Command::Clean,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--chunk-size" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(size) = parse_size(&value).filter(|size| *size > 0) else {
+                    return Err(Error::usage(format!("Invalid size: {value}")));
+                };
+                opts.chunk_size = Some(size);
+            }
+            "--older-than" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(age) = parse_duration(&value) else {
+                    return Err(Error::usage(format!("Invalid duration: {value}")));
+                };
+                opts.older_than = Some(age);
+            }
+            "--chart" => opts.chart = Some(next_value(&mut args, &arg)?),
+            "--state" => opts.state = Some(next_value(&mut args, &arg)?),
+            "--source" => opts.source = Some(next_value(&mut args, &arg)?),
+            "--current" => opts.current = true,
+            "--output" => opts.output = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--export" => opts.only_exports.push(next_value(&mut args, &arg)?),
+            "--layout" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.layout = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--volume" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(volume) = Volume::parse(&value) else {
+                    return Err(Error::usage(format!("Invalid volume: {value}")));
+                };
+                opts.volumes.push(volume);
+            }
+            "--card-size" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(size) = parse_size(&value) else {
+                    return Err(Error::usage(format!("Invalid size: {value}")));
+                };
+                opts.card_size = Some(size);
+            }
+            "--config" => opts.config = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--profile" => opts.only_profiles.push(next_value(&mut args, &arg)?),
+            "--state-dir" => opts.state_dir = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--quarantine" => opts.quarantine = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--executor" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.executor = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--verify-copies" => opts.verify_copies = true,
+            "--checksums" => opts.checksums = true,
+            "--mirror" => opts.mirror = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--dry-run" => opts.dry_run = true,
+            "--top" => {
+                let value = next_value(&mut args, &arg)?;
+                let Ok(top) = value.parse() else {
+                    return Err(Error::usage(format!("Invalid count: {value}")));
+                };
+                opts.top = Some(top);
+            }
+            "--bucket" => {
+                opts.bucket = next_value(&mut args, &arg)?.parse().map_err(Error::usage)?;
+            }
+            "--baseline" => opts.baseline = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--lock" => opts.lock = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--withdrawn" => opts.withdrawn = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--withdrawn-action" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.withdrawn_action = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--preset" => opts.preset = Some(next_value(&mut args, &arg)?),
+            "--size-range" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.size_ranges.push(value.parse().map_err(Error::usage)?);
+            }
+            "--companion" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.companions.push(value.parse().map_err(Error::usage)?);
+            }
+            "--force-attrs" => opts.force_attrs = true,
+            "--fail-on-skipped" => opts.fail_on_skipped = true,
+            "--dir-cache" => opts.dir_cache = true,
+            "--record" => opts.record = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--replay" => opts.replay = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--protect" => opts
+                .protect
+                .push(PathBuf::from(next_value(&mut args, &arg)?)),
+            "--alias" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.aliases.push(value.parse().map_err(Error::usage)?);
+            }
+            #[cfg(feature = "chaos")]
+            "--chaos" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.chaos = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--fixed-fields" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.fixed_fields = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--on-conflict" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.on_conflict = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--group-by" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.group_by = Some(GroupKey::parse_list(&value).map_err(Error::usage)?);
+            }
+            "--signing-key" => {
+                opts.signing_key = Some(PathBuf::from(next_value(&mut args, &arg)?));
+            }
+            "--verifying-key" => {
+                opts.verifying_key = Some(PathBuf::from(next_value(&mut args, &arg)?));
+            }
+            "--require-signed" => opts.require_signed = true,
+            "--allow-anomaly" => opts.allow_anomaly = true,
+            "--once" => opts.once = true,
+            "--buffer-logs" => opts.buffer_logs = true,
+            "--webhook" => opts.webhook = Some(next_value(&mut args, &arg)?),
+            "--origin" => opts.origin = Some(next_value(&mut args, &arg)?),
+            "--license" => opts.license = Some(next_value(&mut args, &arg)?),
+            "--anomaly-factor" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(factor) = value.parse().ok().filter(|f: &f64| *f > 1.0) else {
+                    return Err(Error::usage(format!("Invalid factor: {value}")));
+                };
+                opts.anomaly_factor = Some(factor);
+            }
+            "--quarantine-retention" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(retention) = parse_duration(&value) else {
+                    return Err(Error::usage(format!("Invalid duration: {value}")));
+                };
+                opts.quarantine_retention = Some(retention);
+            }
+            "--priority" => {
+                let value = next_value(&mut args, &arg)?;
+                opts.priority = Some(value.parse().map_err(Error::usage)?);
+            }
+            "--max-deletions" => {
+                let value = next_value(&mut args, &arg)?;
+                let Ok(max) = value.parse() else {
+                    return Err(Error::usage(format!("Invalid count: {value}")));
+                };
+                opts.max_deletions = Some(max);
+            }
+            "--planners" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(planners) = value.parse().ok().filter(|n| *n > 0) else {
+                    return Err(Error::usage(format!("Invalid count: {value}")));
+                };
+                opts.planners = Some(planners);
+            }
+            "--scan-timeout" | "--removal-timeout" | "--lock-stale-after" | "--max-runtime" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(timeout) = parse_duration(&value) else {
+                    return Err(Error::usage(format!("Invalid duration: {value}")));
+                };
+                match arg.as_str() {
+                    "--scan-timeout" => opts.scan_timeout = Some(timeout),
+                    "--removal-timeout" => opts.removal_timeout = Some(timeout),
+                    "--max-runtime" => opts.max_runtime = Some(timeout),
+                    _ => opts.lock_stale_after = Some(timeout),
+                }
+            }
+            "--edition-interval" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(interval) = parse_duration(&value).filter(|i| !i.is_zero()) else {
+                    return Err(Error::usage(format!("Invalid duration: {value}")));
+                };
+                opts.edition_interval = Some(interval);
+            }
+            "--cold-after" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(age) = parse_duration(&value) else {
+                    return Err(Error::usage(format!("Invalid duration: {value}")));
+                };
+                opts.cold_after = Some(age);
+            }
+            "--max-bandwidth" => {
+                let value = next_value(&mut args, &arg)?;
+                let Some(max) = parse_size(&value) else {
+                    return Err(Error::usage(format!("Invalid bandwidth: {value}")));
+                };
+                opts.max_bandwidth = Some(max);
+            }
+            "--max-requests-per-second" => {
+                let value = next_value(&mut args, &arg)?;
+                let Ok(max) = value.parse() else {
+                    return Err(Error::usage(format!("Invalid request rate: {value}")));
+                };
+                opts.max_requests_per_second = Some(max);
+            }
+            "--source-tz" => {
+                let tz = next_value(&mut args, &arg)?;
+                let Some(tz) = UtcOffset::parse(&tz) else {
+                    return Err(Error::usage(format!("Invalid timezone: {tz}")));
+                };
+                opts.source_tz = Some(tz);
+            }
+            _ if arg.starts_with("--") => {
+                return Err(Error::usage(format!("Unknown option: {arg}")));
+            }
+            _ => opts.root = Some(PathBuf::from(arg)),
+        }
     }
+    Ok((command, opts))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+///
+/// What every profile of a run shares
+struct Run {
+    dry_run: bool,
+    /// Clean even if the plan is unusually large
+    allow_anomaly: bool,
+    state_dir: PathBuf,
+    throttle: Arc<Throttle>,
+    journal: Journal,
+    cancel: CancelToken,
+    /// Hold each profile's log lines back and write them out together once it's done
+    buffer_logs: bool,
+    /// When the run's `--max-runtime` is up, if it has one
+    deadline: Option<Instant>,
+    /// Where each profile's scan is recorded, with `--record`
+    record: Option<PathBuf>,
+    /// Where each profile's recorded scan is replayed from instead of scanning, with `--replay`
+    replay: Option<PathBuf>,
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosSpec>,
 }
 
-impl From<FormatError> for Error {
-    fn from(value: FormatError) -> Self {
-        Error::FormatError(value)
+impl Run {
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
     }
 }
 
-#[derive(Debug)]
-pub struct FoundFile {
-    path: String,
-    date: Date,
-    full_path: PathBuf,
+///
+/// The executor a profile removes files with, or pretends to in a dry run
+fn profile_executor(
+    profile: &Profile,
+    run: &Run,
+    dry_run: bool,
+) -> Result<Box<dyn Executor>, Error> {
+    if dry_run {
+        return Ok(Box::new(DryRunExecutor));
+    }
+    let throttle = run.throttle.clone();
+    let kind = match (&profile.executor, &profile.quarantine) {
+        (Some(kind), _) => kind.clone(),
+        (None, Some(_)) => ExecutorKind::Quarantine,
+        (None, None) => ExecutorKind::Delete,
+    };
+    let executor: Box<dyn Executor> = match kind {
+        ExecutorKind::Delete => Box::new(DeleteExecutor::new(throttle)),
+        ExecutorKind::Quarantine => match profile_quarantine(profile, &throttle) {
+            Some(quarantine) => Box::new(quarantine),
+            None => {
+                return Err(Error::usage(
+                    "The quarantine executor needs --quarantine DIR",
+                ))
+            }
+        },
+        ExecutorKind::Trash(dir) => {
+            let dir = dir.unwrap_or_else(TrashExecutor::default_dir);
+            Box::new(TrashExecutor::new(dir, throttle))
+        }
+        ExecutorKind::Hardlink(dir) => Box::new(HardlinkExecutor::new(dir, throttle)),
+        ExecutorKind::Archive(dir) => {
+            Box::new(ArchiveExecutor::new(&dir, throttle).with_verify(profile.verify_copies))
+        }
+        ExecutorKind::Script(path) => Box::new(ScriptExecutor::new(path)),
+        ExecutorKind::OldDir => {
+            Box::new(OldDirExecutor::new(throttle).with_verify(profile.verify_copies))
+        }
+        ExecutorKind::Store(dir) => {
+            Box::new(BlobStore::new(dir, throttle).with_verify(profile.verify_copies))
+        }
+        ExecutorKind::Cold(dir) => {
+            Box::new(ColdTier::new(dir, throttle).with_verify(profile.verify_copies))
+        }
+        ExecutorKind::Marker => Box::new(MarkerExecutor),
+    };
+    #[cfg(feature = "chaos")]
+    if let Some(chaos) = &run.chaos {
+        return Ok(Box::new(ChaosExecutor::new(
+            executor,
+            chaos,
+            &profile.root,
+        )?));
+    }
+    Ok(executor)
 }
 
-impl Display for FoundFile {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}/{}", self.path, self.date)
+fn profile_quarantine(profile: &Profile, throttle: &Arc<Throttle>) -> Option<Quarantine> {
+    profile.quarantine.as_ref().map(|dir| {
+        Quarantine::new(dir)
+            .with_verify_copies(profile.verify_copies)
+            .with_throttle(throttle.clone())
+            .with_scanners(vec![profile_scanner(profile, throttle)])
+    })
+}
+
+fn warn_locked(locked: &BTreeMap<PathBuf, Vec<FileAttr>>) {
+    for (path, attrs) in locked {
+        warn!(
+            "{} is {}, skipping it; use --force-attrs to clear and remove it",
+            path.display(),
+            format_attrs(attrs)
+        );
     }
 }
 
-impl PartialEq for FoundFile {
-    fn eq(&self, other: &Self) -> bool {
-        self.path.eq(&other.path)
+///
+/// Lists the locked files in the report, by their attributes
+fn hold_back_locked(report: &mut ProfileReport, locked: &BTreeMap<PathBuf, Vec<FileAttr>>) {
+    for (path, attrs) in locked {
+        report
+            .held_back
+            .add(SkipReason::Locked, path, format_attrs(attrs));
     }
 }
 
-impl Eq for FoundFile {}
+///
+/// Writes every file each profile neither kept nor removed to `skipped/<profile>.tsv` in the
+/// state dir, for the files the report's listing leaves out
+fn save_skipped(report: &Report, state_dir: &Path) {
+    let dir = state_dir.join("skipped");
+    for profile in &report.profiles {
+        let path = dir.join(format!("{}.tsv", profile.profile));
+        if let Err(e) = profile.skipped_files().save(&path) {
+            warn!("Couldn't list the files {} skipped: {e}", profile.profile);
+        }
+    }
+}
 
-impl PartialOrd for FoundFile {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.path.partial_cmp(&other.path)
+///
+/// The executor, clearing the attributes of the locked files before removing them if the
+/// profile forces them
+fn force_attrs(
+    executor: Box<dyn Executor>,
+    profile: &Profile,
+    locked: BTreeMap<PathBuf, Vec<FileAttr>>,
+) -> Box<dyn Executor> {
+    if !profile.force_attrs || locked.is_empty() {
+        return executor;
     }
+    Box::new(ForceAttrs::new(executor, locked))
 }
 
-impl Ord for FoundFile {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.path.cmp(&other.path)
+///
+/// The profile's policy, along with its baseline, withdrawn charts and protected files if it
+/// has them
+fn profile_policy(profile: &Profile) -> Result<Policy, Error> {
+    let baseline = match &profile.baseline {
+        Some(path) => Some(Arc::new(Baseline::load(path)?)),
+        None => None,
+    };
+    let withdrawn = match &profile.withdrawn {
+        Some(path) => Some(Arc::new(WithdrawnList::load(path)?)),
+        None => None,
+    };
+    let mut protected = None;
+    for path in &profile.protect {
+        let files = ProtectedFiles::load(path)?;
+        info!("{} protects {} files", path.display(), files.len());
+        protected
+            .get_or_insert_with(ProtectedFiles::default)
+            .extend(files);
     }
+    Ok(profile
+        .policy()
+        .with_baseline(baseline)
+        .with_withdrawn(withdrawn, profile.withdrawn_action)
+        .with_protected(protected.map(Arc::new))
+        .with_removable_before(
+            profile
+                .cold_after
+                .map(|age| unix_now() as i64 - age.as_secs() as i64),
+        ))
+}
+
+///
+/// A scanner of the profile's root, filing charts as the profile does
+fn profile_scanner(profile: &Profile, throttle: &Arc<Throttle>) -> Scanner {
+    Scanner::new(&profile.root)
+        .with_source_tz(profile.source_tz)
+        .with_aliases(profile.aliases.clone())
+        .with_fixed_fields(profile.fixed_fields.clone())
+        .with_companions(profile.companions.clone())
+        .with_throttle(throttle.clone())
+}
+
+///
+/// Where a profile's checksums are kept
+fn checksum_db_path(state_dir: &Path, profile: &Profile) -> PathBuf {
+    state_dir
+        .join("checksums")
+        .join(format!("{}.tsv", profile.name))
+}
+
+fn catalog_path(state_dir: &Path, profile: &Profile) -> PathBuf {
+    state_dir
+        .join("catalog")
+        .join(format!("{}.tsv", profile.name))
 }
 
-impl Hash for FoundFile {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.path.hash(state);
+fn checkpoint_path(state_dir: &Path, profile: &Profile) -> PathBuf {
+    state_dir
+        .join("checkpoints")
+        .join(format!("{}.tsv", profile.name))
+}
+
+///
+/// Where the profile's [`DirCache`] is kept
+fn dir_cache_path(state_dir: &Path, profile: &Profile) -> PathBuf {
+    state_dir
+        .join("dircache")
+        .join(format!("{}.tsv", profile.name))
+}
+
+///
+/// Loads the profile's directory cache, starting an empty one in place of one that can't be
+/// read, as it only saves work
+fn load_dir_cache(state_dir: &Path, profile: &Profile) -> DirCache {
+    DirCache::load(&dir_cache_path(state_dir, profile)).unwrap_or_else(|e| {
+        warn!("Ignoring the directory cache of {}: {e}", profile.name);
+        DirCache::default()
+    })
+}
+
+///
+/// Where `--record` writes, and `--replay` reads, the profile's scan
+fn recording_path(dir: &Path, profile: &Profile) -> PathBuf {
+    dir.join(format!("{}.tsv", profile.name))
+}
+
+///
+/// Scans the profile's root, or with `--replay` plans its recorded scan instead, recording
+/// what was found with `--record`, and keeping the profile's directory cache up to date
+fn scan_profile(scanner: Scanner, profile: &Profile, run: &Run) -> Result<Scan, Error> {
+    if let Some(dir) = &run.replay {
+        let recording = Recording::load(&recording_path(dir, profile))?;
+        info!(
+            "Replaying {} files of {} recorded from {}",
+            recording.files.len(),
+            profile.name,
+            recording.root.display()
+        );
+        return Ok(scanner.replay(&recording));
     }
+    let cache = profile
+        .dir_cache
+        .then(|| Arc::new(load_dir_cache(&run.state_dir, profile)));
+    let scanner = match &cache {
+        Some(cache) => scanner.with_dir_cache(cache.clone()),
+        None => scanner,
+    };
+    let parser = scanner.parser_key();
+    let scan = scanner.run(&run.cancel.with_timeout(profile.scan_timeout))?;
+    if let Some(cache) = cache {
+        info!(
+            "{} of {} directories of {} unchanged since the last scan",
+            cache.unchanged(&scan),
+            scan.dir_keys.len(),
+            profile.name
+        );
+        let path = dir_cache_path(&run.state_dir, profile);
+        if let Err(e) = cache.updated(&scan, parser).save(&path) {
+            warn!("Couldn't save the directory cache of {}: {e}", profile.name);
+        }
+    }
+    if let Some(dir) = &run.record {
+        let recording = Recording::from_scan(&profile.name, &profile.root, &scan, unix_now());
+        recording.save(&recording_path(dir, profile))?;
+    }
+    Ok(scan)
 }
 
-fn scan_dir_and_recurse(dir: &DirEntry, to_keep: &mut BTreeSet<FoundFile>, to_remove: &mut BTreeSet<PathBuf>) -> Result<(), Error> {
-    let ty = dir.file_type()?;
-    let path = dir.path();
-    if ty.is_dir() {
-        let dirs = std::fs::read_dir(path)?;
-        for dir in dirs {
-            let dir = dir?;
-            scan_dir_and_recurse(&dir, to_keep, to_remove)?;
+///
+/// Records how much the plan removes, refusing it if it's far more than the usual and the
+/// profile has an anomaly factor
+fn check_volume(
+    profile: &Profile,
+    run: &Run,
+    plan: &CleanPlan,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let path = run
+        .state_dir
+        .join("history")
+        .join(format!("{}.tsv", profile.name));
+    let mut history = RemovalHistory::load(path)?;
+    let volume = RunVolume::from_plan(plan, unix_now());
+    let anomaly = profile
+        .anomaly_factor
+        .and_then(|f| history.check(volume, f));
+    if let Some(anomaly) = anomaly {
+        let msg = format!("{} {anomaly}", profile.name);
+        if let Some(url) = &profile.webhook {
+            let json = format!(
+                "{{\"profile\":{},\"run\":\"{}\",\"files\":{},\"bytes\":{},\"usual\":{},\
+                 \"message\":{}}}",
+                json_string(&profile.name),
+                run.journal.run(),
+                anomaly.planned.files,
+                anomaly.planned.bytes,
+                anomaly.usual,
+                json_string(&msg)
+            );
+            if let Err(e) = post_webhook(url, &json) {
+                warn!("Unable to send alert: {e}");
+            }
         }
-        return Ok(());
+        if !run.allow_anomaly {
+            return Err(Error::validate(format!(
+                "{msg}, use --allow-anomaly if it's expected"
+            )));
+        }
+        warn!("{msg}, cleaning anyway");
+    }
+    if !dry_run {
+        history.record(volume)?;
+    }
+    Ok(())
+}
+
+///
+/// Cleans the profile.  A profile whose root is read-only, see [`read_only_reason`], is planned
+/// and reported on as a dry run would be.
+fn run_profile(profile: &Profile, run: &Run, read_only: Option<&str>) -> ProfileReport {
+    let (dry_run, throttle) = (run.dry_run || read_only.is_some(), &run.throttle);
+    // A replay only plans, leaving the archive, and anything that reads it, alone
+    let replay = run.replay.is_some();
+    let mut report = ProfileReport::new(&profile.name);
+    report.provenance = profile.provenance.clone();
+    if !replay {
+        if let Err(e) = std::fs::metadata(&profile.root).context(Phase::Setup, &profile.root) {
+            report.errors.push(e);
+            return report;
+        }
+    }
+    if let Some(reason) = read_only {
+        warn!(
+            "{reason}, planning {} without removing anything",
+            profile.name
+        );
+        report.read_only = Some(reason.to_string());
+    }
+    match Checkpoint::load(checkpoint_path(&run.state_dir, profile)) {
+        Ok(checkpoint) if !checkpoint.is_empty() && !replay => {
+            let mut resumed = resume(profile, run, checkpoint, dry_run);
+            resumed.read_only = report.read_only;
+            return resumed;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    }
+    let scanner = profile_scanner(profile, throttle).with_planners(profile.planners);
+    let scan = scan_profile(scanner, profile, run);
+    let mut scan = match scan {
+        Ok(scan) => scan,
+        Err(_) if run.out_of_time() => {
+            warn!(
+                "Ran out of time scanning {}, nothing was removed",
+                profile.name
+            );
+            return report;
+        }
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    let mut errors = std::mem::take(&mut scan.errors);
+    report.unreadable = std::mem::take(&mut scan.skipped);
+    report.gaps = find_gaps(
+        scan.to_keep.iter().chain(&scan.to_remove),
+        profile.edition_interval,
+    );
+    if profile.fail_on_skipped && !report.unreadable.is_empty() {
+        let msg = format!("{} directories couldn't be read", report.unreadable.len());
+        let e = Error::validate(msg);
+        error!("Refusing to clean {}: {e}", profile.name);
+        errors.push(e);
+        report.errors = errors;
+        return report;
+    }
+    let mut plan = match profile_policy(profile).and_then(|policy| CleanPlan::new(&scan, &policy)) {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("Refusing to clean {}: {e}", profile.name);
+            report.errors.push(e);
+            return report;
+        }
+    };
+    for file in &plan.withdrawn {
+        let action = if plan.retired.contains(file) {
+            "retiring"
+        } else {
+            "keeping"
+        };
+        info!(
+            "{} has been withdrawn by its publisher, {action} it",
+            file.id()
+        );
+    }
+    for file in &plan.suspect {
+        let range = SizeRange::violated(&profile.size_ranges, file).map(ToString::to_string);
+        let fallback = match plan.retained.iter().find(|f| f.id() == file.id()) {
+            Some(kept) => format!("keeping {} too", kept.full_path().display()),
+            None => "with no previous edition to keep".to_string(),
+        };
+        warn!(
+            "{} is {}, outside the expected {}, {fallback}",
+            file.full_path().display(),
+            format_size(file.size()),
+            range.unwrap_or_default()
+        );
+    }
+    let candidates: Vec<PathBuf> = plan
+        .remove
+        .iter()
+        .map(|r| r.file)
+        .chain(plan.retired.iter().copied())
+        .map(FoundFile::full_path)
+        .collect();
+    let locked = if replay {
+        BTreeMap::new()
+    } else {
+        locked_files(&candidates)
+    };
+    if !profile.force_attrs {
+        warn_locked(&locked);
+        plan.skip_locked(&locked);
+    }
+    if run.deadline.is_some() {
+        plan.by_reclaim();
+    }
+    if let Err(e) = check_volume(profile, run, &plan, dry_run) {
+        error!("Refusing to clean {}: {e}", profile.name);
+        report.errors.push(e);
+        return report;
+    }
+
+    let executor = match profile_executor(profile, run, dry_run) {
+        Ok(executor) => force_attrs(executor, profile, locked),
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    for file in &plan.downgraded {
+        let held = plan.held.iter().filter(|f| f.id() == file.id()).count();
+        let msg = format!(
+            "{} is older than the baseline edition of {}, holding back {held} removals",
+            file.full_path().display(),
+            file.id()
+        );
+        errors.push(Error::validate(msg));
+    }
+    let quarantine = profile_quarantine(profile, throttle);
+    let cancel = run.cancel.with_timeout(profile.removal_timeout);
+    let mut execution = execute(
+        &plan,
+        &profile.root,
+        executor.as_ref(),
+        &run.journal,
+        &profile.name,
+        &cancel,
+        &NoProgress,
+    );
+    report.removed = execution.removed;
+    report.removed_bytes = execution.removed_bytes;
+    report.reclaimed_bytes = execution.reclaimed_bytes;
+    report.held_back = SkippedFiles::from_plan(&plan);
+    // Out of time isn't a failure, what's left is carried on with by the next run
+    let out_of_time = run.out_of_time();
+    if out_of_time {
+        execution
+            .errors
+            .retain(|e| !matches!(e.kind(), ErrorKind::Cancelled(_)));
+        report.pending = execution.pending.len();
+        for entry in &execution.pending {
+            let path = profile.root.join(&entry.path);
+            report
+                .held_back
+                .add(SkipReason::Excluded, path, "checkpointed for the next run");
+        }
+        if !dry_run {
+            let res =
+                Checkpoint::load(checkpoint_path(&run.state_dir, profile)).and_then(|mut c| {
+                    c.entries = std::mem::take(&mut execution.pending);
+                    c.save()
+                });
+            if let Err(e) = res {
+                errors.push(e);
+            }
+        }
+    }
+    errors.append(&mut execution.errors);
+    // Expiry, the mirror and checksums wait for a run that gets through its removals on disk
+    let finish = !out_of_time && !replay;
+    let quarantine = quarantine.filter(|_| finish);
+    if let (Some(quarantine), Some(retention)) = (&quarantine, profile.quarantine_retention) {
+        match quarantine.expire(retention, dry_run) {
+            Ok(expiry) => report.expiry = Some(expiry),
+            Err(e) => errors.push(e),
+        }
+    }
+    if let Some(dir) = profile.mirror.as_ref().filter(|_| finish) {
+        let mirror = Mirror::new(dir)
+            .with_compare_hashes(profile.verify_copies)
+            .with_source_tz(profile.source_tz)
+            .with_aliases(profile.aliases.clone())
+            .with_fixed_fields(profile.fixed_fields.clone())
+            .with_throttle(throttle.clone());
+        let mut synced = mirror.sync(&plan, dry_run);
+        errors.append(&mut synced.errors);
+        report.mirror = Some(synced);
+    }
+    if profile.checksums && finish {
+        let res = ChecksumDb::load(checksum_db_path(&run.state_dir, profile)).and_then(|mut db| {
+            let mut failed = db.record(&profile.root, &plan.keep, unix_now());
+            errors.append(&mut failed);
+            db.save()
+        });
+        if let Err(e) = res {
+            errors.push(e);
+        }
+    }
+    if !profile.group_by.is_empty() {
+        report.groups = Some(GroupReport::new(&profile.group_by, &plan));
+    }
+    if !dry_run {
+        let mut catalog = Catalog::from_plan(&profile.name, &profile.root, &plan, unix_now());
+        catalog.provenance = profile.provenance.clone();
+        if let Err(e) = catalog.save(&catalog_path(&run.state_dir, profile)) {
+            errors.push(e);
+        }
+    }
+    if let Some(path) = profile
+        .baseline
+        .as_ref()
+        .filter(|_| errors.is_empty() && !dry_run)
+    {
+        if let Err(e) = Baseline::from_plan(&plan).save(path) {
+            errors.push(e);
+        }
+    }
+    report.kept = plan.keep.len();
+    report.deferred = plan.deferred.len();
+    report.aging = plan.aging.len();
+    report.withdrawn = plan.withdrawn.len();
+    report.retired = plan.retired.len();
+    report.protected = plan.protected.len();
+    report.retained = plan.retained.len();
+    report.locked = plan.locked.len();
+    report.errors = errors;
+    report
+}
+
+///
+/// Carries on with the removals a time-boxed run of the profile checkpointed, instead of
+/// scanning again.  Whatever this run doesn't get to either is checkpointed again.
+fn resume(
+    profile: &Profile,
+    run: &Run,
+    mut checkpoint: Checkpoint,
+    dry_run: bool,
+) -> ProfileReport {
+    let mut report = ProfileReport::new(&profile.name);
+    report.provenance = profile.provenance.clone();
+    info!(
+        "Resuming {} checkpointed removals",
+        checkpoint.entries.len()
+    );
+    let policy = match profile_policy(profile) {
+        Ok(policy) => policy,
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    let mut removals = Vec::new();
+    for entry in std::mem::take(&mut checkpoint.entries) {
+        let path = profile.root.join(&entry.path);
+        let by = entry.superseded_by.as_ref().map(|by| profile.root.join(by));
+        let unchanged = std::fs::symlink_metadata(&path).is_ok_and(|m| m.len() == entry.size);
+        if !unchanged || by.as_ref().is_some_and(|by| !by.exists()) {
+            info!(
+                "{} changed since it was checkpointed, leaving it",
+                path.display()
+            );
+            report.skipped += 1;
+            let detail = "changed since it was checkpointed";
+            report.held_back.add(SkipReason::Excluded, path, detail);
+            continue;
+        }
+        if policy.protected.as_ref().is_some_and(|p| p.contains(&path)) {
+            report.protected += 1;
+            let detail = "referenced by another application";
+            report.held_back.add(SkipReason::Protected, path, detail);
+            continue;
+        }
+        removals.push((entry, path, by));
+    }
+    let candidates: Vec<PathBuf> = removals.iter().map(|(_, path, _)| path.clone()).collect();
+    let locked = locked_files(&candidates);
+    if !profile.force_attrs {
+        warn_locked(&locked);
+        removals.retain(|(_, path, _)| !locked.contains_key(path));
+        report.locked = locked.len();
+        hold_back_locked(&mut report, &locked);
+    }
+    let executor = match profile_executor(profile, run, dry_run) {
+        Ok(executor) => force_attrs(executor, profile, locked),
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    let cancel = run.cancel.with_timeout(profile.removal_timeout);
+    let mut reclaimed = Reclaimed::new(&candidates);
+    let mut removals = removals.into_iter();
+    for (entry, path, by) in removals.by_ref() {
+        if let Err(e) = cancel.check(Phase::Delete) {
+            if !run.out_of_time() {
+                report.errors.push(e);
+            }
+            checkpoint.entries.push(entry);
+            break;
+        }
+        let (journal, name, root) = (&run.journal, &profile.name, &profile.root);
+        let removed = execute_removal(
+            executor.as_ref(),
+            journal,
+            name,
+            &mut reclaimed,
+            root,
+            &path,
+            by.as_deref(),
+        );
+        match removed {
+            Ok(_) => {
+                report.removed += 1;
+                report.removed_bytes += entry.size;
+            }
+            Err(e) => report.errors.push(e),
+        }
+    }
+    checkpoint
+        .entries
+        .extend(removals.map(|(entry, _, _)| entry));
+    if let Err(e) = executor.finish() {
+        report.errors.push(e);
+    }
+    report.reclaimed_bytes = reclaimed.bytes();
+    report.pending = checkpoint.entries.len();
+    for entry in &checkpoint.entries {
+        let path = profile.root.join(&entry.path);
+        report
+            .held_back
+            .add(SkipReason::Excluded, path, "checkpointed for the next run");
+    }
+    if !dry_run {
+        if let Err(e) = checkpoint.save() {
+            report.errors.push(e);
+        }
+    }
+    report
+}
+
+///
+/// Runs every profile concurrently, each on a thread named after the profile so its log lines
+/// are tagged with it.
+///
+/// Takes the lock of every profile that has one, once per lock file so profiles sharing one can
+/// run together.  A profile whose lock can't be taken gets the reason instead.  Profiles that are
+/// only planned, as their roots are read-only, don't take theirs.
+fn profile_locks(
+    profiles: &[Profile],
+    read_only: &[Option<String>],
+    run: &Run,
+) -> BTreeMap<PathBuf, Result<RunLock, String>> {
+    let mut locks = BTreeMap::new();
+    if run.dry_run {
+        return locks;
+    }
+    for (profile, _) in profiles.iter().zip(read_only).filter(|(_, r)| r.is_none()) {
+        let Some(path) = &profile.lock else {
+            continue;
+        };
+        if !locks.contains_key(path) {
+            let lock = RunLock::acquire(path, run.journal.run(), profile.lock_stale_after);
+            let lock = lock.map_err(|e| match e.kind() {
+                ErrorKind::Locked(msg) => msg.clone(),
+                _ => e.to_string(),
+            });
+            locks.insert(path.clone(), lock);
+        }
+    }
+    locks
+}
+
+fn run_profiles(profiles: &[Profile], run: &Run) -> Report {
+    let mut report = Report {
+        run: Some(run.journal.run()),
+        dry_run: run.dry_run,
+        ..Default::default()
+    };
+    // Checked up front, so a read-only archive's lock isn't taken either
+    let read_only: Vec<Option<String>> = profiles
+        .iter()
+        .map(|profile| {
+            (!run.dry_run)
+                .then(|| read_only_reason(&profile.root))
+                .flatten()
+        })
+        .collect();
+    let locks = profile_locks(profiles, &read_only, run);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = profiles
+            .iter()
+            .zip(&read_only)
+            .map(|(profile, read_only)| {
+                let lock = profile.lock.as_ref().and_then(|path| locks.get(path));
+                if let Some(Err(msg)) = lock {
+                    return (profile, Err(msg.clone()));
+                }
+                let buffer = run.buffer_logs.then(LogBuffer::default);
+                let log = LogContext::nested(&profile.name, buffer.clone());
+                let handle = std::thread::Builder::new()
+                    .name(profile.name.clone())
+                    .spawn_scoped(scope, move || {
+                        let _log = log.enter();
+                        run_profile(profile, run, read_only.as_deref())
+                    });
+                (profile, Ok((handle, buffer)))
+            })
+            .collect();
+        // Buffered lines are written in profile order, as each profile is joined
+        for (profile, handle) in handles {
+            let result = match handle {
+                Ok((Ok(handle), buffer)) => {
+                    let result = handle.join().ok();
+                    if let Some(buffer) = buffer {
+                        buffer.flush();
+                    }
+                    result
+                }
+                Ok((Err(e), _)) => {
+                    error!("Unable to start profile {}: {e}", profile.name);
+                    None
+                }
+                Err(msg) => {
+                    let mut locked = ProfileReport::new(&profile.name);
+                    locked
+                        .errors
+                        .push(Error::new(ErrorKind::Locked(msg), Phase::Setup));
+                    Some(locked)
+                }
+            };
+            report.profiles.push(result.unwrap_or_else(|| {
+                error!("Profile {} did not complete", profile.name);
+                ProfileReport::new(&profile.name)
+            }));
+        }
+    });
+    report
+}
+
+///
+/// Downloads each chart in the list that's newer than the newest version of it in the archive,
+/// and not already there
+fn run_fetch(list: &Path, opts: &Options, config: &Config, run: &Run) -> Result<(), Error> {
+    let [profile] = config.profiles.as_slice() else {
+        return Err(Error::usage(
+            "fetch needs exactly one profile, use --profile NAME",
+        ));
+    };
+    let list = FetchList::load(list)?;
+    let scanner = profile_scanner(profile, &run.throttle);
+    let scan = scanner.run(&run.cancel.with_timeout(profile.scan_timeout))?;
+    let mut newest: BTreeMap<&ChartId, &ChartVersion> = BTreeMap::new();
+    for file in &scan.to_keep {
+        let version = newest.entry(file.id()).or_insert(file.version());
+        *version = (*version).max(file.version());
+    }
+    let downloader = Downloader::new()
+        .with_chunk_size(opts.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE))
+        .with_throttle(run.throttle.clone());
+    let (mut fetched, mut errors) = (0, Vec::new());
+    for entry in &list.entries {
+        // Checked as the list was loaded
+        let relative = entry.relative_path().unwrap_or_default();
+        let file = match scanner.parse_name(&relative) {
+            Ok(file) => file,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if newest
+            .get(file.id())
+            .is_some_and(|newest| *newest >= file.version())
+        {
+            info!(
+                "Skipping {}, the archive has {} as new",
+                entry.url,
+                file.id()
+            );
+            continue;
+        }
+        let dest = profile.root.join(&relative);
+        if dest.exists() {
+            info!(
+                "Skipping {}, {} is already there",
+                entry.url,
+                dest.display()
+            );
+            continue;
+        }
+        if run.dry_run {
+            info!("Would fetch {} to {}", entry.url, dest.display());
+            continue;
+        }
+        match downloader.fetch(&entry.url, &dest, &run.cancel) {
+            Ok(download) => {
+                info!(
+                    "Fetched {} ({})",
+                    dest.display(),
+                    format_size(download.size)
+                );
+                fetched += 1;
+            }
+            Err(e) => {
+                let cancelled = matches!(e.kind(), ErrorKind::Cancelled(_));
+                errors.push(e);
+                if cancelled {
+                    break;
+                }
+            }
+        }
+    }
+    info!(
+        "Fetched {fetched} of the {} charts listed",
+        list.entries.len()
+    );
+    errors.extend(scan.errors);
+    if !errors.is_empty() {
+        Report::log_errors(&errors.iter().collect::<Vec<_>>());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+///
+/// True if the quarantined file is a version of the named chart, matching either the bare name
+/// (`OK_Tulsa`) or the full identity (`OK_Tulsa[TM]`)
+fn is_chart(file: &QuarantinedFile, chart: &str) -> bool {
+    file.chart
+        .as_ref()
+        .is_some_and(|id| id.name() == chart || id.to_string() == chart)
+}
+
+fn run_quarantine(
+    command: &QuarantineCommand,
+    opts: &Options,
+    config: &Config,
+) -> Result<(), Error> {
+    // Held files are filed by the scanners of every profile quarantining into the directory
+    let mut dirs: BTreeMap<&PathBuf, Vec<Scanner>> = BTreeMap::new();
+    for profile in &config.profiles {
+        if let Some(dir) = &profile.quarantine {
+            let scanner = profile_scanner(profile, &Arc::default());
+            dirs.entry(dir).or_default().push(scanner);
+        }
+    }
+    if dirs.is_empty() {
+        return Err(Error::usage(
+            "No quarantine configured, use --quarantine DIR",
+        ));
+    }
+    for (dir, scanners) in dirs {
+        let quarantine = Quarantine::new(dir).with_scanners(scanners);
+        println!("{}:", dir.display());
+        match command {
+            QuarantineCommand::List => {
+                let entries = quarantine.entries()?;
+                let mut by_day: BTreeMap<Date, BTreeMap<String, (usize, u64)>> = BTreeMap::new();
+                for file in &entries {
+                    let chart = file
+                        .chart
+                        .as_ref()
+                        .map_or_else(|| "(unparsed)".to_string(), ToString::to_string);
+                    let counts = by_day
+                        .entry(file.day)
+                        .or_default()
+                        .entry(chart)
+                        .or_default();
+                    counts.0 += 1;
+                    counts.1 += file.size;
+                }
+                for (day, charts) in by_day {
+                    let files: usize = charts.values().map(|c| c.0).sum();
+                    let bytes: u64 = charts.values().map(|c| c.1).sum();
+                    println!("  {day}: {files} files, {}", format_size(bytes));
+                    for (chart, (files, bytes)) in charts {
+                        println!("    {chart}: {files} files, {}", format_size(bytes));
+                    }
+                }
+            }
+            QuarantineCommand::Show(chart) => {
+                for file in quarantine.entries()?.iter().filter(|f| is_chart(f, chart)) {
+                    println!(
+                        "  {}  {}  {}",
+                        file.day,
+                        file.original.display(),
+                        format_size(file.size)
+                    );
+                }
+            }
+            QuarantineCommand::Purge => {
+                let cutoff = opts.older_than.map(|age| {
+                    let days = (age.as_secs() / 86400).min(u16::MAX as u64) as u16;
+                    UTCDateTime::now().get_date().sub_days(days)
+                });
+                let report = quarantine.purge(
+                    |f| {
+                        cutoff.is_none_or(|cutoff| f.day < cutoff)
+                            && opts.chart.as_ref().is_none_or(|c| is_chart(f, c))
+                    },
+                    opts.dry_run,
+                )?;
+                let verb = if opts.dry_run {
+                    "Would purge"
+                } else {
+                    "Purged"
+                };
+                println!(
+                    "  {verb} {} files ({}) from {} days",
+                    report.files,
+                    format_size(report.bytes),
+                    report.days.len()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_store(command: &StoreCommand, opts: &Options, config: &Config) -> Result<(), Error> {
+    let mut dirs: Vec<&PathBuf> = config
+        .profiles
+        .iter()
+        .filter_map(|p| match &p.executor {
+            Some(ExecutorKind::Store(dir)) => Some(dir),
+            _ => None,
+        })
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    if dirs.is_empty() {
+        return Err(Error::usage(
+            "No store configured, use --executor store:DIR",
+        ));
+    }
+    match command {
+        StoreCommand::List => {
+            for dir in dirs {
+                let store = BlobStore::new(dir, Arc::default());
+                let entries = store.entries()?;
+                let mut blobs: BTreeMap<Digest, u64> = BTreeMap::new();
+                for file in &entries {
+                    blobs.insert(file.digest, file.size);
+                }
+                let stored: u64 = entries.iter().map(|f| f.size).sum();
+                let kept: u64 = blobs.values().sum();
+                println!(
+                    "{}: {} files ({}) in {} blobs ({})",
+                    dir.display(),
+                    entries.len(),
+                    format_size(stored),
+                    blobs.len(),
+                    format_size(kept)
+                );
+                for file in &entries {
+                    println!(
+                        "  {}  {}  {}",
+                        file.digest,
+                        format_size(file.size),
+                        file.original.display()
+                    );
+                }
+            }
+        }
+        StoreCommand::Restore(hash) => {
+            let Some(output) = &opts.output else {
+                return Err(Error::usage("store restore requires --output PATH"));
+            };
+            let mut found: Vec<(BlobStore, StoredFile)> = Vec::new();
+            for dir in dirs {
+                let store = BlobStore::new(dir, Arc::default());
+                for file in store.find(hash)? {
+                    if !found.iter().any(|(_, f)| f.digest == file.digest) {
+                        found.push((BlobStore::new(dir, Arc::default()), file));
+                    }
+                }
+            }
+            let [(store, file)] = found.as_slice() else {
+                let msg = match found.len() {
+                    0 => format!("Nothing stored with hash {hash}"),
+                    n => format!("{hash} matches {n} stored files, give more of the hash"),
+                };
+                return Err(Error::usage(msg));
+            };
+            let dest = if output.is_dir() {
+                output.join(file.original.file_name().unwrap_or_default())
+            } else {
+                output.clone()
+            };
+            let bytes = store.restore(&file.digest, &dest)?;
+            println!(
+                "Restored {} ({}) to {}",
+                file.original.display(),
+                format_size(bytes),
+                dest.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_cold(command: &ColdCommand, opts: &Options, config: &Config) -> Result<(), Error> {
+    let mut dirs: Vec<&PathBuf> = config
+        .profiles
+        .iter()
+        .filter_map(|p| match &p.executor {
+            Some(ExecutorKind::Cold(dir)) => Some(dir),
+            _ => None,
+        })
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    if dirs.is_empty() {
+        return Err(Error::usage(
+            "No cold tier configured, use --executor cold:DIR",
+        ));
+    }
+    match command {
+        ColdCommand::List => {
+            for dir in dirs {
+                let entries = ColdTier::new(dir, Arc::default()).entries()?;
+                let moved: u64 = entries.iter().map(|f| f.size).sum();
+                println!(
+                    "{}: {} files ({})",
+                    dir.display(),
+                    entries.len(),
+                    format_size(moved)
+                );
+                for file in &entries {
+                    println!("  {}  {}", format_size(file.size), file.original.display());
+                }
+            }
+        }
+        ColdCommand::Restore(path) => {
+            let Some(output) = &opts.output else {
+                return Err(Error::usage("cold restore requires --output PATH"));
+            };
+            let mut found: Vec<(ColdTier, TieredFile)> = Vec::new();
+            for dir in dirs {
+                for file in ColdTier::new(dir, Arc::default()).find(path)? {
+                    found.push((ColdTier::new(dir, Arc::default()), file));
+                }
+            }
+            let [(tier, file)] = found.as_slice() else {
+                let msg = match found.len() {
+                    0 => format!("Nothing moved to a cold tier from {}", path.display()),
+                    n => format!(
+                        "{} matches {n} files, give more of the path",
+                        path.display()
+                    ),
+                };
+                return Err(Error::usage(msg));
+            };
+            let dest = if output.is_dir() {
+                output.join(file.original.file_name().unwrap_or_default())
+            } else {
+                output.clone()
+            };
+            let bytes = tier.restore(file, &dest)?;
+            println!(
+                "Restored {} ({}) to {}",
+                file.original.display(),
+                format_size(bytes),
+                dest.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_plan(opts: &Options, config: &Config, run: &Run) -> Result<(), Error> {
+    let Some(output) = &opts.output else {
+        return Err(Error::usage("plan requires --output FILE"));
+    };
+    let [profile] = config.profiles.as_slice() else {
+        return Err(Error::usage(
+            "plan needs exactly one profile, use --profile NAME",
+        ));
+    };
+    if run.replay.is_none() {
+        std::fs::metadata(&profile.root).context(Phase::Setup, &profile.root)?;
     }
-    let path_str = path.display().to_string();
-    let base_path :Vec<&str> = path_str.split('/').next_back().unwrap().split('_').collect();
-    let base_path = base_path.split_at(base_path.len()-3).0.join("_");
-    let mut paths = path_str.split('_');
-    let _ext = paths.next_back();
-    let _tm = paths.next_back();
-    let Some(date) = paths.next_back() else {
-        error!("Error processing path: {path_str}");
+    // The manifest records a hash of nearly every file, so read them while the walk goes on
+    let hashers = std::thread::available_parallelism().map_or(1, usize::from);
+    let scanner = profile_scanner(profile, &run.throttle)
+        .with_hashers(hashers)
+        .with_planners(profile.planners);
+    let scan = scan_profile(scanner, profile, run)?;
+    if profile.fail_on_skipped && !scan.skipped.is_empty() {
+        let msg = format!("{} directories couldn't be read", scan.skipped.len());
+        return Err(Error::validate(msg));
+    }
+    let plan = CleanPlan::new(&scan, &profile_policy(profile)?)?;
+    if !plan.retired.is_empty() {
+        let retired = plan.retired.len();
+        warn!(
+            "{retired} files of withdrawn charts aren't in the manifest, retire them with a clean"
+        );
+    }
+    let mut manifest = Manifest::from_plan(&profile.name, &profile.root, &plan, unix_now())?;
+    manifest.provenance = profile.provenance.clone();
+    if let Some(key) = &config.signing_key {
+        manifest.sign(&SigningKey::load(key)?);
+    }
+    manifest.write(output)?;
+    info!(
+        "Wrote {} removals to {}",
+        manifest.removals().count(),
+        output.display()
+    );
+    let errors: Vec<&Error> = scan.errors.iter().collect();
+    Report::log_errors(&errors);
+    Ok(())
+}
+
+///
+/// The profile a manifest was planned for: the one of its name in the config, or without a
+/// config the one the command line describes, at the manifest's root unless one is given
+fn manifest_profile(
+    manifest: &Manifest,
+    opts: &Options,
+    config: &Config,
+) -> Result<Profile, Error> {
+    if opts.config.is_some() {
+        let Some(profile) = config.profiles.iter().find(|p| p.name == manifest.profile) else {
+            let msg = format!("Manifest profile {} isn't in the config", manifest.profile);
+            return Err(Error::usage(msg));
+        };
+        return Ok(profile.clone());
+    }
+    let mut profile = config
+        .profiles
+        .first()
+        .cloned()
+        .unwrap_or_else(|| Profile::new(&manifest.profile, &manifest.root));
+    profile.name.clone_from(&manifest.profile);
+    if opts.root.is_none() {
+        profile.root.clone_from(&manifest.root);
+    }
+    Ok(profile)
+}
+
+fn run_apply(manifest: &Path, opts: &Options, config: &Config, run: &Run) -> Result<(), Error> {
+    let manifest = Manifest::read(manifest)?;
+    let profile = manifest_profile(&manifest, opts, config)?;
+    if let Some(key) = &config.verifying_key {
+        if manifest.signature.is_some() || config.require_signed {
+            manifest.verify_signature(&VerifyingKey::load(key)?)?;
+        }
+    } else if config.require_signed {
+        return Err(Error::usage("--require-signed needs --verifying-key FILE"));
+    } else if manifest.signature.is_some() {
+        warn!("The manifest is signed, but can't be checked without a key");
+    }
+    let read_only = (!run.dry_run)
+        .then(|| read_only_reason(&profile.root))
+        .flatten();
+    if let Some(reason) = &read_only {
+        warn!("{reason}, checking the manifest without removing anything");
+    }
+    let dry_run = run.dry_run || read_only.is_some();
+    // Taken before the manifest is checked, so no other host changes the archive in between
+    let lock = match &profile.lock {
+        Some(path) if !dry_run => Some(RunLock::acquire(
+            path,
+            run.journal.run(),
+            profile.lock_stale_after,
+        )?),
+        _ => None,
+    };
+    info!("Validating manifest against {}", profile.root.display());
+    let (defects, conflicts) = manifest.check(&profile.root);
+    let abort = !conflicts.is_empty() && profile.on_conflict == ConflictPolicy::Abort;
+    if !defects.is_empty() || abort {
+        let conflicted = conflicts.iter().map(|c| &c.error);
+        let failed: Vec<&Error> = defects.iter().chain(conflicted).collect();
+        Report::log_errors(&failed);
+        error!("Manifest preconditions failed, nothing was removed.");
+        // Exiting skips the drop that unlocks
+        drop(lock);
+        std::process::exit(1);
+    }
+    for conflict in &conflicts {
+        warn!("Changed since the plan: {}", conflict.error);
+    }
+    let mut blocked = manifest.blocked(&conflicts);
+    // The charts to plan again, by the files of theirs the conflicts block
+    let mut replan = BTreeSet::new();
+    if profile.on_conflict == ConflictPolicy::Replan {
+        let scanner = profile_scanner(&profile, &run.throttle);
+        let chart = |path: &Path| scanner.parse_name(path).ok().map(|f| f.id().clone());
+        replan = blocked.iter().filter_map(|path| chart(path)).collect();
+        blocked.extend(
+            manifest
+                .removals()
+                .map(|e| e.path.as_path())
+                .filter(|path| chart(path).is_some_and(|id| replan.contains(&id))),
+        );
+    }
+
+    let mut report = ProfileReport::new(&profile.name);
+    report.provenance = profile.provenance.clone();
+    report.read_only = read_only;
+    let policy = profile_policy(&profile)?;
+    let cancel = run.cancel.with_timeout(profile.removal_timeout);
+    let mut removals: Vec<(PathBuf, Option<PathBuf>, u64)> = Vec::new();
+    for entry in manifest.removals() {
+        let path = profile.root.join(&entry.path);
+        if blocked.contains(entry.path.as_path()) {
+            report.skipped += 1;
+            report
+                .held_back
+                .add(SkipReason::Excluded, path, "changed since the plan");
+            continue;
+        }
+        // Protection may have been added since the plan was made
+        if policy.protected.as_ref().is_some_and(|p| p.contains(&path)) {
+            report.protected += 1;
+            let detail = "referenced by another application";
+            report.held_back.add(SkipReason::Protected, path, detail);
+            continue;
+        }
+        let by = entry.superseded_by.as_ref().map(|by| profile.root.join(by));
+        removals.push((path, by, entry.size));
+    }
+    // Held until the replanned removals are done, as they borrow from it
+    let scan;
+    if !replan.is_empty() {
+        info!(
+            "Replanning {} charts that changed since the plan",
+            replan.len()
+        );
+        scan = profile_scanner(&profile, &run.throttle)
+            .run(&run.cancel.with_timeout(profile.scan_timeout))?;
+        let plan = CleanPlan::new(&scan, &policy)?;
+        for removal in plan.remove.iter().filter(|r| replan.contains(r.file.id())) {
+            let by = removal.superseded_by.map(FoundFile::full_path);
+            removals.push((removal.file.full_path(), by, removal.file.size()));
+        }
+        report.replanned = replan.len();
+    }
+    let candidates: Vec<PathBuf> = removals.iter().map(|(path, _, _)| path.clone()).collect();
+    let locked = locked_files(&candidates);
+    if !profile.force_attrs {
+        warn_locked(&locked);
+        removals.retain(|(path, _, _)| !locked.contains_key(path));
+        report.locked = locked.len();
+        hold_back_locked(&mut report, &locked);
+    }
+    let executor = force_attrs(profile_executor(&profile, run, dry_run)?, &profile, locked);
+    let paths: Vec<PathBuf> = removals.iter().map(|(path, _, _)| path.clone()).collect();
+    let mut reclaimed = Reclaimed::new(&paths);
+    for (file, by, size) in removals {
+        if let Err(e) = cancel.check(Phase::Delete) {
+            report.errors.push(e);
+            break;
+        }
+        let (journal, name) = (&run.journal, &profile.name);
+        match execute_removal(
+            executor.as_ref(),
+            journal,
+            name,
+            &mut reclaimed,
+            &profile.root,
+            &file,
+            by.as_deref(),
+        ) {
+            Ok(_) => {
+                report.removed += 1;
+                report.removed_bytes += size;
+            }
+            Err(e) => report.errors.push(e),
+        }
+    }
+    if let Err(e) = executor.finish() {
+        report.errors.push(e);
+    }
+    report.reclaimed_bytes = reclaimed.bytes();
+    let report = Report {
+        run: Some(run.journal.run()),
+        dry_run,
+        profiles: vec![report],
+    };
+    report.log();
+    save_skipped(&report, &run.state_dir);
+    // Exiting skips the drop that unlocks
+    drop(lock);
+    let errors: Vec<&Error> = report.errors().collect();
+    if !errors.is_empty() {
+        Report::log_errors(&errors);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+///
+/// Prints how the manifest differs from a plan of its profile's archive made now, as a unified
+/// diff, colored on a terminal unless `NO_COLOR` is set.  Exits with 1 if there's a difference,
+/// as `diff` does, so a script can replan before applying.
+fn run_diff_plan(path: &Path, opts: &Options, config: &Config, run: &Run) -> Result<(), Error> {
+    let manifest = Manifest::read(path)?;
+    let profile = manifest_profile(&manifest, opts, config)?;
+    info!("Comparing the manifest against {}", profile.root.display());
+    let scanner = profile_scanner(&profile, &run.throttle);
+    let scan = scanner.run(&run.cancel.with_timeout(profile.scan_timeout))?;
+    let policy = profile_policy(&profile)?;
+    let plan = CleanPlan::new(&scan, &policy)?;
+    let diff = PlanDiff::new(&manifest, &profile.root, &scanner, &scan, &plan);
+    if diff.is_empty() {
+        info!("The plan still matches {}", profile.root.display());
         return Ok(());
+    }
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    print!("{}", diff.render(&manifest, path, &profile.root, color));
+    let (planned, now) = diff.counts();
+    warn!(
+        "{} charts changed since the plan: {planned} of its lines differ from {now} of a plan \
+         made now, plan again before applying",
+        diff.hunks.len()
+    );
+    std::process::exit(1);
+}
+
+///
+/// Makes a key pair, the signing key for whoever signs plans and the verifying key for the
+/// machines that apply them
+fn run_keygen(opts: &Options) -> Result<(), Error> {
+    let Some(output) = &opts.output else {
+        return Err(Error::usage("keygen requires --output FILE"));
     };
-    let date = BASIC_CALENDAR_DATE.try_from(date)?;
+    let mut public = output.clone().into_os_string();
+    public.push(".pub");
+    let public = PathBuf::from(public);
+    let key = SigningKey::generate()?;
+    key.save(output)?;
+    key.verifying_key().save(&public)?;
+    info!(
+        "Wrote the signing key to {} and the verifying key to {}",
+        output.display(),
+        public.display()
+    );
+    Ok(())
+}
+
+///
+/// Writes each profile's effective policy, as merged from its presets, the config file and the
+/// command line, to `--output` or stdout.  The file is a config of its own, one section per
+/// profile.
+fn run_policy(opts: &Options, config: &Config) -> Result<(), Error> {
+    let mut text = String::from("# charts-clean effective policy\n");
+    for profile in &config.profiles {
+        text.push_str(&format!("\n[profile {}]\n", profile.name));
+        text.push_str(&format!("root = {}\n", profile.root.display()));
+        text.push_str(&Preset::of(profile).to_string());
+    }
+    match &opts.output {
+        Some(path) => std::fs::write(path, text).context(Phase::Setup, path),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+///
+/// The exports to run: those in the config file (filtered by `--export`), or one built from the
+/// command line
+fn selected_exports(opts: &Options, config: &Config) -> Result<Vec<ExportProfile>, Error> {
+    if opts.config.is_none() {
+        if opts.output.is_none() && opts.volumes.is_empty() {
+            return Err(Error::usage("export requires --output DIR or --volume DIR"));
+        }
+        let mut export = ExportProfile::new("default", "default");
+        export.layout = opts.layout.unwrap_or_default();
+        export.dir = opts.output.clone().unwrap_or_default();
+        export.card_size = opts.card_size;
+        export.volumes.clone_from(&opts.volumes);
+        return Ok(vec![export]);
+    }
+    for name in &opts.only_exports {
+        if !config.exports.iter().any(|e| &e.name == name) {
+            return Err(Error::usage(format!("Unknown export: {name}")));
+        }
+    }
+    Ok(config
+        .exports
+        .iter()
+        .filter(|e| opts.only_exports.is_empty() || opts.only_exports.contains(&e.name))
+        .filter(|e| config.profiles.iter().any(|p| p.name == e.profile))
+        .cloned()
+        .collect())
+}
+
+fn run_export(opts: &Options, config: &Config, run: &Run) -> Result<(), Error> {
+    let exports = selected_exports(opts, config)?;
+    if exports.is_empty() {
+        return Err(Error::usage("No exports configured"));
+    }
+    let mut errors = Vec::new();
+    for export in exports {
+        let Some(profile) = config.profiles.iter().find(|p| p.name == export.profile) else {
+            continue;
+        };
+        std::fs::metadata(&profile.root).context(Phase::Setup, &profile.root)?;
+        let mut scan = profile_scanner(profile, &run.throttle)
+            .run(&run.cancel.with_timeout(profile.scan_timeout))?;
+        let report = Export::new(export.layout, &export.dir)
+            .with_card_size(export.card_size)
+            .with_volumes(export.volumes.clone())
+            .with_verify_copies(profile.verify_copies)
+            .with_provenance(profile.provenance.clone())
+            .with_throttle(run.throttle.clone())
+            .run(&scan, opts.dry_run);
+        let verb = if opts.dry_run { "Would copy" } else { "Copied" };
+        println!(
+            "{} ({} layout): {verb} {} files ({}), {} unchanged",
+            export.name,
+            export.layout,
+            report.copied,
+            format_size(report.copied_bytes),
+            report.unchanged
+        );
+        for card in &report.cards {
+            let cap = card
+                .cap
+                .map_or_else(String::new, |cap| format!(" of {}", format_size(cap)));
+            println!(
+                "  {}: {} files, {}{cap}",
+                card.dir.display(),
+                card.files,
+                format_size(card.bytes)
+            );
+        }
+        errors.append(&mut scan.errors);
+        errors.extend(report.errors);
+    }
+    if !errors.is_empty() {
+        Report::log_errors(&errors.iter().collect::<Vec<_>>());
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
-    let found_file = FoundFile {
-        path: base_path,
-        date,
-        full_path: path,
+fn run_analyze(opts: &Options, config: &Config, run: &Run) -> Result<(), Error> {
+    let mut errors = Vec::new();
+    for profile in &config.profiles {
+        if run.replay.is_none() {
+            std::fs::metadata(&profile.root).context(Phase::Setup, &profile.root)?;
+        }
+        let mut scan = scan_profile(profile_scanner(profile, &run.throttle), profile, run)?;
+        let histogram = AgeHistogram::new(opts.bucket, &scan.to_keep);
+        println!(
+            "{}: editions of {} kept charts by {}",
+            profile.name,
+            scan.to_keep.len(),
+            histogram.bucket
+        );
+        for line in histogram.render() {
+            println!("  {line}");
+        }
+        let families = FamilyReport::new(&CleanPlan::new(&scan, &profile_policy(profile)?)?);
+        let top = opts.top.unwrap_or(DEFAULT_TOP);
+        println!(
+            "{}: families with the most versions, before → after planning",
+            profile.name
+        );
+        for family in families.most_versions(top) {
+            println!("  {family}");
+        }
+        println!(
+            "{}: families taking the most space, before → after planning",
+            profile.name
+        );
+        for family in families.most_bytes(top) {
+            println!("  {family}");
+        }
+        let gaps = find_gaps(
+            scan.to_keep.iter().chain(&scan.to_remove),
+            profile.edition_interval,
+        );
+        if !gaps.is_empty() {
+            println!(
+                "{}: {} gaps between the versions of a chart",
+                profile.name,
+                gaps.len()
+            );
+            for gap in gaps {
+                println!("  {gap}");
+            }
+        }
+        errors.append(&mut std::mem::take(&mut scan.errors));
+    }
+    if !errors.is_empty() {
+        Report::log_errors(&errors.iter().collect::<Vec<_>>());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+///
+/// Lists the files in each profile's catalog that match the filters, as of the profile's last
+/// clean
+fn run_query(opts: &Options, config: &Config, state_dir: &Path) -> Result<(), Error> {
+    let chart = opts
+        .chart
+        .as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(Error::usage)?;
+    let query = CatalogQuery {
+        state: opts.state.clone(),
+        source: opts.source.clone(),
+        chart,
+        published_before: opts
+            .older_than
+            .map(|age| unix_now() as i64 - age.as_secs() as i64),
+        current_only: opts.current,
     };
+    for profile in &config.profiles {
+        let catalog = Catalog::load(&catalog_path(state_dir, profile))?;
+        if catalog.updated == 0 {
+            warn!(
+                "{} has no catalog yet, it's written by a clean",
+                profile.name
+            );
+            continue;
+        }
+        let (mut files, mut bytes) = (0, 0);
+        println!("{}:", profile.name);
+        if !catalog.provenance.is_empty() {
+            println!("  Charts {}", catalog.provenance);
+        }
+        for entry in catalog.query(&query) {
+            let held = if entry.current { "" } else { "  (held)" };
+            println!(
+                "  {}  {}  {}  {}{held}",
+                entry.version(),
+                entry.id,
+                format_size(entry.size),
+                catalog.root.join(&entry.path).display()
+            );
+            files += 1;
+            bytes += entry.size;
+        }
+        println!("  {files} files, {}", format_size(bytes));
+    }
+    Ok(())
+}
 
-    if to_keep.contains(&found_file) {
-        let old = to_keep.take(&found_file).unwrap();
-        if old.date < found_file.date {
-            debug!("Replacing existing {old} with {found_file}");
-            to_keep.insert(found_file);
-            to_remove.insert(old.full_path);
+fn run_audit(config: &Config, run: &Run) -> Result<(), Error> {
+    let (mut errors, mut drifted) = (Vec::new(), 0);
+    for profile in &config.profiles {
+        let Some(path) = &profile.baseline else {
+            let msg = format!(
+                "{} has no baseline to audit, set one with --baseline",
+                profile.name
+            );
+            return Err(Error::usage(msg));
+        };
+        let baseline = Baseline::load(path)?;
+        if baseline.is_empty() {
+            warn!(
+                "Nothing recorded in {}, run with --baseline first",
+                path.display()
+            );
+            continue;
+        }
+        std::fs::metadata(&profile.root).context(Phase::Setup, &profile.root)?;
+        let mut scan = profile_scanner(profile, &run.throttle)
+            .run(&run.cancel.with_timeout(profile.scan_timeout))?;
+        let db_path = checksum_db_path(&run.state_dir, profile);
+        let db = if db_path.exists() {
+            Some(ChecksumDb::load(db_path)?)
         } else {
-            debug!("Not replacing existing {old} with {found_file}");
-            to_remove.insert(found_file.full_path);
-            to_keep.insert(old);
+            None
+        };
+        let report = audit(&scan, &baseline, db.as_ref());
+        println!(
+            "{}: checked {} charts against {}, {} differences",
+            profile.name,
+            report.checked,
+            path.display(),
+            report.drift.len()
+        );
+        for drift in &report.drift {
+            println!("  {drift}");
         }
-    } else {
-        debug!("Found new file {found_file}");
-        to_keep.insert(found_file);
+        drifted += report.drift.len();
+        errors.append(&mut scan.errors);
+        errors.extend(report.errors);
     }
+    if !errors.is_empty() {
+        Report::log_errors(&errors.iter().collect::<Vec<_>>());
+    }
+    if drifted > 0 || !errors.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
 
+fn run_scrub(config: &Config, state_dir: &Path, throttle: &Throttle) -> Result<(), Error> {
+    let mut errors = Vec::new();
+    for profile in &config.profiles {
+        let path = checksum_db_path(state_dir, profile);
+        if !path.exists() {
+            warn!(
+                "No checksums recorded for {}, run with --checksums first",
+                profile.name
+            );
+            continue;
+        }
+        let mut db = ChecksumDb::load(path)?;
+        let report = db.scrub(throttle, unix_now());
+        db.save()?;
+        println!(
+            "{}: checked {} files ({}), {} updated, {} missing, {} corrupt",
+            profile.name,
+            report.checked,
+            format_size(report.checked_bytes),
+            report.updated,
+            report.missing,
+            report.corrupt
+        );
+        errors.extend(report.errors);
+    }
+    if !errors.is_empty() {
+        Report::log_errors(&errors.iter().collect::<Vec<_>>());
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-fn main() -> Result<(), Error> {
-    irox_log::init_console_from_env("CHARTS_LOG");
-    let path = "/chonko-1/chartdata/USGS-Topo/28-JAN-2023";
-    let dirs = std::fs::read_dir(path)?;
+///
+/// Sets up a run of the config: its state directory, journal, throttle and time budget
+fn start_run(opts: &Options, config: &Config, run_id: RunId, state_dir: PathBuf) -> Run {
+    let throttle = Arc::new(
+        Throttle::unlimited()
+            .with_max_requests_per_second(config.max_requests_per_second)
+            .with_max_bytes_per_second(config.max_bandwidth),
+    );
+    Run {
+        // Nothing in the archive or the state directory changes when replaying a recording
+        dry_run: opts.dry_run || opts.replay.is_some(),
+        allow_anomaly: opts.allow_anomaly,
+        journal: Journal::new(state_dir.join("journal.tsv"), run_id),
+        state_dir,
+        throttle,
+        cancel: CancelToken::new().with_timeout(config.max_runtime),
+        buffer_logs: opts.buffer_logs,
+        deadline: config.max_runtime.map(|budget| Instant::now() + budget),
+        record: opts.record.clone(),
+        replay: opts.replay.clone(),
+        #[cfg(feature = "chaos")]
+        chaos: opts.chaos,
+    }
+}
 
-    let mut to_keep: BTreeSet<FoundFile> = BTreeSet::new();
-    let mut to_remove: BTreeSet<PathBuf> = BTreeSet::new();
+///
+/// Cleans every profile of the config, logging the report and any errors the skip-list doesn't
+/// suppress.  Returns whether there were any.
+fn run_clean(config: &Config, run: &Run) -> Result<bool, Error> {
+    let mut skip_list = SkipList::load(run.state_dir.join("skip-list.tsv"))?;
+    let report = run_profiles(&config.profiles, run);
+    report.log();
+    // Like the directory cache, listed on a dry run too, but not for a replay
+    if run.replay.is_none() {
+        save_skipped(&report, &run.state_dir);
+    }
 
-    for dir in dirs {
-        let dir = dir?;
-        scan_dir_and_recurse(&dir, &mut to_keep, &mut to_remove)?;
+    let now = unix_now();
+    let mut reported = Vec::new();
+    for (profile, profile_report) in config.profiles.iter().zip(&report.profiles) {
+        reported.extend(skip_list.update(&profile.root, &profile_report.errors, now));
+    }
+    let suppressed = report.errors().count() - reported.len();
+    if suppressed > 0 {
+        info!("Suppressed {suppressed} errors from files in the skip-list.");
+    }
+    if skip_list.summary_due(now) {
+        warn!("Files that have been failing repeatedly:");
+        for (path, entry) in skip_list.repeated() {
+            warn!(
+                "  {} ({} failed {} times): {}",
+                path.display(),
+                entry.phase,
+                entry.count,
+                entry.reason
+            );
+        }
+        skip_list.mark_summarized(now);
+    }
+    // A replay's failures were recorded, they aren't new ones
+    if run.replay.is_none() {
+        skip_list.save()?;
     }
 
-    for file in &to_remove {
-        info!("Will remove {}", file.display());
-        std::fs::remove_file(&file)?;
+    if !reported.is_empty() {
+        Report::log_errors(&reported);
     }
-    info!("Found {} files to keep.", to_keep.len());
-    info!("Found {} files to remove.", to_remove.len());
+    Ok(!reported.is_empty())
+}
 
+///
+/// Cleans each tenant on its own schedule until the process is stopped, or once each with
+/// `--once`.  Every tenant has a thread of its own, so a slow archive doesn't hold up the
+/// others, and each clean's log is appended to `reports/RUN.log` in the tenant's state
+/// directory as well as written to stderr.
+fn run_serve(tenants: &Path, opts: &Options) -> Result<(), Error> {
+    let tenants = Tenant::load(tenants)?;
+    if tenants.is_empty() {
+        return Err(Error::usage(
+            "The tenants file has no [tenant NAME] sections",
+        ));
+    }
+    let state_dir = opts.state_dir.clone().unwrap_or_else(default_state_dir);
+    info!("Serving {} tenants", tenants.len());
+    std::thread::scope(|scope| {
+        for tenant in &tenants {
+            let state_dir = &state_dir;
+            let spawned = std::thread::Builder::new()
+                .name(tenant.name.clone())
+                .spawn_scoped(scope, move || loop {
+                    let started = Instant::now();
+                    serve_tenant(tenant, opts, state_dir);
+                    if opts.once {
+                        break;
+                    }
+                    let next = started + tenant.every;
+                    std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                });
+            if let Err(e) = spawned {
+                error!("Unable to start tenant {}: {e}", tenant.name);
+            }
+        }
+    });
+    Ok(())
+}
+
+///
+/// One clean of the tenant.  Failures are logged rather than returned, so they don't stop the
+/// tenant's next clean or any other tenant's.
+fn serve_tenant(tenant: &Tenant, opts: &Options, serve_state_dir: &Path) {
+    let config = match Config::load(&tenant.config) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Unable to load the config of tenant {}: {e}", tenant.name);
+            return;
+        }
+    };
+    let state_dir = tenant
+        .state_dir
+        .clone()
+        .or_else(|| config.state_dir.clone())
+        .unwrap_or_else(|| serve_state_dir.join("tenants").join(&tenant.name));
+    let run_id = RunId::new();
+    let run = start_run(opts, &config, run_id, state_dir);
+    let buffer = LogBuffer::default();
+    let log = LogContext::new(&tenant.name, Some(buffer.clone()))
+        .with_run(run_id)
+        .enter();
+    info!("Cleaning tenant {}", tenant.name);
+    if let Err(e) = run_clean(&config, &run) {
+        error!("Unable to clean tenant {}: {e}", tenant.name);
+    }
+    drop(log);
+    let report = run.state_dir.join("reports").join(format!("{run_id}.log"));
+    if let Err(e) = buffer.save(&report) {
+        error!("Unable to write the report of tenant {}: {e}", tenant.name);
+    }
+    buffer.flush();
+}
+
+fn main() -> Result<(), Error> {
+    let run_id = RunId::new();
+    init_logging("CHARTS_LOG", run_id);
+    let (command, opts) = parse_args()?;
+    if let Command::Serve(tenants) = &command {
+        return run_serve(tenants, &opts);
+    }
+    if let Command::Keygen = &command {
+        return run_keygen(&opts);
+    }
+    let config = opts.to_config()?;
+    if let Command::Quarantine(command) = &command {
+        return run_quarantine(command, &opts, &config);
+    }
+    if let Command::Store(command) = &command {
+        return run_store(command, &opts, &config);
+    }
+    if let Command::Cold(command) = &command {
+        return run_cold(command, &opts, &config);
+    }
+    let recorded = matches!(command, Command::Clean | Command::Plan | Command::Analyze);
+    if (opts.record.is_some() || opts.replay.is_some()) && !recorded {
+        return Err(Error::usage(
+            "--record and --replay only work with clean, plan and analyze",
+        ));
+    }
+    if opts.record.is_some() && opts.replay.is_some() {
+        return Err(Error::usage("--record can't be combined with --replay"));
+    }
+    let state_dir = config.state_dir.clone().unwrap_or_else(default_state_dir);
+    let run = start_run(&opts, &config, run_id, state_dir.clone());
+    match &command {
+        Command::Plan => return run_plan(&opts, &config, &run),
+        Command::Export => return run_export(&opts, &config, &run),
+        Command::Scrub => return run_scrub(&config, &state_dir, &run.throttle),
+        Command::Query => return run_query(&opts, &config, &state_dir),
+        Command::Analyze => return run_analyze(&opts, &config, &run),
+        Command::Audit => return run_audit(&config, &run),
+        Command::Policy => return run_policy(&opts, &config),
+        Command::Apply(manifest) => return run_apply(manifest, &opts, &config, &run),
+        Command::DiffPlan(manifest) => return run_diff_plan(manifest, &opts, &config, &run),
+        Command::Fetch(list) => return run_fetch(list, &opts, &config, &run),
+        _ => {}
+    }
+    if run_clean(&config, &run)? {
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -0,0 +1,517 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter, Write as _};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use irox_tools::sha1::SHA1;
+
+use crate::hash::hash_file;
+use crate::{
+    CleanPlan, Digest, Error, ErrorContext, ErrorKind, FoundFile, Phase, Provenance, Signature,
+    SigningKey, VerifyingKey,
+};
+
+const HEADER: &str = "# charts-clean manifest v1";
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ManifestAction {
+    /// A kept file a removal depends on, it must be intact before anything it supersedes goes
+    Keep,
+    Remove,
+}
+
+impl Display for ManifestAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestAction::Keep => write!(f, "keep"),
+            ManifestAction::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+///
+/// What `apply` does about files that changed after the manifest was made: a removal that no
+/// longer has its recorded size or hash, or a kept file that's gone or changed.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Remove nothing
+    #[default]
+    Abort,
+    /// Leave the removals that depend on a changed file, and carry out the rest
+    Skip,
+    /// Leave the removals of every chart with a changed file, and plan those charts again from a
+    /// fresh scan.  Those removals weren't reviewed with the manifest, signed or not.
+    Replan,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(ConflictPolicy::Abort),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "replan" => Ok(ConflictPolicy::Replan),
+            _ => Err(format!(
+                "Unknown conflict policy {s}, expected abort, skip or replan"
+            )),
+        }
+    }
+}
+
+impl Display for ConflictPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Abort => write!(f, "abort"),
+            ConflictPolicy::Skip => write!(f, "skip"),
+            ConflictPolicy::Replan => write!(f, "replan"),
+        }
+    }
+}
+
+///
+/// A file that isn't as its [`Manifest`] recorded it.
+#[derive(Debug)]
+pub struct Conflict {
+    /// The entry's path, relative to the root
+    pub path: PathBuf,
+    pub action: ManifestAction,
+    pub error: Error,
+}
+
+///
+/// A file recorded in a [`Manifest`], by its path relative to the scan root.
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    pub action: ManifestAction,
+    pub path: PathBuf,
+    pub size: u64,
+    pub digest: Digest,
+    /// For removals, the kept file that supersedes this one
+    pub superseded_by: Option<PathBuf>,
+}
+
+///
+/// A portable plan: generated against a scan on one machine, and applied (possibly on another,
+/// air-gapped machine with the archive mounted elsewhere) with [`Manifest::validate`] checking
+/// every precondition first.  Paths are relative to the scan root, and every file is recorded
+/// with its size and hash so the applying side can tell it's looking at the same bytes.
+///
+/// Stored as a tab separated file (shown here with spaces):
+/// ```text
+/// # charts-clean manifest v1
+/// #profile  usgs-topo
+/// #root  /chonko-1/chartdata/USGS-Topo
+/// #created  1700000000
+/// #signature  <ed25519 signature>
+/// #origin  USGS National Map
+/// #license  Public domain
+/// keep  OK/OK_Tulsa_20230126_TM_geo.pdf  52428800  <sha1>
+/// remove  OK/OK_Tulsa_20200101_TM_geo.pdf  51200000  <sha1>  OK/OK_Tulsa_20230126_TM_geo.pdf
+/// ```
+///
+/// A manifest can be signed with a [`SigningKey`] once it has been reviewed, so `apply` can
+/// refuse anything else, checking it with only the [`VerifyingKey`].  The signature covers a
+/// hash chain of every other line, in order.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    pub profile: String,
+    /// Where the archive was mounted when the manifest was generated
+    pub root: PathBuf,
+    pub created: u64,
+    /// Where the charts came from, recorded when the profile is tagged with it
+    pub provenance: Provenance,
+    pub entries: Vec<ManifestEntry>,
+    pub signature: Option<Signature>,
+}
+
+fn entry(
+    action: ManifestAction,
+    file: &FoundFile,
+    superseded_by: Option<&FoundFile>,
+) -> Result<ManifestEntry, Error> {
+    let digest = match file.digest() {
+        Some(digest) => digest,
+        None => {
+            let path = file.full_path();
+            hash_file(&path).context(Phase::Scan, &path)?
+        }
+    };
+    Ok(ManifestEntry {
+        action,
+        path: file.relative_path(),
+        size: file.size(),
+        digest,
+        superseded_by: superseded_by.map(FoundFile::relative_path),
+    })
+}
+
+fn manifest_error(msg: String) -> Error {
+    Error::new(ErrorKind::ManifestError(msg), Phase::Setup)
+}
+
+///
+/// A path of the manifest, which has to be relative to the root and stay inside it, so that
+/// applying a tampered or mangled manifest can't reach any file outside the archive
+fn archive_path(value: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(value);
+    let inside = path
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    (inside && !value.is_empty()).then_some(path)
+}
+
+impl Manifest {
+    ///
+    /// Builds a manifest of the plan's removals (in execution order), hashing each file along
+    /// with the kept file that supersedes it.  Withdrawn charts the plan retires aren't
+    /// included, as nothing supersedes them for `apply` to check.
+    pub fn from_plan(
+        profile: &str,
+        root: &Path,
+        plan: &CleanPlan,
+        created: u64,
+    ) -> Result<Manifest, Error> {
+        let mut manifest = Manifest {
+            profile: profile.to_string(),
+            root: root.to_path_buf(),
+            created,
+            provenance: Provenance::default(),
+            entries: Vec::new(),
+            signature: None,
+        };
+        let mut kept = Vec::new();
+        for removal in &plan.remove {
+            let keeper = removal.superseded_by;
+            if let Some(keeper) = keeper {
+                if !kept.contains(&keeper.full_path()) {
+                    kept.push(keeper.full_path());
+                    manifest
+                        .entries
+                        .push(entry(ManifestAction::Keep, keeper, None)?);
+                }
+            }
+            manifest
+                .entries
+                .push(entry(ManifestAction::Remove, removal.file, keeper)?);
+        }
+        Ok(manifest)
+    }
+
+    pub fn removals(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.action == ManifestAction::Remove)
+    }
+
+    ///
+    /// Every line of the manifest but the signature, in order
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            HEADER.to_string(),
+            format!("#profile\t{}", self.profile),
+            format!("#root\t{}", self.root.display()),
+            format!("#created\t{}", self.created),
+        ];
+        lines.extend(self.provenance.header_lines());
+        for e in &self.entries {
+            let mut line = format!(
+                "{}\t{}\t{}\t{}",
+                e.action,
+                e.path.display(),
+                e.size,
+                e.digest
+            );
+            if let Some(by) = &e.superseded_by {
+                let _ = write!(line, "\t{}", by.display());
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    ///
+    /// The hash chain of the manifest: each line hashed together with the hash of the lines
+    /// before it, so no line can be changed, dropped or reordered without changing the result
+    pub fn chain(&self) -> Digest {
+        self.lines().iter().fold(Digest::default(), |prev, line| {
+            let mut hasher = SHA1::default();
+            hasher.write(prev.as_bytes());
+            hasher.write(line.as_bytes());
+            Digest::from(hasher.finish())
+        })
+    }
+
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.signature = Some(key.sign(self.chain().as_bytes()));
+    }
+
+    ///
+    /// Checks the manifest is signed, and signed with this key's signing key
+    pub fn verify_signature(&self, key: &VerifyingKey) -> Result<(), Error> {
+        let Some(signature) = &self.signature else {
+            return Err(Error::validate("the manifest isn't signed"));
+        };
+        if !key.verify(self.chain().as_bytes(), signature) {
+            let msg = "bad signature, the manifest was changed or signed with another key";
+            return Err(Error::validate(msg));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut lines = self.lines();
+        if let Some(signature) = &self.signature {
+            lines.insert(4, format!("#signature\t{signature}"));
+        }
+        let mut out = String::new();
+        for line in lines {
+            let _ = writeln!(out, "{line}");
+        }
+        std::fs::write(path, out).context(Phase::Setup, path)
+    }
+
+    pub fn read(path: &Path) -> Result<Manifest, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        Manifest::parse(&text).map_err(|e| e.with_path(path))
+    }
+
+    pub fn parse(text: &str) -> Result<Manifest, Error> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, l)| l) != Some(HEADER) {
+            return Err(manifest_error("Not a charts-clean manifest".to_string()));
+        }
+        let mut manifest = Manifest::default();
+        for (idx, line) in lines {
+            let err = |msg: &str| manifest_error(format!("line {}: {msg}", idx + 1));
+            if let Some(header) = line.strip_prefix('#') {
+                match header.split_once('\t') {
+                    Some(("profile", value)) => manifest.profile = value.to_string(),
+                    Some(("root", value)) => manifest.root = PathBuf::from(value),
+                    Some(("created", value)) => {
+                        manifest.created = value.parse().map_err(|_| err("Invalid time"))?;
+                    }
+                    Some(("signature", value)) => {
+                        let signature = Signature::from_hex(value);
+                        manifest.signature =
+                            Some(signature.ok_or_else(|| err("Invalid signature"))?);
+                    }
+                    Some((key, value)) => {
+                        manifest.provenance.read_header(key, value);
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (action, path, size, digest, by) = match fields.as_slice() {
+                ["keep", path, size, digest] => (ManifestAction::Keep, path, size, digest, None),
+                ["remove", path, size, digest, by] => {
+                    (ManifestAction::Remove, path, size, digest, Some(by))
+                }
+                ["remove", path, size, digest] => {
+                    (ManifestAction::Remove, path, size, digest, None)
+                }
+                _ => return Err(err("Unrecognized entry")),
+            };
+            let outside = |path: &str| err(&format!("{path} isn't a path inside the root"));
+            let by = by.filter(|by| !by.is_empty());
+            let by = by
+                .map(|by| archive_path(by).ok_or_else(|| outside(by)))
+                .transpose()?;
+            manifest.entries.push(ManifestEntry {
+                action,
+                path: archive_path(path).ok_or_else(|| outside(path))?,
+                size: size.parse().map_err(|_| err("Invalid size"))?,
+                digest: Digest::from_hex(digest).ok_or_else(|| err("Invalid hash"))?,
+                superseded_by: by,
+            });
+        }
+        Ok(manifest)
+    }
+
+    ///
+    /// Checks every precondition of applying this manifest against the archive mounted at `root`:
+    /// each file must exist with the recorded size and hash, and every removal's superseding file
+    /// must be in the manifest and intact.  Returns every failure, not just the first.
+    pub fn validate(&self, root: &Path) -> Vec<Error> {
+        let (mut errors, conflicts) = self.check(root);
+        errors.extend(conflicts.into_iter().map(|c| c.error));
+        errors
+    }
+
+    ///
+    /// Checks the manifest as [`Manifest::validate`] does, telling apart the defects of the
+    /// manifest itself, which nothing can be applied past, from the files that changed since it
+    /// was made
+    pub fn check(&self, root: &Path) -> (Vec<Error>, Vec<Conflict>) {
+        let mut errors = Vec::new();
+        let mut conflicts = Vec::new();
+        for e in &self.entries {
+            let path = root.join(&e.path);
+            if let Err(error) = check_entry(e, &path) {
+                conflicts.push(Conflict {
+                    path: e.path.clone(),
+                    action: e.action,
+                    error,
+                });
+            }
+            if e.action != ManifestAction::Remove {
+                continue;
+            }
+            let Some(by) = &e.superseded_by else {
+                let msg = "removal isn't superseded by any file, it may be the only copy";
+                errors.push(Error::validate(msg).with_path(&path));
+                continue;
+            };
+            let removed = self
+                .entries
+                .iter()
+                .any(|r| r.action == ManifestAction::Remove && &r.path == by);
+            if by == &e.path || removed {
+                let msg = format!("superseding file {} is also removed", by.display());
+                errors.push(Error::validate(msg).with_path(&path));
+                continue;
+            }
+            let recorded = self
+                .entries
+                .iter()
+                .any(|k| k.action == ManifestAction::Keep && &k.path == by);
+            if !recorded {
+                let msg = format!("superseding file {} is not in the manifest", by.display());
+                errors.push(Error::validate(msg).with_path(&path));
+            }
+        }
+        (errors, conflicts)
+    }
+
+    ///
+    /// The removals the conflicts stand in the way of: those of a changed file, and those
+    /// superseded by one
+    pub fn blocked(&self, conflicts: &[Conflict]) -> BTreeSet<&Path> {
+        let changed: BTreeSet<&Path> = conflicts.iter().map(|c| c.path.as_path()).collect();
+        self.removals()
+            .filter(|e| {
+                changed.contains(e.path.as_path())
+                    || e.superseded_by
+                        .as_deref()
+                        .is_some_and(|by| changed.contains(by))
+            })
+            .map(|e| e.path.as_path())
+            .collect()
+    }
+}
+
+fn check_entry(entry: &ManifestEntry, path: &Path) -> Result<(), Error> {
+    let meta = std::fs::metadata(path).context(Phase::Validate, path)?;
+    if meta.len() != entry.size {
+        let msg = format!("expected {} bytes, found {}", entry.size, meta.len());
+        return Err(Error::validate(msg).with_path(path));
+    }
+    let digest = hash_file(path).context(Phase::Validate, path)?;
+    if digest != entry.digest {
+        let msg = format!("expected hash {}, found {digest}", entry.digest);
+        return Err(Error::validate(msg).with_path(path));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "89abcdef0123456789abcdef0123456789abcdef";
+
+    fn manifest() -> Manifest {
+        let entry = |action, path: &str, by: Option<&str>| ManifestEntry {
+            action,
+            path: PathBuf::from(path),
+            size: 100,
+            digest: Digest::from_hex(HASH).unwrap(),
+            superseded_by: by.map(PathBuf::from),
+        };
+        Manifest {
+            profile: "usgs-topo".to_string(),
+            root: PathBuf::from("/chonko-1/chartdata/USGS-Topo"),
+            created: 1_700_000_000,
+            entries: vec![
+                entry(
+                    ManifestAction::Keep,
+                    "OK/OK_Tulsa_20230126_TM_geo.pdf",
+                    None,
+                ),
+                entry(
+                    ManifestAction::Remove,
+                    "OK/OK_Tulsa_20200101_TM_geo.pdf",
+                    Some("OK/OK_Tulsa_20230126_TM_geo.pdf"),
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn text(manifest: &Manifest) -> String {
+        manifest.lines().join("\n")
+    }
+
+    #[test]
+    fn chain_covers_every_line_in_order() {
+        let manifest = manifest();
+        let chain = manifest.chain();
+        assert_eq!(chain, Manifest::parse(&text(&manifest)).unwrap().chain());
+
+        let mut resized = manifest.clone();
+        resized.entries[1].size = 99;
+        let mut dropped = manifest.clone();
+        dropped.entries.pop();
+        let mut reordered = manifest.clone();
+        reordered.entries.reverse();
+        let mut moved = manifest.clone();
+        moved.root = PathBuf::from("/mnt/USGS-Topo");
+        for changed in [resized, dropped, reordered, moved] {
+            assert_ne!(changed.chain(), chain);
+        }
+    }
+
+    #[test]
+    fn archive_paths_stay_inside_the_root() {
+        assert_eq!(
+            archive_path("OK/OK_Tulsa_20230126_TM_geo.pdf"),
+            Some(PathBuf::from("OK/OK_Tulsa_20230126_TM_geo.pdf"))
+        );
+        assert!(archive_path("./OK/OK_Tulsa_20230126_TM_geo.pdf").is_some());
+        for outside in [
+            "",
+            "/etc/passwd",
+            "../OK_Tulsa.pdf",
+            "OK/../../OK_Tulsa.pdf",
+        ] {
+            assert_eq!(archive_path(outside), None, "{outside}");
+        }
+    }
+
+    #[test]
+    fn rejects_entries_outside_the_root() {
+        let parse = |path: &str, by: &str| {
+            let text = format!("{HEADER}\nremove\t{path}\t100\t{HASH}\t{by}\n");
+            Manifest::parse(&text).map(|_| ()).unwrap_err().to_string()
+        };
+        let e = parse("../OK_Tulsa_20200101_TM_geo.pdf", "OK/OK_Tulsa.pdf");
+        assert!(e.contains("isn't a path inside the root"), "{e}");
+        let e = parse("OK/OK_Tulsa_20200101_TM_geo.pdf", "/OK/OK_Tulsa.pdf");
+        assert!(
+            e.contains("/OK/OK_Tulsa.pdf isn't a path inside the root"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn rejects_a_removal_superseded_by_nothing_kept() {
+        let mut manifest = manifest();
+        manifest.entries.remove(0);
+        let (errors, _) = manifest.check(Path::new("/nonexistent"));
+        assert_eq!(errors.len(), 1);
+        let e = errors[0].to_string();
+        assert!(e.contains("is not in the manifest"), "{e}");
+    }
+}
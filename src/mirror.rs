@@ -0,0 +1,206 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use irox_log::log::{debug, info};
+
+use crate::copy::copy_verified;
+use crate::hash::hash_file;
+use crate::quarantine::walk_files;
+use crate::{
+    ChartAliases, CleanPlan, Error, ErrorContext, FixedFields, FoundFile, Phase, Throttle,
+    UtcOffset,
+};
+
+///
+/// What was (or in a dry-run, would have been) changed in a mirror by [`Mirror::sync`].
+#[derive(Debug, Default)]
+pub struct MirrorReport {
+    pub copied: usize,
+    pub copied_bytes: u64,
+    /// Kept files already up to date in the mirror
+    pub unchanged: usize,
+    pub removed: usize,
+    pub removed_bytes: u64,
+    pub errors: Vec<Error>,
+}
+
+///
+/// A copy of the newest edition of every chart, kept under `<dir>/<path relative to the scan
+/// root>` (a plotter card, say).  Syncing only copies the kept files that are missing or differ
+/// from what's already there, and removes the superseded editions (and any retired charts) from
+/// the mirror.
+pub struct Mirror {
+    dir: PathBuf,
+    compare_hashes: bool,
+    source_tz: UtcOffset,
+    aliases: ChartAliases,
+    fixed_fields: Option<FixedFields>,
+    throttle: Arc<Throttle>,
+}
+
+impl Mirror {
+    pub fn new(dir: impl Into<PathBuf>) -> Mirror {
+        Mirror {
+            dir: dir.into(),
+            compare_hashes: false,
+            source_tz: UtcOffset::UTC,
+            aliases: ChartAliases::default(),
+            fixed_fields: None,
+            throttle: Arc::default(),
+        }
+    }
+
+    ///
+    /// Compare the contents of files that are the same size, rather than trusting the size alone.
+    /// Copies are hash-verified too.
+    #[must_use]
+    pub fn with_compare_hashes(mut self, compare_hashes: bool) -> Mirror {
+        self.compare_hashes = compare_hashes;
+        self
+    }
+
+    ///
+    /// Sets the timezone assumed for mirrored file names without a zone designator
+    #[must_use]
+    pub fn with_source_tz(mut self, source_tz: UtcOffset) -> Mirror {
+        self.source_tz = source_tz;
+        self
+    }
+
+    ///
+    /// Files mirrored charts under the identities the scan did
+    #[must_use]
+    pub fn with_aliases(mut self, aliases: ChartAliases) -> Mirror {
+        self.aliases = aliases;
+        self
+    }
+
+    ///
+    /// Reads mirrored names by the position of their fields, as the scan did
+    #[must_use]
+    pub fn with_fixed_fields(mut self, fixed_fields: Option<FixedFields>) -> Mirror {
+        self.fixed_fields = fixed_fields;
+        self
+    }
+
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Mirror {
+        self.throttle = throttle;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    ///
+    /// Brings the mirror up to date with the kept files of a plan.  Files in the mirror that
+    /// aren't charts, or are charts the plan didn't find, are left alone.
+    pub fn sync(&self, plan: &CleanPlan, dry_run: bool) -> MirrorReport {
+        let mut report = MirrorReport::default();
+        for kept in &plan.keep {
+            let src = kept.full_path();
+            let dest = self.destination(kept);
+            match self.is_current(kept, &dest) {
+                Ok(true) => {
+                    debug!("{} is up to date", dest.display());
+                    report.unchanged += 1;
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    report.errors.push(e);
+                    continue;
+                }
+            }
+            if dry_run {
+                info!("Would mirror {}", src.display());
+            } else {
+                info!("Mirroring {}", src.display());
+                if let Err(e) = copy_verified(&src, &dest, self.compare_hashes, &self.throttle) {
+                    report.errors.push(e);
+                    continue;
+                }
+            }
+            report.copied += 1;
+            report.copied_bytes += kept.size();
+        }
+        if let Err(e) = self.remove_superseded(plan, dry_run, &mut report) {
+            report.errors.push(e);
+        }
+        report
+    }
+
+    fn destination(&self, kept: &FoundFile) -> PathBuf {
+        self.dir.join(kept.relative_path())
+    }
+
+    fn is_current(&self, kept: &FoundFile, dest: &Path) -> Result<bool, Error> {
+        let meta = match std::fs::metadata(dest) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e).context(Phase::Scan, dest),
+        };
+        if meta.len() != kept.size() {
+            return Ok(false);
+        }
+        if !self.compare_hashes {
+            return Ok(true);
+        }
+        let src = kept.full_path();
+        let expected = hash_file(&src).context(Phase::Scan, &src)?;
+        let actual = hash_file(dest).context(Phase::Scan, dest)?;
+        Ok(expected == actual)
+    }
+
+    ///
+    /// Removes every chart in the mirror that's a version of a kept chart, but not the kept one,
+    /// or a version of a retired chart
+    fn remove_superseded(
+        &self,
+        plan: &CleanPlan,
+        dry_run: bool,
+        report: &mut MirrorReport,
+    ) -> Result<(), Error> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+        let kept: BTreeSet<&FoundFile> = plan.keep.iter().copied().collect();
+        // Every file of a kept bundle has its chart, so which are kept goes by path
+        let kept_paths: BTreeSet<PathBuf> = plan.keep.iter().map(|k| self.destination(k)).collect();
+        let retired: BTreeSet<&FoundFile> = plan.retired.iter().copied().collect();
+        let mut files = Vec::new();
+        walk_files(&self.dir, &mut files)?;
+        files.sort();
+        for (path, size) in files {
+            let found = match &self.fixed_fields {
+                Some(fields) => fields.parse(&path, self.source_tz),
+                None => FoundFile::parse(path.clone(), self.source_tz),
+            };
+            let Ok(found) = found else {
+                continue;
+            };
+            let found = self.aliases.file(found);
+            match kept.get(&found) {
+                Some(_) if kept_paths.contains(&path) => continue,
+                Some(_) => {}
+                None if retired.contains(&found) => {}
+                None => continue,
+            }
+            if dry_run {
+                info!("Would remove {} from the mirror", path.display());
+            } else {
+                info!("Removing {} from the mirror", path.display());
+                self.throttle.request();
+                if let Err(e) = std::fs::remove_file(&path).context(Phase::Delete, &path) {
+                    report.errors.push(e);
+                    continue;
+                }
+            }
+            report.removed += 1;
+            report.removed_bytes += size;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs::OpenOptions;
+use std::path::Path;
+
+///
+/// Why files under the root can't be removed, if they can't: the filesystem it's on is mounted
+/// read-only, or writes to it are refused, ie by a share exported read-only or credentials that
+/// only allow reading.  Checked before a profile is cleaned, so that a read-only archive is
+/// planned and reported on rather than failing on its first removal.
+///
+/// The mount options are read from `/proc/self/mounts` on Linux.  Everywhere, a probe file is
+/// created in the root and removed again, which catches what the mount options don't say.
+pub fn read_only_reason(root: &Path) -> Option<String> {
+    if let Some(mount) = read_only_mount(root) {
+        return Some(format!(
+            "{} is on {mount}, which is mounted read-only",
+            root.display()
+        ));
+    }
+    let probe = root.join(format!(".charts-clean-probe-{}", std::process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            None
+        }
+        Err(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::ReadOnlyFilesystem | std::io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            Some(format!("{} can't be written to: {e}", root.display()))
+        }
+        Err(_) => None,
+    }
+}
+
+///
+/// The mount point of the read-only filesystem the path is on, if it's on one
+#[cfg(target_os = "linux")]
+fn read_only_mount(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    // `DEVICE MOUNT_POINT TYPE OPTIONS DUMP PASS`, the innermost mount containing the path wins
+    let (mount, options) = mounts
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            Some((unescape(fields.get(1)?), *fields.get(3)?))
+        })
+        .filter(|(mount, _)| path.starts_with(mount))
+        .max_by_key(|(mount, _)| mount.len())?;
+    options
+        .split(',')
+        .any(|option| option == "ro")
+        .then_some(mount)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_only_mount(_path: &Path) -> Option<String> {
+    None
+}
+
+///
+/// Undoes the octal escapes of spaces, tabs and backslashes in a mount point, ie `\040`
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(idx) = rest.find('\\') {
+        out.push_str(&rest[..idx]);
+        let code = rest
+            .get(idx + 1..idx + 4)
+            .and_then(|o| u8::from_str_radix(o, 8).ok());
+        match code {
+            Some(code) => {
+                out.push(char::from(code));
+                rest = &rest[idx + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,920 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use irox_log::log::info;
+use irox_time::epoch::UnixTimestamp;
+
+use crate::{
+    Baseline, CancelToken, CheckpointEntry, Decision, Error, Event, Executor, FileAttr, FoundFile,
+    Journal, Phase, Progress, ProtectedFiles, Reclaimed, RemovalPriority, Scan, SizeRange,
+    WithdrawnAction, WithdrawnList,
+};
+
+///
+/// The rules a plan is made by.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub priority: RemovalPriority,
+    /// Removals past this many files, companions included, are deferred to a later run.  A file
+    /// and its companions are removed or deferred together, never split by the cap.
+    pub max_deletions: Option<usize>,
+    /// Hold back the removals of any chart whose kept edition is older than this baseline's
+    pub baseline: Option<Arc<Baseline>>,
+    /// Charts their publisher has withdrawn
+    pub withdrawn: Option<Arc<WithdrawnList>>,
+    pub withdrawn_action: WithdrawnAction,
+    /// Files other applications reference, never removed
+    pub protected: Option<Arc<ProtectedFiles>>,
+    /// The sizes files of each type are expected to be
+    pub size_ranges: Vec<SizeRange>,
+    /// Only remove editions published before this, seconds since the epoch, leaving newer ones
+    /// where they are until they're old enough, ie to move them to a cold tier
+    pub removable_before: Option<i64>,
+}
+
+impl Policy {
+    #[must_use]
+    pub fn with_priority(mut self, priority: RemovalPriority) -> Policy {
+        self.priority = priority;
+        self
+    }
+
+    #[must_use]
+    pub fn with_max_deletions(mut self, max_deletions: Option<usize>) -> Policy {
+        self.max_deletions = max_deletions;
+        self
+    }
+
+    #[must_use]
+    pub fn with_baseline(mut self, baseline: Option<Arc<Baseline>>) -> Policy {
+        self.baseline = baseline;
+        self
+    }
+
+    #[must_use]
+    pub fn with_withdrawn(
+        mut self,
+        withdrawn: Option<Arc<WithdrawnList>>,
+        action: WithdrawnAction,
+    ) -> Policy {
+        self.withdrawn = withdrawn;
+        self.withdrawn_action = action;
+        self
+    }
+
+    #[must_use]
+    pub fn with_size_ranges(mut self, size_ranges: Vec<SizeRange>) -> Policy {
+        self.size_ranges = size_ranges;
+        self
+    }
+
+    #[must_use]
+    pub fn with_removable_before(mut self, removable_before: Option<i64>) -> Policy {
+        self.removable_before = removable_before;
+        self
+    }
+
+    #[must_use]
+    pub fn with_protected(mut self, protected: Option<Arc<ProtectedFiles>>) -> Policy {
+        self.protected = protected;
+        self
+    }
+}
+
+///
+/// A superseded file to remove, and the kept file that supersedes it.
+#[derive(Debug, Clone, Copy)]
+pub struct Removal<'a> {
+    pub file: &'a FoundFile,
+    pub superseded_by: Option<&'a FoundFile>,
+}
+
+///
+/// What to do with every file of a scan.  Making a plan is pure, it touches nothing on disk, so
+/// the same scan and policy always give the same plan.  Carrying it out is left to [`execute`].
+///
+/// Whatever the policy, a plan never removes the only copy of a chart: every removal must be
+/// superseded by a kept file, of the same chart and at least as new, at another path.  See
+/// [`CleanPlan::check`].  The one exception is charts withdrawn by their publisher, which are
+/// only ever retired, every version at once, when the policy asks for it.  Files another
+/// application references are never removed at all, see [`ProtectedFiles`].
+///
+/// The companions of a file, see [`crate::CompanionRule`], follow it into whichever of the
+/// plan's lists it's in, straight after it.  If any file of the bundle is protected or locked,
+/// all of it is.
+#[derive(Debug, Default)]
+pub struct CleanPlan<'a> {
+    pub keep: Vec<&'a FoundFile>,
+    /// In execution order
+    pub remove: Vec<Removal<'a>>,
+    /// Removal candidates left for a later run by the deletion cap
+    pub deferred: Vec<&'a FoundFile>,
+    /// Kept files older than the baseline edition of their chart
+    pub downgraded: Vec<&'a FoundFile>,
+    /// Removal candidates held back because their chart is downgraded
+    pub held: Vec<&'a FoundFile>,
+    /// Removal candidates left where they are until they're older than the policy's
+    /// `removable_before`
+    pub aging: Vec<&'a FoundFile>,
+    /// The newest files of withdrawn charts, whether they're kept or retired
+    pub withdrawn: Vec<&'a FoundFile>,
+    /// Every file of the withdrawn charts, removed after the superseded ones
+    pub retired: Vec<&'a FoundFile>,
+    /// Removal candidates, superseded or retired, held back because another application
+    /// references them
+    pub protected: Vec<&'a FoundFile>,
+    /// Kept files outside the size range expected of their type, ie truncated downloads
+    pub suspect: Vec<&'a FoundFile>,
+    /// The newest removal candidate of each suspect chart, kept in case the suspect is bad
+    pub retained: Vec<&'a FoundFile>,
+    /// Removals skipped because file attributes stop the file being removed, see
+    /// [`CleanPlan::skip_locked`]
+    pub locked: Vec<&'a FoundFile>,
+    /// The primary of each companion in the plan, by their full paths
+    pub primaries: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl<'a> CleanPlan<'a> {
+    ///
+    /// Plans the scan's removals by the policy, failing if the plan would break the guarantee
+    /// that every chart keeps a copy
+    pub fn new(scan: &'a Scan, policy: &Policy) -> Result<CleanPlan<'a>, Error> {
+        let companions =
+            |f: &'a FoundFile| scan.companions.get(&f.full_path()).into_iter().flatten();
+        let bundle = |f: &'a FoundFile| std::iter::once(f).chain(companions(f));
+        let bundles = |files: Vec<&'a FoundFile>| files.into_iter().flat_map(bundle).collect();
+        let downgraded: Vec<&FoundFile> = match &policy.baseline {
+            Some(baseline) => {
+                let older = |f: &&FoundFile| baseline.newer_than(f).is_some();
+                scan.to_keep.iter().filter(older).collect()
+            }
+            None => Vec::new(),
+        };
+        let withdrawn: Vec<&FoundFile> = match &policy.withdrawn {
+            Some(list) => scan
+                .to_keep
+                .iter()
+                .filter(|f| list.get(f.id()).is_some())
+                .collect(),
+            None => Vec::new(),
+        };
+        let retired_ids: BTreeSet<_> = match policy.withdrawn_action {
+            WithdrawnAction::Remove => withdrawn.iter().map(|f| f.id()).collect(),
+            WithdrawnAction::Flag => BTreeSet::new(),
+        };
+        let (mut retired, to_remove): (Vec<&FoundFile>, Vec<&FoundFile>) = scan
+            .to_remove
+            .iter()
+            .partition(|f| retired_ids.contains(f.id()));
+        let (retired_keep, keep): (Vec<&FoundFile>, Vec<&FoundFile>) = scan
+            .to_keep
+            .iter()
+            .partition(|f| retired_ids.contains(f.id()));
+        retired.extend(retired_keep);
+        let protects = |f: &&'a FoundFile| {
+            policy
+                .protected
+                .as_ref()
+                .is_some_and(|p| bundle(f).any(|f| p.protects(f)))
+        };
+        let (mut protected, retired): (Vec<&FoundFile>, Vec<&FoundFile>) =
+            retired.into_iter().partition(protects);
+        let (referenced, to_remove): (Vec<&FoundFile>, Vec<&FoundFile>) =
+            to_remove.into_iter().partition(protects);
+        protected.extend(referenced);
+        protected.sort_by(|a, b| a.cmp_path(b));
+        let protected = bundles(protected);
+        let suspect: Vec<&FoundFile> = keep
+            .iter()
+            .copied()
+            .filter(|f| SizeRange::violated(&policy.size_ranges, f).is_some())
+            .collect();
+        let mut retained: Vec<&FoundFile> = suspect
+            .iter()
+            .filter_map(|kept| {
+                let older = to_remove.iter().filter(|f| f.id() == kept.id());
+                older.max_by(|a, b| a.version().cmp(b.version()).then_with(|| b.cmp_path(a)))
+            })
+            .copied()
+            .collect();
+        retained.sort_by(|a, b| a.cmp_path(b));
+        let to_remove: Vec<&FoundFile> = to_remove
+            .into_iter()
+            .filter(|f| !retained.iter().any(|r| std::ptr::eq(*r, *f)))
+            .collect();
+        let held_ids: BTreeSet<_> = downgraded.iter().map(|f| f.id()).collect();
+        let (held, candidates): (Vec<&FoundFile>, Vec<&FoundFile>) = to_remove
+            .into_iter()
+            .partition(|f| held_ids.contains(f.id()));
+        let published = |f: &FoundFile| {
+            UnixTimestamp::from(f.version().timestamp())
+                .get_offset()
+                .as_seconds_f64()
+        };
+        let (aging, mut candidates): (Vec<&FoundFile>, Vec<&FoundFile>) =
+            candidates.into_iter().partition(|f| {
+                policy
+                    .removable_before
+                    .is_some_and(|before| published(f) >= before as f64)
+            });
+        policy.priority.sort(&mut candidates);
+        let cap = policy.max_deletions.unwrap_or(usize::MAX);
+        let mut files = 0;
+        let fits = candidates
+            .iter()
+            .take_while(|f| {
+                files += bundle(f).count();
+                files <= cap
+            })
+            .count();
+        let deferred = candidates.split_off(fits);
+        let primaries = scan
+            .companions
+            .iter()
+            .flat_map(|(primary, files)| files.iter().map(|f| (f.full_path(), primary.clone())))
+            .collect();
+        let plan = CleanPlan {
+            keep: bundles(keep),
+            // A companion has its primary's chart and version, so it's superseded by its keeper
+            remove: candidates
+                .into_iter()
+                .flat_map(bundle)
+                .map(|file| Removal {
+                    file,
+                    superseded_by: scan.to_keep.get(file),
+                })
+                .collect(),
+            deferred: bundles(deferred),
+            downgraded,
+            held: bundles(held),
+            aging: bundles(aging),
+            withdrawn,
+            retired: bundles(retired),
+            protected,
+            suspect,
+            retained: bundles(retained),
+            locked: Vec::new(),
+            primaries,
+        };
+        plan.check()?;
+        Ok(plan)
+    }
+
+    ///
+    /// Checks that every removal is superseded by a kept copy: a file of the same chart, at least
+    /// as new, at another path, that the plan keeps
+    pub fn check(&self) -> Result<(), Error> {
+        let kept: BTreeSet<PathBuf> = self.keep.iter().map(|f| f.full_path()).collect();
+        for removal in &self.remove {
+            let path = &removal.file.full_path();
+            let msg = match removal.superseded_by {
+                _ if kept.contains(path) => "is planned to be both kept and removed".to_string(),
+                None => format!("{} would remove the only copy", removal.file.id()),
+                Some(keeper) if !kept.contains(&keeper.full_path()) => {
+                    let by = keeper.full_path();
+                    format!("superseding file {} isn't kept", by.display())
+                }
+                Some(keeper) if keeper.id() != removal.file.id() => {
+                    format!("superseded by {}, a different chart", keeper.id())
+                }
+                Some(keeper) if keeper.version() < removal.file.version() => {
+                    let by = keeper.full_path();
+                    format!("superseded by an older version, {}", by.display())
+                }
+                Some(_) => continue,
+            };
+            return Err(Error::validate(msg).with_path(path));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Moves the removals and retirements of the locked files, ie those [`crate::locked_files`]
+    /// found, to [`CleanPlan::locked`], along with the rest of their bundles.  Finding them means
+    /// reading the disk, so it's left to the caller rather than done in [`CleanPlan::new`].
+    pub fn skip_locked(&mut self, locked: &BTreeMap<PathBuf, Vec<FileAttr>>) {
+        let primaries = &self.primaries;
+        let bundles: BTreeSet<PathBuf> = locked.keys().map(|p| bundle_of(primaries, p)).collect();
+        let is_locked =
+            |file: &FoundFile| bundles.contains(&bundle_of(primaries, &file.full_path()));
+        let (skipped, remove) = std::mem::take(&mut self.remove)
+            .into_iter()
+            .partition::<Vec<_>, _>(|r| is_locked(r.file));
+        self.remove = remove;
+        self.locked.extend(skipped.into_iter().map(|r| r.file));
+        let (skipped, retired) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition::<Vec<_>, _>(|f| is_locked(f));
+        self.retired = retired;
+        self.locked.extend(skipped);
+    }
+
+    ///
+    /// Orders the removals and retirements largest first, so a run that runs out of time has
+    /// freed as much as it could
+    pub fn by_reclaim(&mut self) {
+        // By the size of the whole bundle, keeping its files together
+        let primaries = &self.primaries;
+        let mut sizes: BTreeMap<PathBuf, u64> = BTreeMap::new();
+        let files = self
+            .remove
+            .iter()
+            .map(|r| r.file)
+            .chain(self.retired.iter().copied());
+        for file in files {
+            *sizes
+                .entry(bundle_of(primaries, &file.full_path()))
+                .or_default() += file.size();
+        }
+        let key = |file: &FoundFile| {
+            let bundle = bundle_of(primaries, &file.full_path());
+            (std::cmp::Reverse(sizes[&bundle]), bundle)
+        };
+        self.remove.sort_by_cached_key(|r| key(r.file));
+        self.retired.sort_by_cached_key(|f| key(f));
+    }
+
+    pub fn removed_bytes(&self) -> u64 {
+        self.remove.iter().map(|r| r.file.size()).sum()
+    }
+
+    pub fn retired_bytes(&self) -> u64 {
+        self.retired.iter().map(|f| f.size()).sum()
+    }
+
+    ///
+    /// Reports the decision about every file of the plan
+    pub fn notify(&self, progress: &dyn Progress) {
+        let keep = self.keep.iter().map(|f| (*f, Decision::Keep));
+        let remove = self.remove.iter().map(|r| (r.file, Decision::Remove));
+        let defer = self.deferred.iter().map(|f| (*f, Decision::Defer));
+        let hold = self.held.iter().map(|f| (*f, Decision::Hold));
+        let aging = self.aging.iter().map(|f| (*f, Decision::Aging));
+        let retire = self.retired.iter().map(|f| (*f, Decision::Retire));
+        let protect = self.protected.iter().map(|f| (*f, Decision::Protect));
+        let retain = self.retained.iter().map(|f| (*f, Decision::Retain));
+        let lock = self.locked.iter().map(|f| (*f, Decision::Locked));
+        let decisions = keep
+            .chain(remove)
+            .chain(defer)
+            .chain(hold)
+            .chain(aging)
+            .chain(retire);
+        for (file, decision) in decisions.chain(protect).chain(retain).chain(lock) {
+            progress.event(&Event::Decided { file, decision });
+        }
+    }
+}
+
+///
+/// The full path of the primary of the file's bundle, the file's own if it's not a companion
+fn bundle_of(primaries: &BTreeMap<PathBuf, PathBuf>, path: &Path) -> PathBuf {
+    primaries
+        .get(path)
+        .cloned()
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+///
+/// Logs each removal without touching anything, the executor of a dry-run.
+#[derive(Default)]
+pub struct DryRunExecutor;
+
+impl Executor for DryRunExecutor {
+    fn name(&self) -> &'static str {
+        "dry-run"
+    }
+
+    fn changes_files(&self) -> bool {
+        false
+    }
+
+    fn remove(&self, _root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        info!("Would remove {}", path.display());
+        Ok(None)
+    }
+}
+
+///
+/// The outcome of executing a plan.
+#[derive(Debug, Default)]
+pub struct Execution {
+    pub removed: usize,
+    pub removed_bytes: u64,
+    /// The disk space the removals gave back, see [`Reclaimed`]
+    pub reclaimed_bytes: u64,
+    /// The removals left undone when the token was cancelled, in order
+    pub pending: Vec<CheckpointEntry>,
+    pub errors: Vec<Error>,
+}
+
+///
+/// Removes one file under `root`, superseded by the file at `by`, with the executor.  The change
+/// is journaled under the profile's name if the executor made one, and the space it gave back is
+/// counted in `reclaimed` once the file is actually gone.  Returns where the file went, if
+/// anywhere.
+pub fn execute_removal(
+    executor: &dyn Executor,
+    journal: &Journal,
+    profile: &str,
+    reclaimed: &mut Reclaimed,
+    root: &Path,
+    path: &Path,
+    by: Option<&Path>,
+) -> Result<Option<PathBuf>, Error> {
+    let allocation = reclaimed.measure(path);
+    let dest = executor.remove_superseded(root, path, by)?;
+    if executor.changes_files() {
+        if std::fs::symlink_metadata(path).is_err() {
+            reclaimed.removed(allocation);
+        }
+        journal.record(profile, executor.name(), path, dest.as_deref())?;
+    }
+    Ok(dest)
+}
+
+///
+/// Carries out the removals of a plan over `root` in order, then retires its withdrawn charts and
+/// finishes the executor.  If the token is cancelled the remaining removals are skipped.  A plan
+/// that fails its [`CleanPlan::check`] isn't executed at all.
+pub fn execute(
+    plan: &CleanPlan,
+    root: &Path,
+    executor: &dyn Executor,
+    journal: &Journal,
+    profile: &str,
+    cancel: &CancelToken,
+    progress: &dyn Progress,
+) -> Execution {
+    let mut execution = Execution::default();
+    if let Err(e) = plan.check() {
+        execution.errors.push(e);
+        return execution;
+    }
+    let total = plan.remove.len() + plan.retired.len();
+    let removals = plan.remove.iter().map(|r| (r.file, r.superseded_by));
+    let retirements = plan.retired.iter().map(|f| (*f, None));
+    let removals: Vec<_> = removals.chain(retirements).collect();
+    let paths: Vec<PathBuf> = removals.iter().map(|(file, _)| file.full_path()).collect();
+    let mut reclaimed = Reclaimed::new(&paths);
+    for (idx, ((file, by), path)) in removals.iter().zip(&paths).enumerate() {
+        if let Err(e) = cancel.check(Phase::Delete) {
+            execution.errors.push(e);
+            execution.pending = removals[idx..]
+                .iter()
+                .map(|(file, by)| CheckpointEntry {
+                    path: file.relative_path(),
+                    size: file.size(),
+                    superseded_by: by.map(FoundFile::relative_path),
+                })
+                .collect();
+            break;
+        }
+        let by = by.map(FoundFile::full_path);
+        let removed = execute_removal(
+            executor,
+            journal,
+            profile,
+            &mut reclaimed,
+            root,
+            path,
+            by.as_deref(),
+        );
+        match removed {
+            Ok(dest) => {
+                execution.removed += 1;
+                execution.removed_bytes += file.size();
+                progress.event(&Event::Removed {
+                    path,
+                    dest: dest.as_deref(),
+                    done: execution.removed,
+                    total,
+                });
+            }
+            Err(e) => execution.errors.push(e),
+        }
+    }
+    if let Err(e) = executor.finish() {
+        execution.errors.push(e);
+    }
+    execution.reclaimed_bytes = reclaimed.bytes();
+    execution
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::{DeleteExecutor, DryRunExecutor, MarkerExecutor, RunId, UtcOffset};
+
+    /// 2021-01-01, seconds since the epoch
+    const JAN_2021: i64 = 1_609_459_200;
+
+    fn file(path: &str, size: u64) -> FoundFile {
+        FoundFile::parse(PathBuf::from(path), UtcOffset::UTC)
+            .unwrap()
+            .with_size(size)
+    }
+
+    ///
+    /// A scan of the files, keeping the newest of each chart as a scan does.  Only the first file
+    /// at each path is found.
+    fn scan(files: Vec<FoundFile>) -> Scan {
+        let mut scan = Scan::default();
+        let mut paths = BTreeSet::new();
+        for file in files.into_iter().filter(|f| paths.insert(f.full_path())) {
+            let mut found = Scan::default();
+            found.to_keep.insert(file);
+            scan.merge(found);
+        }
+        scan
+    }
+
+    fn names(files: &[&FoundFile]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.relative_path().display().to_string())
+            .collect()
+    }
+
+    fn removed(plan: &CleanPlan) -> Vec<String> {
+        names(&plan.remove.iter().map(|r| r.file).collect::<Vec<_>>())
+    }
+
+    fn tulsa() -> Scan {
+        scan(vec![
+            file("a/OK_Tulsa_20190126_TM_geo.pdf", 300),
+            file("a/OK_Tulsa_20210126_TM_geo.pdf", 100),
+            file("a/OK_Tulsa_20230126_TM_geo.pdf", 200),
+            file("b/TX_Waco_20200101_TM_geo.pdf", 500),
+            file("b/TX_Waco_20220101_TM_geo.pdf", 400),
+        ])
+    }
+
+    #[test]
+    fn keeps_the_newest_of_each_chart() {
+        let scan = tulsa();
+        let plan = CleanPlan::new(&scan, &Policy::default()).unwrap();
+        let mut keep = names(&plan.keep);
+        keep.sort();
+        assert_eq!(
+            keep,
+            [
+                "a/OK_Tulsa_20230126_TM_geo.pdf",
+                "b/TX_Waco_20220101_TM_geo.pdf"
+            ]
+        );
+        assert_eq!(plan.remove.len(), 3);
+        for removal in &plan.remove {
+            let keeper = removal.superseded_by.unwrap();
+            assert_eq!(keeper.id(), removal.file.id());
+            assert!(keeper.version() > removal.file.version());
+        }
+        assert!(plan.deferred.is_empty() && plan.held.is_empty() && plan.aging.is_empty());
+    }
+
+    #[test]
+    fn orders_removals_by_priority() {
+        let scan = tulsa();
+        let plan = |priority| {
+            let plan = CleanPlan::new(&scan, &Policy::default().with_priority(priority)).unwrap();
+            removed(&plan)
+        };
+        let largest = [
+            "b/TX_Waco_20200101_TM_geo.pdf",
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "a/OK_Tulsa_20210126_TM_geo.pdf",
+        ];
+        assert_eq!(plan(RemovalPriority::Largest), largest);
+        let oldest = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "b/TX_Waco_20200101_TM_geo.pdf",
+            "a/OK_Tulsa_20210126_TM_geo.pdf",
+        ];
+        assert_eq!(plan(RemovalPriority::Oldest), oldest);
+        let path = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "a/OK_Tulsa_20210126_TM_geo.pdf",
+            "b/TX_Waco_20200101_TM_geo.pdf",
+        ];
+        assert_eq!(plan(RemovalPriority::Path), path);
+    }
+
+    #[test]
+    fn defers_removals_past_the_cap() {
+        let scan = tulsa();
+        let policy = Policy::default().with_max_deletions(Some(1));
+        let plan = CleanPlan::new(&scan, &policy).unwrap();
+        assert_eq!(removed(&plan), ["b/TX_Waco_20200101_TM_geo.pdf"]);
+        let deferred = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "a/OK_Tulsa_20210126_TM_geo.pdf",
+        ];
+        assert_eq!(names(&plan.deferred), deferred);
+        let plan = CleanPlan::new(&scan, &Policy::default().with_max_deletions(Some(0))).unwrap();
+        assert!(plan.remove.is_empty());
+        assert_eq!(plan.deferred.len(), 3);
+    }
+
+    #[test]
+    fn counts_companions_against_the_cap() {
+        let mut scan = tulsa();
+        let waco = PathBuf::from("b/TX_Waco_20200101_TM_geo.pdf");
+        let companion = file("b/TX_Waco_20200101_TM_geo.xml", 10);
+        scan.companions.insert(waco, vec![companion]);
+        let plan = |max| {
+            let policy = Policy::default().with_max_deletions(Some(max));
+            let plan = CleanPlan::new(&scan, &policy).unwrap();
+            (removed(&plan), names(&plan.deferred))
+        };
+        // The largest removal and its companion don't fit under a cap of one
+        let (removed, deferred) = plan(1);
+        assert!(removed.is_empty());
+        assert_eq!(deferred.len(), 4);
+        let (removed, deferred) = plan(2);
+        let bundle = [
+            "b/TX_Waco_20200101_TM_geo.pdf",
+            "b/TX_Waco_20200101_TM_geo.xml",
+        ];
+        assert_eq!(removed, bundle);
+        assert_eq!(deferred.len(), 2);
+    }
+
+    #[test]
+    fn holds_back_charts_older_than_the_baseline() {
+        let newer = scan(vec![file("a/OK_Tulsa_20240126_TM_geo.pdf", 100)]);
+        let baseline = Baseline::from_plan(&CleanPlan::new(&newer, &Policy::default()).unwrap());
+        let scan = tulsa();
+        let policy = Policy::default().with_baseline(Some(Arc::new(baseline)));
+        let plan = CleanPlan::new(&scan, &policy).unwrap();
+        assert_eq!(names(&plan.downgraded), ["a/OK_Tulsa_20230126_TM_geo.pdf"]);
+        let held = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "a/OK_Tulsa_20210126_TM_geo.pdf",
+        ];
+        assert_eq!(names(&plan.held), held);
+        assert_eq!(removed(&plan), ["b/TX_Waco_20200101_TM_geo.pdf"]);
+    }
+
+    #[test]
+    fn flags_or_retires_withdrawn_charts() {
+        let scan = tulsa();
+        let list = || {
+            let chart = "OK_Tulsa[TM]".parse().unwrap();
+            Some(Arc::new(WithdrawnList::from_iter([chart])))
+        };
+        let flag = Policy::default().with_withdrawn(list(), WithdrawnAction::Flag);
+        let plan = CleanPlan::new(&scan, &flag).unwrap();
+        assert_eq!(names(&plan.withdrawn), ["a/OK_Tulsa_20230126_TM_geo.pdf"]);
+        assert!(plan.retired.is_empty());
+        assert_eq!(plan.remove.len(), 3);
+
+        let remove = Policy::default().with_withdrawn(list(), WithdrawnAction::Remove);
+        let plan = CleanPlan::new(&scan, &remove).unwrap();
+        let mut retired = names(&plan.retired);
+        retired.sort();
+        let all = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "a/OK_Tulsa_20210126_TM_geo.pdf",
+            "a/OK_Tulsa_20230126_TM_geo.pdf",
+        ];
+        assert_eq!(retired, all);
+        assert_eq!(names(&plan.keep), ["b/TX_Waco_20220101_TM_geo.pdf"]);
+        assert_eq!(removed(&plan), ["b/TX_Waco_20200101_TM_geo.pdf"]);
+    }
+
+    #[test]
+    fn never_removes_protected_files() {
+        let scan = tulsa();
+        let mut protected = ProtectedFiles::default();
+        protected.insert(Path::new("a/OK_Tulsa_20190126_TM_geo.pdf"));
+        let policy = Policy::default().with_protected(Some(Arc::new(protected)));
+        let plan = CleanPlan::new(&scan, &policy).unwrap();
+        assert_eq!(names(&plan.protected), ["a/OK_Tulsa_20190126_TM_geo.pdf"]);
+        let removed = removed(&plan);
+        assert!(!removed.contains(&"a/OK_Tulsa_20190126_TM_geo.pdf".to_string()));
+        assert_eq!(removed.len(), 2);
+    }
+
+    #[test]
+    fn retains_the_edition_before_a_suspect_one() {
+        let scan = tulsa();
+        // The newest Tulsa is 200 bytes, the newest Waco 400
+        let ranges = vec!["pdf 300-".parse::<SizeRange>().unwrap()];
+        let plan = CleanPlan::new(&scan, &Policy::default().with_size_ranges(ranges)).unwrap();
+        assert_eq!(names(&plan.suspect), ["a/OK_Tulsa_20230126_TM_geo.pdf"]);
+        assert_eq!(names(&plan.retained), ["a/OK_Tulsa_20210126_TM_geo.pdf"]);
+        let mut removed = removed(&plan);
+        removed.sort();
+        let expected = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "b/TX_Waco_20200101_TM_geo.pdf",
+        ];
+        assert_eq!(removed, expected);
+    }
+
+    #[test]
+    fn leaves_editions_until_they_age() {
+        let scan = tulsa();
+        let policy = Policy::default().with_removable_before(Some(JAN_2021));
+        let plan = CleanPlan::new(&scan, &policy).unwrap();
+        assert_eq!(names(&plan.aging), ["a/OK_Tulsa_20210126_TM_geo.pdf"]);
+        let mut removed = removed(&plan);
+        removed.sort();
+        let expected = [
+            "a/OK_Tulsa_20190126_TM_geo.pdf",
+            "b/TX_Waco_20200101_TM_geo.pdf",
+        ];
+        assert_eq!(removed, expected);
+    }
+
+    fn editions() -> (FoundFile, FoundFile) {
+        let old = file("OK_Tulsa_20190126_TM_geo.pdf", 1);
+        (old, file("OK_Tulsa_20230126_TM_geo.pdf", 1))
+    }
+
+    fn removal<'a>(file: &'a FoundFile, by: &'a FoundFile) -> Removal<'a> {
+        Removal {
+            file,
+            superseded_by: Some(by),
+        }
+    }
+
+    #[test]
+    fn check_accepts_a_superseded_removal() {
+        let (old, new) = editions();
+        let plan = CleanPlan {
+            keep: vec![&new],
+            remove: vec![removal(&old, &new)],
+            ..Default::default()
+        };
+        assert!(plan.check().is_ok());
+    }
+
+    fn check_fails(plan: &CleanPlan, msg: &str) {
+        let e = plan.check().unwrap_err();
+        assert_eq!(e.phase(), Phase::Validate);
+        assert!(e.kind().to_string().contains(msg), "{e} doesn't say {msg}");
+    }
+
+    #[test]
+    fn check_rejects_a_removal_without_a_superseding_file() {
+        let old = file("OK_Tulsa_20190126_TM_geo.pdf", 1);
+        let plan = CleanPlan {
+            remove: vec![Removal {
+                file: &old,
+                superseded_by: None,
+            }],
+            ..Default::default()
+        };
+        check_fails(&plan, "would remove the only copy");
+    }
+
+    #[test]
+    fn check_rejects_an_older_keeper() {
+        let (old, new) = editions();
+        let plan = CleanPlan {
+            keep: vec![&old],
+            remove: vec![removal(&new, &old)],
+            ..Default::default()
+        };
+        check_fails(&plan, "superseded by an older version");
+    }
+
+    #[test]
+    fn check_rejects_a_keeper_of_another_chart() {
+        let (old, _) = editions();
+        let waco = file("TX_Waco_20230126_TM_geo.pdf", 1);
+        let plan = CleanPlan {
+            keep: vec![&waco],
+            remove: vec![removal(&old, &waco)],
+            ..Default::default()
+        };
+        check_fails(&plan, "a different chart");
+    }
+
+    #[test]
+    fn check_rejects_a_keeper_that_isnt_kept() {
+        let (old, new) = editions();
+        let plan = CleanPlan {
+            remove: vec![removal(&old, &new)],
+            ..Default::default()
+        };
+        check_fails(&plan, "isn't kept");
+        let kept_elsewhere = file("b/OK_Tulsa_20230126_TM_geo.pdf", 1);
+        let plan = CleanPlan {
+            keep: vec![&kept_elsewhere],
+            remove: vec![removal(&old, &new)],
+            ..Default::default()
+        };
+        check_fails(&plan, "isn't kept");
+    }
+
+    #[test]
+    fn check_rejects_a_file_both_kept_and_removed() {
+        let (old, new) = editions();
+        let plan = CleanPlan {
+            keep: vec![&new, &old],
+            remove: vec![removal(&old, &new)],
+            ..Default::default()
+        };
+        check_fails(&plan, "both kept and removed");
+    }
+
+    #[test]
+    fn reclaims_only_what_is_gone() {
+        let dir = TempDir::new("reclaim");
+        let journal = Journal::new(dir.path().join("journal.tsv"), RunId::new());
+        let path = dir.write("OK_Tulsa_20190126_TM_geo.pdf", &[1; 8192]);
+        let mut reclaimed = Reclaimed::default();
+        let remove = |executor: &dyn Executor, reclaimed: &mut Reclaimed| {
+            execute_removal(
+                executor,
+                &journal,
+                "test",
+                reclaimed,
+                dir.path(),
+                &path,
+                None,
+            )
+        };
+        remove(&DryRunExecutor, &mut reclaimed).unwrap();
+        remove(&MarkerExecutor, &mut reclaimed).unwrap();
+        assert_eq!(reclaimed.bytes(), 0);
+        remove(&DeleteExecutor::new(Arc::default()), &mut reclaimed).unwrap();
+        assert!(!path.exists());
+        assert!(reclaimed.bytes() >= 8192);
+    }
+
+    fn arb_file() -> impl Strategy<Value = FoundFile> {
+        let chart = prop::sample::select(vec!["OK_Tulsa", "TX_Waco", "CA_Alpha"]);
+        let dir = prop::sample::select(vec!["a", "b", "a/c"]);
+        (chart, dir, 2000..2030u32, 1..=12u32, 1..=28u32, 1..1000u64).prop_map(
+            |(chart, dir, year, month, day, size)| {
+                file(
+                    &format!("{dir}/{chart}_{year}{month:02}{day:02}_TM_geo.pdf"),
+                    size,
+                )
+            },
+        )
+    }
+
+    fn arb_priority() -> impl Strategy<Value = RemovalPriority> {
+        prop::sample::select(vec![
+            RemovalPriority::Largest,
+            RemovalPriority::Oldest,
+            RemovalPriority::Path,
+        ])
+    }
+
+    proptest! {
+        #[test]
+        fn every_file_is_kept_removed_or_deferred(
+            files in prop::collection::vec(arb_file(), 0..40),
+            max_deletions in prop::option::of(0..10usize),
+            priority in arb_priority(),
+        ) {
+            let scan = scan(files);
+            let policy = Policy::default()
+                .with_priority(priority)
+                .with_max_deletions(max_deletions);
+            let plan = CleanPlan::new(&scan, &policy).unwrap();
+            prop_assert!(plan.check().is_ok());
+            let mut planned: Vec<PathBuf> = plan.keep.iter().map(|f| f.full_path()).collect();
+            planned.extend(plan.remove.iter().map(|r| r.file.full_path()));
+            planned.extend(plan.deferred.iter().map(|f| f.full_path()));
+            let mut scanned: Vec<PathBuf> = scan.to_keep.iter().chain(&scan.to_remove)
+                .map(|f| f.full_path())
+                .collect();
+            planned.sort();
+            scanned.sort();
+            prop_assert_eq!(planned, scanned);
+            prop_assert_eq!(plan.keep.len(), scan.to_keep.len());
+            if let Some(max) = max_deletions {
+                prop_assert!(plan.remove.len() <= max);
+            }
+            prop_assert_eq!(plan.remove.len() + plan.deferred.len(), scan.to_remove.len());
+        }
+
+        #[test]
+        fn keeps_each_charts_newest_and_removes_in_priority_order(
+            files in prop::collection::vec(arb_file(), 0..40),
+            priority in arb_priority(),
+        ) {
+            let scan = scan(files);
+            let plan = CleanPlan::new(&scan, &Policy::default().with_priority(priority)).unwrap();
+            for kept in &plan.keep {
+                let older = scan.to_remove.iter().filter(|f| f.id() == kept.id());
+                prop_assert!(older.into_iter().all(|f| f.version() <= kept.version()));
+            }
+            let mut sorted: Vec<&FoundFile> = plan.remove.iter().map(|r| r.file).collect();
+            priority.sort(&mut sorted);
+            let order: Vec<PathBuf> = plan.remove.iter().map(|r| r.file.full_path()).collect();
+            let sorted: Vec<PathBuf> = sorted.iter().map(|f| f.full_path()).collect();
+            prop_assert_eq!(order, sorted);
+        }
+    }
+}
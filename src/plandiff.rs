@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use irox_time::datetime::UTCDateTime;
+use irox_time::epoch::UnixTimestamp;
+use irox_tools::ansi_colors::{
+    FORMAT_BOLD, FORMAT_COLOR_FG_CYAN, FORMAT_COLOR_FG_GREEN, FORMAT_COLOR_FG_RED, FORMAT_RESET,
+};
+
+use crate::hash::hash_file;
+use crate::{
+    format_size, CleanPlan, FoundFile, Manifest, ManifestAction, ManifestEntry, Scan, Scanner,
+};
+
+///
+/// Which side of a [`PlanDiff`] a line is on.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum DiffSide {
+    /// In the manifest and in a plan made now, the same
+    Both,
+    /// In the manifest, but not in a plan made now, or not the same
+    Planned,
+    /// In a plan made now, but not in the manifest, or not the same
+    Now,
+}
+
+///
+/// A file kept or removed by one side of a [`PlanDiff`], or both.
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub side: DiffSide,
+    pub action: ManifestAction,
+    /// Relative to the root
+    pub path: PathBuf,
+    pub size: u64,
+    /// Why the line differs, ie `gone` for a planned file that isn't there any more
+    pub note: Option<String>,
+}
+
+///
+/// The lines of one chart, or of one kept file and its removals if what chart they are can't be
+/// told, as none of them are on disk any more.
+#[derive(Debug, Clone)]
+pub struct DiffHunk {
+    pub chart: String,
+    pub lines: Vec<DiffLine>,
+}
+
+///
+/// What's changed since a manifest was planned, as the difference between it and a plan of the
+/// archive made now: planned files that are gone or changed since, and removals a new edition
+/// has brought about.  Only the charts with a difference are kept, each with the lines both
+/// plans share around the ones they don't, like the hunks of a unified diff.
+#[derive(Debug, Clone, Default)]
+pub struct PlanDiff {
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl PlanDiff {
+    ///
+    /// Compares the manifest with a plan of a scan of the archive at `root` made now, by the
+    /// scanner that made it.  Every planned file still the planned size is hashed, to tell
+    /// whether it changed.
+    pub fn new(
+        manifest: &Manifest,
+        root: &Path,
+        scanner: &Scanner,
+        scan: &Scan,
+        now: &CleanPlan,
+    ) -> PlanDiff {
+        let files = scan.to_keep.iter().chain(&scan.to_remove);
+        let on_disk: BTreeMap<PathBuf, &FoundFile> = files
+            .chain(scan.companions.values().flatten())
+            .map(|f| (f.relative_path(), f))
+            .collect();
+        // As a manifest records a plan, ie keeping only the files that supersede a removal
+        let mut replanned: BTreeMap<PathBuf, (ManifestAction, &FoundFile)> = BTreeMap::new();
+        for removal in &now.remove {
+            if let Some(keeper) = removal.superseded_by {
+                replanned.insert(keeper.relative_path(), (ManifestAction::Keep, keeper));
+            }
+            replanned.insert(
+                removal.file.relative_path(),
+                (ManifestAction::Remove, removal.file),
+            );
+        }
+        let mut hunks: BTreeMap<String, Vec<DiffLine>> = BTreeMap::new();
+        for e in &manifest.entries {
+            // A planned file that's gone is filed under the chart its name parses as
+            let chart = match on_disk.get(&e.path) {
+                Some(file) => file.id().to_string(),
+                None => scanner
+                    .parse_name(&e.path)
+                    .map_or_else(|_| e.path.display().to_string(), |f| f.id().to_string()),
+            };
+            let lines = hunks.entry(chart).or_default();
+            let planned = |note: String| DiffLine {
+                side: DiffSide::Planned,
+                action: e.action,
+                path: e.path.clone(),
+                size: e.size,
+                note: Some(note),
+            };
+            let change = compare(e, &root.join(&e.path));
+            match (replanned.remove(&e.path), change) {
+                (Some((action, _)), None) if action == e.action => lines.push(DiffLine {
+                    note: None,
+                    side: DiffSide::Both,
+                    ..planned(String::new())
+                }),
+                (Some((action, file)), change) => {
+                    let note = change.unwrap_or_else(|| format!("now to {action}"));
+                    lines.push(planned(note));
+                    lines.push(now_line(action, file, None));
+                }
+                (None, Some(change)) => lines.push(planned(change)),
+                (None, None) => lines.push(planned("not planned now".to_string())),
+            }
+        }
+        for (action, file) in replanned.into_values() {
+            let note = Some("not in the plan".to_string());
+            hunks
+                .entry(file.id().to_string())
+                .or_default()
+                .push(now_line(action, file, note));
+        }
+        let hunks = hunks
+            .into_iter()
+            .filter(|(_, lines)| lines.iter().any(|l| l.side != DiffSide::Both))
+            .map(|(chart, mut lines)| {
+                lines.sort_by(|a, b| a.path.cmp(&b.path).then(a.side.cmp(&b.side)));
+                DiffHunk { chart, lines }
+            })
+            .collect();
+        PlanDiff { hunks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+
+    ///
+    /// The lines on one side only, `(planned, now)`
+    pub fn counts(&self) -> (usize, usize) {
+        let lines = self.hunks.iter().flat_map(|h| &h.lines);
+        lines.fold((0, 0), |(planned, now), line| match line.side {
+            DiffSide::Both => (planned, now),
+            DiffSide::Planned => (planned + 1, now),
+            DiffSide::Now => (planned, now + 1),
+        })
+    }
+
+    ///
+    /// The diff in the unified format, from the manifest at `path` to the archive at `root`, in
+    /// color if asked
+    pub fn render(&self, manifest: &Manifest, path: &Path, root: &Path, color: bool) -> String {
+        let paint = |code: &str, text: String| match color {
+            true => format!("{code}{text}{FORMAT_RESET}"),
+            false => text,
+        };
+        let created = UTCDateTime::from(UnixTimestamp::from_seconds_f64(manifest.created as f64));
+        let mut out = String::new();
+        let planned = format!("--- {}\t{created}", path.display());
+        let _ = writeln!(out, "{}", paint(FORMAT_BOLD, planned));
+        let _ = writeln!(
+            out,
+            "{}",
+            paint(FORMAT_BOLD, format!("+++ {}\tnow", root.display()))
+        );
+        for hunk in &self.hunks {
+            let header = format!("@@ {} @@", hunk.chart);
+            let _ = writeln!(out, "{}", paint(FORMAT_COLOR_FG_CYAN, header));
+            for line in &hunk.lines {
+                let (sign, code) = match line.side {
+                    DiffSide::Both => (' ', None),
+                    DiffSide::Planned => ('-', Some(FORMAT_COLOR_FG_RED)),
+                    DiffSide::Now => ('+', Some(FORMAT_COLOR_FG_GREEN)),
+                };
+                let mut text = format!(
+                    "{sign}{:<7} {}  {}",
+                    line.action.to_string(),
+                    line.path.display(),
+                    format_size(line.size)
+                );
+                if let Some(note) = &line.note {
+                    let _ = write!(text, "  ({note})");
+                }
+                let text = match code {
+                    Some(code) => paint(code, text),
+                    None => text,
+                };
+                let _ = writeln!(out, "{text}");
+            }
+        }
+        out
+    }
+}
+
+fn now_line(action: ManifestAction, file: &FoundFile, note: Option<String>) -> DiffLine {
+    DiffLine {
+        side: DiffSide::Now,
+        action,
+        path: file.relative_path(),
+        size: file.size(),
+        note,
+    }
+}
+
+///
+/// How the planned file at `path` differs from what the manifest recorded, if it does
+fn compare(entry: &ManifestEntry, path: &Path) -> Option<String> {
+    let meta = match std::fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Some("gone".to_string()),
+        Err(e) => return Some(format!("can't be read: {e}")),
+    };
+    if meta.len() != entry.size {
+        return Some(format!("now {}", format_size(meta.len())));
+    }
+    match hash_file(path) {
+        Ok(digest) if digest == entry.digest => None,
+        Ok(_) => Some("contents changed".to_string()),
+        Err(e) => Some(format!("can't be read: {e}")),
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::{format_duration, Error, ErrorContext, ErrorKind, Phase, Profile};
+
+///
+/// The presets that ship with charts-clean, by name
+const BUILTIN: [(&str, &str); 4] = [
+    (
+        "usgs-topo",
+        "# USGS US Topo and historical quads: large GeoPDFs and GeoTIFFs, reissued every few years
+priority = largest
+group_by = source, state
+withdrawn_action = flag
+anomaly_factor = 5
+on_conflict = abort",
+    ),
+    (
+        "noaa-enc",
+        "# NOAA ENC cells: many small files updated weekly, with cancelled cells withdrawn
+priority = oldest
+group_by = source, scale
+withdrawn_action = remove
+anomaly_factor = 10
+planners = 4
+on_conflict = replan",
+    ),
+    (
+        "faa-vfr",
+        "# FAA sectionals and TACs: every chart replaced at once on the 56 day cycle
+priority = oldest
+group_by = chart
+withdrawn_action = remove
+on_conflict = replan",
+    ),
+    (
+        "tile-cache",
+        "# Tile caches: huge numbers of small tiles that can always be fetched again
+priority = path
+planners = 8
+scan_timeout = 6h
+executor = delete
+on_conflict = skip",
+    ),
+];
+
+///
+/// A named set of profile settings for a kind of collection.  In a config file, `preset = NAME`
+/// applies it where it appears, so the keys after it override it; `--preset` applies it under
+/// the config file and the other command line settings.  Written like a profile section without
+/// the header, one `key = value` per line with `#` comments:
+/// ```text
+/// # NOAA ENC cells
+/// priority = oldest
+/// withdrawn_action = remove
+/// ```
+#[derive(Debug, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub settings: Vec<(String, String)>,
+}
+
+impl Preset {
+    pub fn builtin(name: &str) -> Option<Preset> {
+        let (_, text) = BUILTIN.iter().find(|(n, _)| *n == name)?;
+        Preset::parse(name, text).ok()
+    }
+
+    pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+        BUILTIN.iter().map(|(name, _)| *name)
+    }
+
+    ///
+    /// The built-in preset with the name, or the preset in the file at that path
+    pub fn find(name: &str) -> Result<Preset, Error> {
+        if let Some(preset) = Preset::builtin(name) {
+            return Ok(preset);
+        }
+        let path = Path::new(name);
+        if !path.is_file() {
+            let names: Vec<&str> = Preset::builtin_names().collect();
+            let msg = format!(
+                "Unknown preset {name}, expected {} or a file",
+                names.join(", ")
+            );
+            return Err(preset_error(msg));
+        }
+        Preset::load(path)
+    }
+
+    pub fn load(path: &Path) -> Result<Preset, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        Preset::parse(&path.to_string_lossy(), &text).map_err(|e| e.with_path(path))
+    }
+
+    ///
+    /// Checks every setting against a scratch profile, so a bad preset is refused whole
+    pub fn parse(name: &str, text: &str) -> Result<Preset, Error> {
+        let mut scratch = Profile::new("", "");
+        let mut settings = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| preset_error(format!("line {}: {msg}", idx + 1));
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(err(format!("Expected key = value: {line}")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == "root" {
+                return Err(err("a preset can't set the root".to_string()));
+            }
+            scratch.set(key, value).map_err(err)?;
+            settings.push((key.to_string(), value.to_string()));
+        }
+        Ok(Preset {
+            name: name.to_string(),
+            settings,
+        })
+    }
+
+    ///
+    /// The effective policy of the profile: every setting that decides what's removed and how,
+    /// leaving out its root
+    pub fn of(profile: &Profile) -> Preset {
+        let path = |path: &Path| path.display().to_string();
+        let mut settings = vec![("source_tz", profile.source_tz.to_string())];
+        settings.extend(
+            profile
+                .aliases
+                .iter()
+                .map(|alias| ("alias", alias.to_string())),
+        );
+        if let Some(fields) = &profile.fixed_fields {
+            settings.push(("fixed_fields", fields.to_string()));
+        }
+        if let Some(quarantine) = &profile.quarantine {
+            settings.push(("quarantine", path(quarantine)));
+        }
+        if let Some(executor) = &profile.executor {
+            settings.push(("executor", executor.to_string()));
+        }
+        let durations = [
+            ("quarantine_retention", profile.quarantine_retention),
+            ("scan_timeout", profile.scan_timeout),
+            ("removal_timeout", profile.removal_timeout),
+            ("lock_stale_after", profile.lock_stale_after),
+            ("edition_interval", profile.edition_interval),
+            ("cold_after", profile.cold_after),
+        ];
+        for (key, duration) in durations {
+            if let Some(duration) = duration {
+                settings.push((key, format_duration(duration)));
+            }
+        }
+        settings.push(("verify_copies", profile.verify_copies.to_string()));
+        settings.push(("checksums", profile.checksums.to_string()));
+        settings.push(("priority", profile.priority.to_string()));
+        if let Some(max) = profile.max_deletions {
+            settings.push(("max_deletions", max.to_string()));
+        }
+        settings.push(("planners", profile.planners.to_string()));
+        if let Some(baseline) = &profile.baseline {
+            settings.push(("baseline", path(baseline)));
+        }
+        if !profile.group_by.is_empty() {
+            let keys: Vec<String> = profile.group_by.iter().map(ToString::to_string).collect();
+            settings.push(("group_by", keys.join(", ")));
+        }
+        settings.extend(
+            profile
+                .size_ranges
+                .iter()
+                .map(|range| ("size_range", range.to_string())),
+        );
+        settings.extend(
+            profile
+                .companions
+                .iter()
+                .map(|rule| ("companion", rule.to_string())),
+        );
+        if let Some(factor) = profile.anomaly_factor {
+            settings.push(("anomaly_factor", factor.to_string()));
+        }
+        if let Some(withdrawn) = &profile.withdrawn {
+            settings.push(("withdrawn", path(withdrawn)));
+        }
+        settings.push(("withdrawn_action", profile.withdrawn_action.to_string()));
+        settings.extend(
+            profile
+                .protect
+                .iter()
+                .map(|protect| ("protect", path(protect))),
+        );
+        settings.push(("force_attrs", profile.force_attrs.to_string()));
+        settings.push(("fail_on_skipped", profile.fail_on_skipped.to_string()));
+        settings.push(("dir_cache", profile.dir_cache.to_string()));
+        settings.push(("on_conflict", profile.on_conflict.to_string()));
+        Preset {
+            name: profile.name.clone(),
+            settings: settings
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        }
+    }
+
+    ///
+    /// Sets each of the preset's settings on the profile, in order
+    pub fn apply(&self, profile: &mut Profile) -> Result<(), String> {
+        for (key, value) in &self.settings {
+            profile
+                .set(key, value)
+                .map_err(|msg| format!("preset {}: {msg}", self.name))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in &self.settings {
+            writeln!(f, "{key} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+fn preset_error(msg: String) -> Error {
+    Error::new(ErrorKind::ConfigError(msg), Phase::Setup)
+}
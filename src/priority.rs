@@ -0,0 +1,65 @@
+use std::cmp::Reverse;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::FoundFile;
+
+///
+/// The order removals are executed in.  Reclaiming the most space first means that a run that's
+/// interrupted, or stops at its deletion cap, has already freed as much as it could.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum RemovalPriority {
+    /// Biggest files first
+    #[default]
+    Largest,
+    /// Oldest editions first
+    Oldest,
+    /// Sorted by path, the order the files would be listed in
+    Path,
+}
+
+impl RemovalPriority {
+    ///
+    /// Sorts the removal candidates into execution order.  Ties are broken by path so the order
+    /// is the same from run to run.
+    pub fn sort(&self, files: &mut [&FoundFile]) {
+        match self {
+            RemovalPriority::Largest => {
+                files.sort_by(|a, b| {
+                    Reverse(a.size())
+                        .cmp(&Reverse(b.size()))
+                        .then_with(|| a.cmp_path(b))
+                });
+            }
+            RemovalPriority::Oldest => {
+                files.sort_by(|a, b| a.version().cmp(b.version()).then_with(|| a.cmp_path(b)));
+            }
+            RemovalPriority::Path => files.sort_by(|a, b| a.cmp_path(b)),
+        }
+    }
+}
+
+impl FromStr for RemovalPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "largest" => Ok(RemovalPriority::Largest),
+            "oldest" => Ok(RemovalPriority::Oldest),
+            "path" => Ok(RemovalPriority::Path),
+            _ => Err(format!(
+                "Unknown priority {s}, expected largest, oldest or path"
+            )),
+        }
+    }
+}
+
+impl Display for RemovalPriority {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemovalPriority::Largest => write!(f, "largest"),
+            RemovalPriority::Oldest => write!(f, "oldest"),
+            RemovalPriority::Path => write!(f, "path"),
+        }
+    }
+}
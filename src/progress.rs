@@ -0,0 +1,70 @@
+use std::path::Path;
+
+use crate::FoundFile;
+
+///
+/// What the planner decided to do with a file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Decision {
+    Keep,
+    Remove,
+    /// A removal left for a later run by the deletion cap
+    Defer,
+    /// A removal held back because its chart is older than the baseline
+    Hold,
+    /// A removal left until the edition is older than the policy's `removable_before`
+    Aging,
+    /// A removal held back because another application references the file
+    Protect,
+    /// A removal held back because the newest edition of its chart is a suspect size
+    Retain,
+    /// A removal skipped because the file is immutable, append-only or read-only
+    Locked,
+    /// A file of a chart withdrawn by its publisher, removed with the rest of the chart
+    Retire,
+}
+
+///
+/// Something that happened during a run, for embedders rendering their own progress.
+#[derive(Debug, Copy, Clone)]
+pub enum Event<'a> {
+    /// A directory is about to be listed
+    EnteringDir(&'a Path),
+    /// A chart was found, `scanned` is the number found so far
+    FileScanned { file: &'a FoundFile, scanned: usize },
+    /// The plan's decision about a file
+    Decided {
+        file: &'a FoundFile,
+        decision: Decision,
+    },
+    /// A planned removal was carried out, the `done`th of `total`
+    Removed {
+        path: &'a Path,
+        dest: Option<&'a Path>,
+        done: usize,
+        total: usize,
+    },
+}
+
+///
+/// Receives the events of a run as they happen.  Called from whichever thread is doing the work,
+/// so implementations should be quick and hand anything slow off elsewhere.  Any
+/// `Fn(&Event) + Send + Sync` closure is a `Progress`.
+pub trait Progress: Send + Sync {
+    fn event(&self, event: &Event<'_>);
+}
+
+impl<F: Fn(&Event<'_>) + Send + Sync> Progress for F {
+    fn event(&self, event: &Event<'_>) {
+        self(event);
+    }
+}
+
+///
+/// Ignores every event.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn event(&self, _event: &Event<'_>) {}
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{Error, ErrorContext, FoundFile, Phase};
+
+///
+/// Files another application depends on, ie the rasters a QGIS project has as layers, which a
+/// plan never removes however superseded they are.  Loaded from the application's own index:
+///
+/// * a QGIS project (`.qgs`), whose layers' data sources are protected.  Layers served from
+///   elsewhere, ie WMS or PostGIS, are ignored, and a layer inside a zip protects the zip.
+///   Zipped `.qgz` projects have to be unzipped first.
+/// * anything else is a list of paths, one per line, with `#` comments, ie exported from
+///   OpenCPN's chart groups.  OpenCPN's own chart database is binary and isn't read.
+///
+/// Relative paths are relative to the index's directory.  Paths are compared as written once
+/// made absolute, and, for those that exist, with their symlinks resolved too.
+#[derive(Debug, Default, Clone)]
+pub struct ProtectedFiles {
+    paths: BTreeSet<PathBuf>,
+}
+
+impl ProtectedFiles {
+    pub fn load(path: &Path) -> Result<ProtectedFiles, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        let referenced = if path.extension().is_some_and(|ext| ext == "qgs") {
+            qgis_sources(&text)
+        } else {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(PathBuf::from)
+                .collect()
+        };
+        let base = path.parent().unwrap_or(Path::new(""));
+        let mut protected = ProtectedFiles::default();
+        for referenced in referenced {
+            protected.insert(&base.join(referenced));
+        }
+        Ok(protected)
+    }
+
+    ///
+    /// Protects the file at the path
+    pub fn insert(&mut self, path: &Path) {
+        if let Ok(canonical) = std::fs::canonicalize(path) {
+            self.paths.insert(canonical);
+        }
+        self.paths.insert(absolute(path));
+    }
+
+    pub fn extend(&mut self, other: ProtectedFiles) {
+        self.paths.extend(other.paths);
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(&absolute(path))
+    }
+
+    pub fn protects(&self, file: &FoundFile) -> bool {
+        self.contains(&file.full_path())
+    }
+
+    ///
+    /// How many paths are protected, counting a path and its resolved form separately
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+///
+/// The path made absolute against the working directory, with `.` and `..` resolved lexically
+fn absolute(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+///
+/// The local files a QGIS project's layers are read from
+fn qgis_sources(text: &str) -> Vec<PathBuf> {
+    let mut sources = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("<datasource>") {
+        rest = &rest[start + "<datasource>".len()..];
+        let Some(end) = rest.find("</datasource>") else {
+            break;
+        };
+        let source = unescape_xml(&rest[..end]);
+        rest = &rest[end..];
+        let source = source.trim();
+        let source = source.strip_prefix("file://").unwrap_or(source);
+        let source = source.split(['|', '?']).next().unwrap_or_default();
+        if source.is_empty() || source.contains("://") || source.contains('=') {
+            continue;
+        }
+        let source = match source.strip_prefix("/vsizip/") {
+            Some(inner) => match inner.find(".zip/") {
+                Some(idx) => &inner[..idx + ".zip".len()],
+                None => inner,
+            },
+            None => source,
+        };
+        sources.push(PathBuf::from(source));
+    }
+    sources
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
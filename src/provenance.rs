@@ -0,0 +1,56 @@
+use std::fmt::{Display, Formatter};
+
+///
+/// Where a profile's charts came from and the terms they're used under, as its config tags
+/// them, ie `origin = USGS National Map` and `license = Public domain`.  Carried into the
+/// catalog, plan manifests, export indexes and the run report, so what an archive keeps can be
+/// accounted for in a compliance review.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Provenance {
+    pub origin: Option<String>,
+    pub license: Option<String>,
+}
+
+impl Provenance {
+    pub fn is_empty(&self) -> bool {
+        self.origin.is_none() && self.license.is_none()
+    }
+
+    ///
+    /// The `#origin\t<origin>` and `#license\t<license>` header lines of the files that carry
+    /// it, for those that are set
+    pub fn header_lines(&self) -> Vec<String> {
+        let origin = self
+            .origin
+            .iter()
+            .map(|origin| format!("#origin\t{origin}"));
+        let license = self
+            .license
+            .iter()
+            .map(|license| format!("#license\t{license}"));
+        origin.chain(license).collect()
+    }
+
+    ///
+    /// Takes the value of a header line written by [`Provenance::header_lines`], returning false
+    /// if the key isn't one of its
+    pub fn read_header(&mut self, key: &str, value: &str) -> bool {
+        match key {
+            "origin" => self.origin = Some(value.to_string()),
+            "license" => self.license = Some(value.to_string()),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.origin, &self.license) {
+            (Some(origin), Some(license)) => write!(f, "from {origin}, under {license}"),
+            (Some(origin), None) => write!(f, "from {origin}"),
+            (None, Some(license)) => write!(f, "under {license}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use irox_log::log::info;
+use irox_time::datetime::UTCDateTime;
+use irox_time::format::iso8601::BASIC_CALENDAR_DATE;
+use irox_time::format::FormatParser;
+use irox_time::gregorian::Date;
+
+use crate::copy::move_verified;
+use crate::hash::hash_file;
+use crate::{ChartId, Digest, Error, ErrorContext, FoundFile, Phase, Scanner, Throttle, UtcOffset};
+
+///
+/// What was (or in a dry-run, would have been) purged from a quarantine by
+/// [`Quarantine::purge`] or [`Quarantine::expire`].
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    pub days: Vec<Date>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+///
+/// A file held in the quarantine.
+#[derive(Debug, Clone)]
+pub struct QuarantinedFile {
+    /// The day it was quarantined
+    pub day: Date,
+    pub path: PathBuf,
+    /// Where it was, relative to the root it was scanned from
+    pub original: PathBuf,
+    pub size: u64,
+    /// The chart it's a version of, if the name still parses
+    pub chart: Option<ChartId>,
+}
+
+///
+/// The files held on every day, to look up an identical copy of a file by its size and hash.
+/// Files are only hashed once a file of the same size is to be held.
+#[derive(Debug, Default)]
+struct HeldIndex {
+    unhashed: BTreeMap<u64, Vec<PathBuf>>,
+    hashed: BTreeMap<(u64, Digest), PathBuf>,
+}
+
+impl HeldIndex {
+    fn insert(&mut self, path: PathBuf, size: u64, digest: Option<Digest>) {
+        match digest {
+            Some(digest) => {
+                self.hashed.entry((size, digest)).or_insert(path);
+            }
+            None => self.unhashed.entry(size).or_default().push(path),
+        }
+    }
+
+    fn has_size(&self, size: u64) -> bool {
+        let mut hashed = self.hashed.range((size, Digest::default())..);
+        self.unhashed.contains_key(&size) || hashed.next().is_some_and(|((s, _), _)| *s == size)
+    }
+
+    ///
+    /// The held file of this size and hash, hashing those of the size that aren't yet
+    fn find(&mut self, size: u64, digest: Digest) -> Option<PathBuf> {
+        for held in self.unhashed.remove(&size).unwrap_or_default() {
+            let Ok(held_digest) = hash_file(&held) else {
+                // Purged since the index was made
+                continue;
+            };
+            self.hashed.entry((size, held_digest)).or_insert(held);
+        }
+        let held = self.hashed.get(&(size, digest))?;
+        if std::fs::metadata(held).is_ok_and(|m| m.len() == size) {
+            return Some(held.clone());
+        }
+        self.hashed.remove(&(size, digest));
+        None
+    }
+}
+
+///
+/// A holding area for superseded files.  Instead of being deleted, files are moved to
+/// `<dir>/<YYYYMMDD>/<path relative to the scan root>`, grouped by the day they were quarantined.
+pub struct Quarantine {
+    dir: PathBuf,
+    verify_copies: bool,
+    throttle: Arc<Throttle>,
+    /// What a held file's name is parsed by, to tell its chart
+    scanners: Vec<Scanner>,
+    /// Made from the days held the first time a file is held
+    index: Mutex<Option<HeldIndex>>,
+}
+
+impl Quarantine {
+    pub fn new(dir: impl Into<PathBuf>) -> Quarantine {
+        Quarantine {
+            dir: dir.into(),
+            verify_copies: false,
+            throttle: Arc::default(),
+            scanners: Vec::new(),
+            index: Mutex::default(),
+        }
+    }
+
+    ///
+    /// Hash-verify files copied into the quarantine across devices before removing the original
+    #[must_use]
+    pub fn with_verify_copies(mut self, verify_copies: bool) -> Quarantine {
+        self.verify_copies = verify_copies;
+        self
+    }
+
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Quarantine {
+        self.throttle = throttle;
+        self
+    }
+
+    ///
+    /// Parses held files' names as these scanners do, ie in the source timezone, by the fixed
+    /// fields and filed under the aliases of the profiles quarantining here, the first that
+    /// parses a name telling its chart.  Without any, names are parsed as `UTC`.
+    #[must_use]
+    pub fn with_scanners(mut self, scanners: Vec<Scanner>) -> Quarantine {
+        self.scanners = scanners;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    ///
+    /// Where the file at `path` under `root` lands if quarantined today
+    pub fn destination(&self, root: &Path, path: &Path) -> PathBuf {
+        let today = UTCDateTime::now().get_date().format(&BASIC_CALENDAR_DATE);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let relative = relative.strip_prefix("/").unwrap_or(relative);
+        self.dir.join(today).join(relative)
+    }
+
+    ///
+    /// Moves the file at `path` under `root` into the quarantine.  If the quarantine already holds
+    /// an identical copy, from anywhere on any day, the file is removed instead of being held
+    /// twice, and the existing copy is returned.  A different file already held today at the same
+    /// path is never replaced; the hold fails, leaving both where they are.
+    pub fn hold(&self, root: &Path, path: &Path) -> Result<PathBuf, Error> {
+        let (held, size, digest) = self.identical(path)?;
+        if let Some(held) = held {
+            info!("{} is already held as {}", path.display(), held.display());
+            self.throttle.request();
+            std::fs::remove_file(path).context(Phase::Delete, path)?;
+            return Ok(held);
+        }
+        let dest = self.destination(root, path);
+        move_verified(path, &dest, self.verify_copies, &self.throttle)?;
+        self.with_index(|index| {
+            index.insert(dest.clone(), size, digest);
+            Ok(())
+        })?;
+        Ok(dest)
+    }
+
+    ///
+    /// A copy of the file at `path` held on any day, with the same size and hash, wherever it
+    /// was held from
+    pub fn find_identical(&self, path: &Path) -> Result<Option<PathBuf>, Error> {
+        self.identical(path).map(|(held, _, _)| held)
+    }
+
+    ///
+    /// The identical copy held, if there is one, along with the file's size and its hash if it
+    /// had to be hashed, as it only is if a file of its size is held
+    fn identical(&self, path: &Path) -> Result<(Option<PathBuf>, u64, Option<Digest>), Error> {
+        let size = std::fs::metadata(path).context(Phase::Copy, path)?.len();
+        self.with_index(|index| {
+            if !index.has_size(size) {
+                return Ok((None, size, None));
+            }
+            let digest = hash_file(path).context(Phase::Copy, path)?;
+            Ok((index.find(size, digest), size, Some(digest)))
+        })
+    }
+
+    fn with_index<T>(
+        &self,
+        f: impl FnOnce(&mut HeldIndex) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = match &mut *index {
+            Some(index) => index,
+            None => {
+                let mut held = HeldIndex::default();
+                for (_, dir) in self.days()? {
+                    let mut files = Vec::new();
+                    walk_files(&dir, &mut files)?;
+                    for (path, size) in files {
+                        held.insert(path, size, None);
+                    }
+                }
+                index.insert(held)
+            }
+        };
+        f(index)
+    }
+
+    ///
+    /// Each day held in the quarantine and its directory, oldest first.  Entries that aren't
+    /// named like a date are ignored.
+    pub fn days(&self) -> Result<Vec<(Date, PathBuf)>, Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(Phase::Scan, &self.dir),
+        };
+        let mut days = Vec::new();
+        for entry in entries {
+            let entry = entry.context(Phase::Scan, &self.dir)?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Ok(date) = BASIC_CALENDAR_DATE.try_from(&name) {
+                days.push((date, entry.path()));
+            }
+        }
+        days.sort();
+        Ok(days)
+    }
+
+    ///
+    /// Every file held in the quarantine, oldest day first
+    pub fn entries(&self) -> Result<Vec<QuarantinedFile>, Error> {
+        let mut entries = Vec::new();
+        for (day, dir) in self.days()? {
+            let mut files = Vec::new();
+            walk_files(&dir, &mut files)?;
+            files.sort();
+            for (path, size) in files {
+                let original = path.strip_prefix(&dir).unwrap_or(&path).to_path_buf();
+                let chart = match self.scanners.as_slice() {
+                    [] => FoundFile::parse(path.clone(), UtcOffset::UTC).ok(),
+                    scanners => scanners.iter().find_map(|s| s.parse_name(&original).ok()),
+                }
+                .map(|f| f.id().clone());
+                entries.push(QuarantinedFile {
+                    day,
+                    path,
+                    original,
+                    size,
+                    chart,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    ///
+    /// Removes every quarantined file matching `filter`, and the directories left empty.  With
+    /// `dry_run` nothing is removed, but the report still describes what would have been.
+    pub fn purge(
+        &self,
+        filter: impl Fn(&QuarantinedFile) -> bool,
+        dry_run: bool,
+    ) -> Result<PurgeReport, Error> {
+        let mut report = PurgeReport::default();
+        for file in self.entries()?.iter().filter(|f| filter(f)) {
+            if dry_run {
+                info!("Would purge {}", file.path.display());
+            } else {
+                info!("Purging {}", file.path.display());
+                self.throttle.request();
+                std::fs::remove_file(&file.path).context(Phase::Delete, &file.path)?;
+            }
+            report.files += 1;
+            report.bytes += file.size;
+            if !report.days.contains(&file.day) {
+                report.days.push(file.day);
+            }
+        }
+        if !dry_run {
+            for (day, dir) in self.days()? {
+                if report.days.contains(&day) {
+                    prune_empty_dirs(&dir).context(Phase::Delete, &dir)?;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    ///
+    /// Purges every day quarantined more than `retention` ago.
+    pub fn expire(&self, retention: Duration, dry_run: bool) -> Result<PurgeReport, Error> {
+        let retention_days = (retention.as_secs() / 86400).min(u16::MAX as u64) as u16;
+        let cutoff = UTCDateTime::now().get_date().sub_days(retention_days);
+        self.purge(|f| f.day < cutoff, dry_run)
+    }
+}
+
+///
+/// Removes `dir` and everything under it if it holds no files, returning true if it did.
+fn prune_empty_dirs(dir: &Path) -> std::io::Result<bool> {
+    let mut empty = true;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            empty &= prune_empty_dirs(&entry.path())?;
+        } else {
+            empty = false;
+        }
+    }
+    if empty {
+        std::fs::remove_dir(dir)?;
+    }
+    Ok(empty)
+}
+
+///
+/// Collects every file (and its size) under `dir`
+pub(crate) fn walk_files(dir: &Path, files: &mut Vec<(PathBuf, u64)>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir).context(Phase::Scan, dir)? {
+        let entry = entry.context(Phase::Scan, dir)?;
+        let path = entry.path();
+        let meta = entry.metadata().context(Phase::Scan, &path)?;
+        if meta.is_dir() {
+            walk_files(&path, files)?;
+        } else {
+            files.push((path, meta.len()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn holds_a_file_under_today() {
+        let dir = TempDir::new("quarantine");
+        let root = dir.path().join("root");
+        let path = dir.write("root/a/OK_Tulsa_20190126_TM_geo.pdf", b"old");
+        let quarantine = Quarantine::new(dir.path().join("q"));
+        let expected = quarantine.destination(&root, &path);
+        assert_eq!(quarantine.hold(&root, &path).unwrap(), expected);
+        assert!(!path.exists());
+        assert_eq!(std::fs::read(&expected).unwrap(), b"old");
+    }
+
+    #[test]
+    fn removes_a_file_already_held() {
+        let dir = TempDir::new("quarantine-identical");
+        let root = dir.path().join("root");
+        let quarantine = Quarantine::new(dir.path().join("q"));
+        let path = dir.write("root/a/OK_Tulsa_20190126_TM_geo.pdf", b"old");
+        let held = quarantine.hold(&root, &path).unwrap();
+        let again = dir.write("root/b/OK_Tulsa_20190126_TM_geo.pdf", b"old");
+        assert_eq!(quarantine.hold(&root, &again).unwrap(), held);
+        assert!(!again.exists());
+    }
+
+    #[test]
+    fn never_replaces_a_different_file_held_today() {
+        let dir = TempDir::new("quarantine-collision");
+        let root = dir.path().join("root");
+        let quarantine = Quarantine::new(dir.path().join("q"));
+        let path = dir.write("root/a/OK_Tulsa_20190126_TM_geo.pdf", b"first");
+        let held = quarantine.hold(&root, &path).unwrap();
+        let path = dir.write("root/a/OK_Tulsa_20190126_TM_geo.pdf", b"second");
+        assert!(quarantine.hold(&root, &path).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        assert_eq!(std::fs::read(&held).unwrap(), b"first");
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+///
+/// How many paths are handed to one `filefrag`
+const BATCH: usize = 256;
+
+///
+/// Where a file's data is on disk, read just before it's removed
+#[derive(Debug, Copy, Clone)]
+pub struct Allocation {
+    /// The blocks allocated to the file, less than its size if it's sparse
+    pub allocated: u64,
+    /// Of those, the bytes in extents shared with other files, ie reflinked copies
+    pub shared: u64,
+    /// The device and inode, for telling the links of one file apart, if known
+    inode: Option<(u64, u64)>,
+    links: u64,
+}
+
+impl Allocation {
+    #[cfg(unix)]
+    fn of(path: &Path, shared: u64) -> Option<Allocation> {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = std::fs::symlink_metadata(path).ok()?;
+        Some(Allocation {
+            allocated: meta.blocks() * 512,
+            shared,
+            inode: Some((meta.dev(), meta.ino())),
+            links: meta.nlink(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn of(path: &Path, shared: u64) -> Option<Allocation> {
+        let meta = std::fs::symlink_metadata(path).ok()?;
+        Some(Allocation {
+            allocated: meta.len(),
+            shared,
+            inode: None,
+            links: 1,
+        })
+    }
+}
+
+///
+/// Tallies the disk space removing files gives back, rather than their apparent sizes: a sparse
+/// file frees only its allocated blocks, a file with other hard links frees nothing until the
+/// last of them goes, and extents shared with a reflinked copy stay allocated to the copy.
+///
+/// Shared extents are read with `filefrag` on Linux, for every file up front, so where it isn't
+/// installed or the filesystem can't report them none are found.  Only files that are actually
+/// gone are counted, so a dry run, or an executor that leaves files in place, reclaims nothing.
+#[derive(Debug, Default)]
+pub struct Reclaimed {
+    bytes: u64,
+    shared: BTreeMap<PathBuf, u64>,
+    /// The links of each file removed so far
+    removed_links: BTreeMap<(u64, u64), u64>,
+}
+
+impl Reclaimed {
+    pub fn new(paths: &[PathBuf]) -> Reclaimed {
+        let mut shared = BTreeMap::new();
+        for batch in paths.chunks(BATCH) {
+            read_shared(batch, &mut shared);
+        }
+        Reclaimed {
+            shared,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Where the file's data is, to be passed to [`Reclaimed::removed`] once it's gone
+    pub fn measure(&self, path: &Path) -> Option<Allocation> {
+        Allocation::of(path, self.shared.get(path).copied().unwrap_or_default())
+    }
+
+    ///
+    /// Counts the space the removed file gave back
+    pub fn removed(&mut self, allocation: Option<Allocation>) {
+        let Some(allocation) = allocation else {
+            return;
+        };
+        if let Some(inode) = allocation.inode {
+            let removed = self.removed_links.entry(inode).or_default();
+            *removed += 1;
+            // A real removal unlinks as it goes, so the last link sees a count of one
+            if *removed < allocation.links {
+                return;
+            }
+        }
+        self.bytes += allocation.allocated.saturating_sub(allocation.shared);
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_shared(paths: &[PathBuf], shared: &mut BTreeMap<PathBuf, u64>) {
+    use irox_log::log::debug;
+    use std::process::Command;
+
+    let output = match Command::new("filefrag").arg("-v").args(paths).output() {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Unable to run filefrag, not checking for shared extents: {e}");
+            return;
+        }
+    };
+    // Each file starts `File size of PATH is N (B blocks of S bytes)`, then a row per extent:
+    // `ext: logical_offset: physical_offset: length: expected: flags`
+    let mut current: Option<(PathBuf, u64)> = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(rest) = line.strip_prefix("File size of ") {
+            current = rest.rsplit_once(" is ").and_then(|(path, sizes)| {
+                let block = sizes.rsplit_once(" of ")?.1.split_whitespace().next()?;
+                Some((PathBuf::from(path), block.parse().ok()?))
+            });
+            continue;
+        }
+        let Some((path, block)) = &current else {
+            continue;
+        };
+        let fields: Vec<&str> = line.split(':').collect();
+        let (Some(length), Some(flags)) = (fields.get(3), fields.last()) else {
+            continue;
+        };
+        if fields.len() > 4 && flags.split(',').any(|flag| flag.trim() == "shared") {
+            if let Ok(length) = length.trim().parse::<u64>() {
+                *shared.entry(path.clone()).or_default() += length * block;
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_shared(_paths: &[PathBuf], _shared: &mut BTreeMap<PathBuf, u64>) {}
@@ -0,0 +1,129 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::{Digest, Error, ErrorContext, ErrorKind, Phase, Scan};
+
+const HEADER: &str = "# charts-clean recording v1";
+
+///
+/// A file listed in a [`Recording`].
+#[derive(Debug, Clone)]
+pub struct RecordedFile {
+    /// Relative to the root
+    pub path: PathBuf,
+    pub size: u64,
+    /// Set if the scan that was recorded hashed it
+    pub digest: Option<Digest>,
+}
+
+///
+/// What a scan of a profile's archive found, written with `--record` so planning can be tried
+/// again later with `--replay`, under different policies, without touching the archive.  Only
+/// the paths and the sizes and hashes read from the filesystem are kept: names are parsed again
+/// when the recording is replayed, so a different source timezone or set of aliases applies to
+/// it as it would to a fresh scan.  Files whose names didn't parse as charts aren't recorded.
+///
+/// Stored as a tab separated file, one file per line: `file\t<size>\t<sha1>\t<path>`, and a
+/// `skipped\t<path>` line for each directory the scan couldn't read.
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub profile: String,
+    pub root: PathBuf,
+    /// When the scan was recorded, seconds since the epoch
+    pub created: u64,
+    pub files: Vec<RecordedFile>,
+    /// Relative to the root
+    pub skipped: Vec<PathBuf>,
+}
+
+impl Recording {
+    pub fn from_scan(profile: &str, root: &Path, scan: &Scan, created: u64) -> Recording {
+        let mut files: Vec<RecordedFile> = scan
+            .to_keep
+            .iter()
+            .chain(&scan.to_remove)
+            .chain(scan.companions.values().flatten())
+            .map(|f| RecordedFile {
+                path: f.relative_path(),
+                size: f.size(),
+                digest: f.digest(),
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let skipped = scan.skipped.iter().map(|dir| {
+            dir.strip_prefix(root)
+                .map_or_else(|_| dir.clone(), Path::to_path_buf)
+        });
+        Recording {
+            profile: profile.to_string(),
+            root: root.to_path_buf(),
+            created,
+            files,
+            skipped: skipped.collect(),
+        }
+    }
+
+    ///
+    /// Loads the recording at the path.  Unlike the state files, a missing recording is an
+    /// error, there's nothing to replay without it.
+    pub fn load(path: &Path) -> Result<Recording, Error> {
+        let mut recording = Recording::default();
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        for (idx, line) in text.lines().enumerate() {
+            let err = |msg: &str| {
+                let msg = format!("line {}: {msg}", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Setup).with_path(path)
+            };
+            if let Some(profile) = line.strip_prefix("#profile\t") {
+                recording.profile = profile.to_string();
+                continue;
+            }
+            if let Some(root) = line.strip_prefix("#root\t") {
+                recording.root = PathBuf::from(root);
+                continue;
+            }
+            if let Some(created) = line.strip_prefix("#created\t") {
+                recording.created = created.parse().map_err(|_| err("Invalid time"))?;
+                continue;
+            }
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["file", size, digest, file] => {
+                    let digest = match *digest {
+                        "" => None,
+                        hex => Some(Digest::from_hex(hex).ok_or_else(|| err("Invalid hash"))?),
+                    };
+                    recording.files.push(RecordedFile {
+                        path: PathBuf::from(file),
+                        size: size.parse().map_err(|_| err("Invalid size"))?,
+                        digest,
+                    });
+                }
+                ["skipped", dir] => recording.skipped.push(PathBuf::from(dir)),
+                _ => return Err(err("Unrecognized entry")),
+            }
+        }
+        Ok(recording)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = format!("{HEADER}\n");
+        let _ = writeln!(out, "#profile\t{}", self.profile);
+        let _ = writeln!(out, "#root\t{}", self.root.display());
+        let _ = writeln!(out, "#created\t{}", self.created);
+        for f in &self.files {
+            let digest = f.digest.map(|d| d.to_hex()).unwrap_or_default();
+            let _ = writeln!(out, "file\t{}\t{digest}\t{}", f.size, f.path.display());
+        }
+        for dir in &self.skipped {
+            let _ = writeln!(out, "skipped\t{}", dir.display());
+        }
+        std::fs::write(path, out).context(Phase::Setup, path)
+    }
+}
@@ -0,0 +1,306 @@
+use std::path::PathBuf;
+
+use irox_log::log::{error, info, warn};
+
+use crate::{
+    format_size, EditionGap, Error, GroupReport, MirrorReport, Provenance, PurgeReport, RunId,
+    SkippedFiles,
+};
+
+/// How many of each kind of skipped file the report lists, the rest are in the saved listing
+const SKIPPED_LISTED: usize = 10;
+
+///
+/// The outcome of running a single profile.
+#[derive(Debug, Default)]
+pub struct ProfileReport {
+    pub profile: String,
+    /// Where the profile's charts came from, as its config tags them
+    pub provenance: Provenance,
+    pub kept: usize,
+    pub removed: usize,
+    pub removed_bytes: u64,
+    /// The disk space the removals gave back, less than their size for sparse, hard linked or
+    /// reflinked files
+    pub reclaimed_bytes: u64,
+    /// Removal candidates left for a later run by the deletion cap
+    pub deferred: usize,
+    /// Removal candidates left in place until they're older than the profile's `cold_after`
+    pub aging: usize,
+    /// Charts withdrawn by their publisher
+    pub withdrawn: usize,
+    /// Files of withdrawn charts planned for removal, included in the removals
+    pub retired: usize,
+    /// Removal candidates held back because another application references them
+    pub protected: usize,
+    /// Removal candidates kept because the newest edition of their chart is a suspect size
+    pub retained: usize,
+    /// Removal candidates left alone because their attributes stop them being removed
+    pub locked: usize,
+    /// Removals the run ran out of time for, checkpointed for the next run
+    pub pending: usize,
+    /// Directories left out of the scan because they couldn't be read
+    pub unreadable: Vec<PathBuf>,
+    /// Missing editions among the versions found, which may have failed to download
+    pub gaps: Vec<EditionGap>,
+    /// Why the profile was only planned, if its root turned out to be read-only
+    pub read_only: Option<String>,
+    /// Removals of a manifest left alone because a file they depend on changed since the plan
+    pub skipped: usize,
+    /// Charts of a manifest planned again because their files changed since the plan
+    pub replanned: usize,
+    /// The removal candidates held back, by why, see [`ProfileReport::skipped_files`]
+    pub held_back: SkippedFiles,
+    /// Set if the profile's quarantine was checked for expired days
+    pub expiry: Option<PurgeReport>,
+    /// Set if the profile has a mirror to sync
+    pub mirror: Option<MirrorReport>,
+    /// Set if the profile rolls its report up by group
+    pub groups: Option<GroupReport>,
+    pub errors: Vec<Error>,
+}
+
+impl ProfileReport {
+    pub fn new(profile: impl Into<String>) -> ProfileReport {
+        ProfileReport {
+            profile: profile.into(),
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Every file that was neither kept nor removed, by why: the removal candidates held back,
+    /// and the files the errors name
+    pub fn skipped_files(&self) -> SkippedFiles {
+        let mut skipped = self.held_back.clone();
+        skipped.add_errors(&self.errors);
+        skipped
+    }
+}
+
+///
+/// The merged outcome of every profile in a run.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub run: Option<RunId>,
+    pub dry_run: bool,
+    pub profiles: Vec<ProfileReport>,
+}
+
+impl Report {
+    pub fn kept(&self) -> usize {
+        self.profiles.iter().map(|p| p.kept).sum()
+    }
+
+    pub fn removed(&self) -> usize {
+        self.profiles.iter().map(|p| p.removed).sum()
+    }
+
+    pub fn removed_bytes(&self) -> u64 {
+        self.profiles.iter().map(|p| p.removed_bytes).sum()
+    }
+
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.profiles.iter().map(|p| p.reclaimed_bytes).sum()
+    }
+
+    pub fn deferred(&self) -> usize {
+        self.profiles.iter().map(|p| p.deferred).sum()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Error> {
+        self.profiles.iter().flat_map(|p| p.errors.iter())
+    }
+
+    ///
+    /// Logs a summary line per profile and the totals.  Errors are left to the caller, as some
+    /// may be suppressed by the skip-list.
+    pub fn log(&self) {
+        if let Some(run) = &self.run {
+            info!("Run {run}");
+        }
+        if self.dry_run {
+            info!("Dry run, nothing was removed.");
+        }
+        for profile in &self.profiles {
+            info!(
+                "[{}] Kept {} files, removed {} files ({}, {} on disk), deferred {}, {} errors.",
+                profile.profile,
+                profile.kept,
+                profile.removed,
+                format_size(profile.removed_bytes),
+                format_size(profile.reclaimed_bytes),
+                profile.deferred,
+                profile.errors.len()
+            );
+        }
+        for profile in self.profiles.iter().filter(|p| !p.provenance.is_empty()) {
+            info!("[{}] Charts {}.", profile.profile, profile.provenance);
+        }
+        info!("Found {} files to keep.", self.kept());
+        info!(
+            "Found {} files to remove ({}, {} on disk).",
+            self.removed(),
+            format_size(self.removed_bytes()),
+            format_size(self.reclaimed_bytes())
+        );
+        if self.deferred() > 0 {
+            info!("Deferred {} files past the deletion cap.", self.deferred());
+        }
+        for profile in self.profiles.iter().filter(|p| p.aging > 0) {
+            info!(
+                "[{}] Left {} superseded files in place until they're older than cold_after.",
+                profile.profile, profile.aging
+            );
+        }
+        for profile in self.profiles.iter().filter(|p| p.withdrawn > 0) {
+            info!(
+                "[{}] {} charts have been withdrawn by their publisher, {} of their files retired.",
+                profile.profile, profile.withdrawn, profile.retired
+            );
+        }
+        for profile in self.profiles.iter().filter(|p| p.protected > 0) {
+            info!(
+                "[{}] Held back {} removals of files other applications reference.",
+                profile.profile, profile.protected
+            );
+        }
+        for profile in self.profiles.iter().filter(|p| p.retained > 0) {
+            info!(
+                "[{}] Kept {} previous editions of charts whose newest file is a suspect size.",
+                profile.profile, profile.retained
+            );
+        }
+        for profile in self.profiles.iter().filter(|p| p.locked > 0) {
+            info!(
+                "[{}] Skipped {} removals of immutable or read-only files, see --force-attrs.",
+                profile.profile, profile.locked
+            );
+        }
+        for profile in &self.profiles {
+            let skipped = profile.skipped_files();
+            if skipped.is_empty() {
+                continue;
+            }
+            let counts: Vec<String> = skipped
+                .iter()
+                .map(|(reason, files)| format!("{} {reason}", files.len()))
+                .collect();
+            info!(
+                "[{}] Neither kept nor removed {} files: {}.",
+                profile.profile,
+                skipped.len(),
+                counts.join(", ")
+            );
+            for (reason, files) in skipped.iter() {
+                info!("  {reason}, {}:", reason.description());
+                for file in files.iter().take(SKIPPED_LISTED) {
+                    info!("    {} ({})", file.path.display(), file.detail);
+                }
+                if files.len() > SKIPPED_LISTED {
+                    let more = files.len() - SKIPPED_LISTED;
+                    let name = &profile.profile;
+                    info!("    and {more} more, see skipped/{name}.tsv in the state dir");
+                }
+            }
+        }
+        for profile in self.profiles.iter().filter(|p| !p.unreadable.is_empty()) {
+            warn!(
+                "[{}] Skipped {} directories that couldn't be read, see --fail-on-skipped:",
+                profile.profile,
+                profile.unreadable.len()
+            );
+            for dir in &profile.unreadable {
+                warn!("  {}", dir.display());
+            }
+        }
+        for profile in &self.profiles {
+            if let Some(reason) = &profile.read_only {
+                warn!(
+                    "[{}] Planned without removing anything: {reason}.",
+                    profile.profile
+                );
+            }
+        }
+        for profile in self.profiles.iter().filter(|p| !p.gaps.is_empty()) {
+            warn!(
+                "[{}] Found {} gaps between the versions of a chart, check they downloaded:",
+                profile.profile,
+                profile.gaps.len()
+            );
+            for gap in &profile.gaps {
+                warn!("  {gap}");
+            }
+        }
+        for profile in self.profiles.iter().filter(|p| p.pending > 0) {
+            info!(
+                "[{}] Ran out of time, checkpointed {} removals for the next run.",
+                profile.profile, profile.pending
+            );
+        }
+        for profile in self
+            .profiles
+            .iter()
+            .filter(|p| p.skipped > 0 || p.replanned > 0)
+        {
+            info!(
+                "[{}] Skipped {} planned removals of changed files, replanned {} charts.",
+                profile.profile, profile.skipped, profile.replanned
+            );
+        }
+        for profile in &self.profiles {
+            let Some(expiry) = &profile.expiry else {
+                continue;
+            };
+            info!(
+                "[{}] Quarantine expiry: purged {} files ({}) from {} days.",
+                profile.profile,
+                expiry.files,
+                format_size(expiry.bytes),
+                expiry.days.len()
+            );
+        }
+        for profile in &self.profiles {
+            let Some(mirror) = &profile.mirror else {
+                continue;
+            };
+            info!(
+                "[{}] Mirror: copied {} files ({}), {} unchanged, removed {} files ({}).",
+                profile.profile,
+                mirror.copied,
+                format_size(mirror.copied_bytes),
+                mirror.unchanged,
+                mirror.removed,
+                format_size(mirror.removed_bytes)
+            );
+        }
+        for profile in &self.profiles {
+            let Some(groups) = &profile.groups else {
+                continue;
+            };
+            let keys: Vec<String> = groups.keys.iter().map(ToString::to_string).collect();
+            info!("[{}] By {}:", profile.profile, keys.join(" → "));
+            for (depth, name, group) in groups.walk() {
+                info!(
+                    "{:indent$}{name}: {} charts ({}), {} removed ({} freed)",
+                    "",
+                    group.charts,
+                    format_size(group.kept_bytes),
+                    group.removed,
+                    format_size(group.removed_bytes),
+                    indent = depth * 2
+                );
+            }
+        }
+    }
+
+    pub fn log_errors(errors: &[&Error]) {
+        if errors.is_empty() {
+            return;
+        }
+        error!("Encountered {} errors:", errors.len());
+        for e in errors {
+            error!("  {e}");
+        }
+    }
+}
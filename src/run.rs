@@ -0,0 +1,298 @@
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irox_log::log::{Level, Log, Metadata, Record};
+use irox_time::datetime::UTCDateTime;
+use irox_time::format::iso8601::BASIC_TIME_OF_DAY;
+use irox_tools::ansi_colors::{
+    FORMAT_COLOR_FG_BLUE, FORMAT_COLOR_FG_CYAN, FORMAT_COLOR_FG_MAGENTA, FORMAT_COLOR_FG_RED,
+    FORMAT_COLOR_FG_YELLOW, FORMAT_RESET,
+};
+use irox_tools::random::{Random, PRNG};
+use irox_tools::uuid::UUID;
+
+use crate::{Error, ErrorContext, Phase};
+
+///
+/// A unique ID for one invocation of the tool, recorded in its log lines, journal entries and
+/// report so everything a run did can be traced back to it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RunId(UUID);
+
+impl RunId {
+    pub fn new() -> RunId {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        let seed = nanos ^ (u64::from(std::process::id()) << 32);
+        RunId(UUID::from(Random::new_seed(seed).next_u128()))
+    }
+}
+
+impl Default for RunId {
+    fn default() -> Self {
+        RunId::new()
+    }
+}
+
+impl Display for RunId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for RunId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UUID::try_from(s)
+            .map(RunId)
+            .map_err(|_| format!("Invalid run ID: {s}"))
+    }
+}
+
+thread_local! {
+    static LOG_CONTEXT: RefCell<Option<LogContext>> = const { RefCell::new(None) };
+}
+
+///
+/// Log lines held back to be written together, so that one profile's lines come out in one
+/// block rather than interleaved with every other profile running alongside it.
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl LogBuffer {
+    fn push(&self, line: String) {
+        self.lines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(line);
+    }
+
+    ///
+    /// Writes out everything buffered so far, in the order it was logged
+    pub fn flush(&self) {
+        let lines = std::mem::take(&mut *self.lines.lock().unwrap_or_else(PoisonError::into_inner));
+        let mut stderr = std::io::stderr().lock();
+        for line in lines {
+            let _ = stderr.write_all(line.as_bytes());
+        }
+        let _ = stderr.flush();
+    }
+
+    ///
+    /// Appends everything buffered so far to the file, without its colors, leaving the lines
+    /// buffered to be flushed
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut text = String::new();
+        for line in self
+            .lines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            text.push_str(&strip_colors(line));
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(Phase::Setup, path)?;
+        file.write_all(text.as_bytes()).context(Phase::Setup, path)
+    }
+}
+
+///
+/// The line without its ANSI color sequences, ie `\x1b[34m`
+fn strip_colors(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find('\x1b') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match rest.find('m') {
+            Some(end) => rest = &rest[end + 1..],
+            None => rest = "",
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+///
+/// Who a thread logs as, and where its lines go.  Threads started by the tool's own workers,
+/// ie a scan's walkers, aren't named, so each takes a context from the thread that started it:
+/// its name with the worker's role added, `usgs-topo/walk-2`, and its log buffer if it has one.
+#[derive(Debug, Clone)]
+pub struct LogContext {
+    name: Arc<str>,
+    buffer: Option<LogBuffer>,
+    /// Tags the lines with this run rather than the process's, ie for a tenant of `serve`
+    run: Option<RunId>,
+}
+
+impl LogContext {
+    pub fn new(name: &str, buffer: Option<LogBuffer>) -> LogContext {
+        LogContext {
+            name: name.into(),
+            buffer,
+            run: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_run(mut self, run: RunId) -> LogContext {
+        self.run = Some(run);
+        self
+    }
+
+    ///
+    /// A context named within the calling thread's, ie a profile run by a tenant of `serve` as
+    /// `south-central/usgs-topo`.  Its lines go to the calling context's buffer if it has one,
+    /// and to `buffer` if not.
+    pub fn nested(name: &str, buffer: Option<LogBuffer>) -> LogContext {
+        LogContext::with_current(|context| match context {
+            Some(context) => LogContext {
+                name: format!("{}/{name}", context.name).into(),
+                buffer: context.buffer.clone().or(buffer),
+                run: context.run,
+            },
+            None => LogContext::new(name, buffer),
+        })
+    }
+
+    ///
+    /// A context for a worker the calling thread starts, to [`LogContext::enter`] on the
+    /// worker's thread
+    pub fn worker(role: impl Display) -> LogContext {
+        let (parent, buffer, run) = LogContext::with_current(|context| match context {
+            Some(context) => (
+                context.name.to_string(),
+                context.buffer.clone(),
+                context.run,
+            ),
+            None => (
+                std::thread::current().name().unwrap_or("").to_string(),
+                None,
+                None,
+            ),
+        });
+        let name = if parent.is_empty() {
+            role.to_string()
+        } else {
+            format!("{parent}/{role}")
+        };
+        LogContext {
+            run,
+            ..LogContext::new(&name, buffer)
+        }
+    }
+
+    ///
+    /// Logs as this context on the calling thread until the guard is dropped
+    pub fn enter(self) -> LogContextGuard {
+        let previous = LOG_CONTEXT.with(|current| current.replace(Some(self)));
+        LogContextGuard { previous }
+    }
+
+    fn with_current<T>(f: impl FnOnce(Option<&LogContext>) -> T) -> T {
+        LOG_CONTEXT.with(|current| f(current.borrow().as_ref()))
+    }
+}
+
+///
+/// Restores the thread's previous [`LogContext`] when dropped
+pub struct LogContextGuard {
+    previous: Option<LogContext>,
+}
+
+impl Drop for LogContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        LOG_CONTEXT.with(|current| current.replace(previous));
+    }
+}
+
+///
+/// The console logger, in the same format as irox-log's with the run ID added as a field:
+/// `[ThhMMss {LEVEL} {ThreadName} {Module} run={RunId}] {message}`.  The thread name is its
+/// [`LogContext`]'s if it has one.  Each line is written whole, with a single write.
+struct RunLogger {
+    max_level: Level,
+    run: RunId,
+}
+
+impl Log for RunLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.max_level >= metadata.level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = match record.level() {
+            Level::Error => format!("{FORMAT_COLOR_FG_RED}ERROR{FORMAT_RESET}"),
+            Level::Warn => format!("{FORMAT_COLOR_FG_YELLOW}WARN{FORMAT_RESET}"),
+            Level::Info => format!("{FORMAT_COLOR_FG_BLUE}INFO{FORMAT_RESET}"),
+            Level::Debug => format!("{FORMAT_COLOR_FG_MAGENTA}DEBUG{FORMAT_RESET}"),
+            Level::Trace => format!("{FORMAT_COLOR_FG_CYAN}TRACE{FORMAT_RESET}"),
+        };
+        let time = UTCDateTime::now().format(&BASIC_TIME_OF_DAY);
+        let module = record
+            .module_path()
+            .unwrap_or("")
+            .split("::")
+            .last()
+            .unwrap_or("");
+        LogContext::with_current(|context| {
+            let thread = std::thread::current();
+            let thread = match context {
+                Some(context) => &context.name,
+                None => thread.name().unwrap_or(""),
+            };
+            let run = context.and_then(|c| c.run).unwrap_or(self.run);
+            let line = format!(
+                "[{time} {level} {thread} {module} run={run}] {}\n",
+                record.args()
+            );
+            match context.and_then(|c| c.buffer.as_ref()) {
+                Some(buffer) => buffer.push(line),
+                None => {
+                    let _ = std::io::stderr().write_all(line.as_bytes());
+                }
+            }
+        });
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+///
+/// Initializes console logging at the level named in the environment variable (`warn` if it's
+/// unset or invalid), tagging every line with the run ID.
+pub fn init_logging(var: &str, run: RunId) {
+    let max_level = std::env::var(var)
+        .ok()
+        .and_then(|level| Level::from_str(&level).ok())
+        .unwrap_or(Level::Warn);
+    irox_log::log::set_max_level(max_level.to_level_filter());
+    let logger = Box::leak(Box::new(RunLogger { max_level, run }));
+    if let Err(e) = irox_log::log::set_logger(logger) {
+        eprintln!("Error setting logger: {e:?}");
+    }
+}
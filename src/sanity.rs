@@ -0,0 +1,93 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{parse_size, FoundFile};
+
+///
+/// The sizes a chart file of one type is expected to be, ie a GeoTIFF quad between 10M and
+/// 500M.  Written `EXT MIN-MAX`, either bound optional: `tif 10M-500M`, `pdf 1M-`.
+///
+/// A newest edition outside its range is suspect, most likely a truncated or failed download,
+/// so the edition before it is kept alongside it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SizeRange {
+    /// Matched against file extensions ignoring case, without the dot
+    pub extension: String,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl SizeRange {
+    pub fn applies_to(&self, file: &FoundFile) -> bool {
+        Path::new(file.file_name())
+            .extension()
+            .is_some_and(|ext| ext.to_string_lossy().eq_ignore_ascii_case(&self.extension))
+    }
+
+    pub fn contains(&self, size: u64) -> bool {
+        self.min.is_none_or(|min| size >= min) && self.max.is_none_or(|max| size <= max)
+    }
+
+    ///
+    /// The first of the ranges that applies to the file, if the file's size is outside it
+    pub fn violated<'a>(ranges: &'a [SizeRange], file: &FoundFile) -> Option<&'a SizeRange> {
+        ranges
+            .iter()
+            .find(|range| range.applies_to(file))
+            .filter(|range| !range.contains(file.size()))
+    }
+}
+
+impl FromStr for SizeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid size range {s}, expected EXT MIN-MAX, ie tif 10M-500M");
+        let (extension, range) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(invalid)?;
+        let (min, max) = range.trim().split_once('-').ok_or_else(invalid)?;
+        let bound = |value: &str| match value.trim() {
+            "" => Ok(None),
+            value => parse_size(value).map(Some).ok_or_else(invalid),
+        };
+        let range = SizeRange {
+            extension: extension.trim_start_matches('.').to_string(),
+            min: bound(min)?,
+            max: bound(max)?,
+        };
+        match (range.min, range.max) {
+            (None, None) => Err(invalid()),
+            (Some(min), Some(max)) if min > max => Err(invalid()),
+            _ => Ok(range),
+        }
+    }
+}
+
+impl Display for SizeRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let min = self.min.map(exact_size).unwrap_or_default();
+        let max = self.max.map(exact_size).unwrap_or_default();
+        write!(f, "{} {min}-{max}", self.extension)
+    }
+}
+
+///
+/// The size in the largest suffix it's a whole number of, so that it parses back unchanged
+fn exact_size(bytes: u64) -> String {
+    const SUFFIXES: [(&str, u64); 4] = [
+        ("T", 1 << 40),
+        ("G", 1 << 30),
+        ("M", 1 << 20),
+        ("K", 1 << 10),
+    ];
+    match SUFFIXES
+        .iter()
+        .find(|(_, mult)| bytes > 0 && bytes.is_multiple_of(*mult))
+    {
+        Some((suffix, mult)) => format!("{}{suffix}", bytes / mult),
+        None => bytes.to_string(),
+    }
+}
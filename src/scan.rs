@@ -0,0 +1,784 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+use irox_log::log::{debug, warn};
+use irox_tools::sha1::SHA1;
+
+use crate::download::is_partial;
+use crate::hash::hash_file;
+use crate::timestamp::UtcOffset;
+use crate::{
+    CancelToken, ChartAliases, ChartId, ChartVersion, CompanionRule, Digest, DirCache, Error,
+    ErrorContext, ErrorKind, Event, FixedFields, FoundFile, Interner, LogContext, NoProgress,
+    Phase, Progress, Recording, Throttle, MARKER_SUFFIX, OLD_DIR,
+};
+
+///
+/// The results of a scan: the newest edition of each chart, the superseded editions (ordered by
+/// path), and any per-file errors and unreadable directories encountered along the way.
+#[derive(Default)]
+pub struct Scan {
+    pub to_keep: BTreeSet<FoundFile>,
+    pub to_remove: Vec<FoundFile>,
+    /// The companions of the kept and superseded files, by their primary's full path, see
+    /// [`CompanionRule`]
+    pub companions: BTreeMap<PathBuf, Vec<FoundFile>>,
+    pub errors: Vec<Error>,
+    /// Directories that couldn't be listed for lack of permission, and were left out rather
+    /// than failing the scan
+    pub skipped: Vec<PathBuf>,
+    /// The key of each directory listed, relative to the root, if the scan had a [`DirCache`]
+    pub dir_keys: BTreeMap<PathBuf, Digest>,
+}
+
+impl Scan {
+    ///
+    /// Folds another scan, ie of another part of the tree, into this one.  Which edition of a
+    /// chart found in both is kept doesn't depend on the order scans are merged in.
+    pub fn merge(&mut self, other: Scan) {
+        if self.to_keep.is_empty() {
+            self.to_keep = other.to_keep;
+        } else {
+            for file in other.to_keep {
+                process_file(file, self);
+            }
+        }
+        self.to_remove.extend(other.to_remove);
+        for (primary, companions) in other.companions {
+            self.companions
+                .entry(primary)
+                .or_default()
+                .extend(companions);
+        }
+        self.errors.extend(other.errors);
+        self.skipped.extend(other.skipped);
+        self.dir_keys.extend(other.dir_keys);
+        self.to_remove.sort_by(FoundFile::cmp_path);
+    }
+}
+
+///
+/// Files waiting between two stages of the scan.  Bounded, so a slow stage holds the ones before
+/// it back instead of letting found files pile up in memory.
+const CHANNEL_BOUND: usize = 1024;
+const DEFAULT_WALKERS: usize = 4;
+
+///
+/// A file listed by a walker, waiting to be parsed: the directory it's in relative to the root,
+/// shared with the rest of the files listed there, its name, and what the walker already knows
+/// of it if it has a [`DirCache`]
+type Entry = (Arc<Path>, OsString, Option<Known>);
+
+///
+/// What a walker with a [`DirCache`] knows of a file it listed: its size, and if its directory
+/// hasn't changed since the cache was made, its hash and what its name parsed as, if the cache
+/// has them.
+struct Known {
+    size: u64,
+    digest: Option<Digest>,
+    parsed: Option<(ChartId, ChartVersion)>,
+}
+
+///
+/// Walks a directory tree, parsing every file found into a [`FoundFile`] and keeping only the
+/// newest version of each chart.
+///
+/// The scan runs as a pipeline: walker threads list directories, parsers turn the files they
+/// find into charts, optionally hashers read each one, and the calling thread plans what to keep
+/// as they arrive.  The stages are joined by bounded channels, so hashing never stalls the
+/// walk by more than a channel's worth of files, and the walk never runs away from the hashing.
+/// With more than one planner, each top-level directory under the root is planned on one of
+/// them and their scans merged at the end, giving the same scan as a single planner would.
+///
+/// The files of a scan share one copy of the root and of each chart name and source, and the
+/// files found in a directory one copy of its path under the root, so that a scan of millions of
+/// files holds little more than their names.
+///
+/// Listing a directory costs one call per batch of entries, and on filesystems that report each
+/// entry's type with it (most do, NFS included) that's all the walk needs.  Only the files whose
+/// names parse as charts are stat'ed, for their size, by the parsers.  With a [`DirCache`] the
+/// walkers stat every file instead, to tell whether the directory changed since the cache was
+/// made, and the files of one that didn't are taken from the cache rather than parsed and hashed
+/// again.
+pub struct Scanner {
+    root: Arc<Path>,
+    source_tz: UtcOffset,
+    aliases: ChartAliases,
+    fixed_fields: Option<FixedFields>,
+    companions: Vec<CompanionRule>,
+    dir_cache: Option<Arc<DirCache>>,
+    throttle: Arc<Throttle>,
+    progress: Arc<dyn Progress>,
+    walkers: usize,
+    hashers: usize,
+    planners: usize,
+}
+
+impl Scanner {
+    pub fn new(root: impl Into<PathBuf>) -> Scanner {
+        Scanner {
+            root: Arc::from(root.into()),
+            source_tz: UtcOffset::UTC,
+            aliases: ChartAliases::default(),
+            fixed_fields: None,
+            companions: Vec::new(),
+            dir_cache: None,
+            throttle: Arc::default(),
+            progress: Arc::new(NoProgress),
+            walkers: DEFAULT_WALKERS,
+            hashers: 0,
+            planners: 1,
+        }
+    }
+
+    ///
+    /// Sets the timezone assumed for file names without a zone designator
+    #[must_use]
+    pub fn with_source_tz(mut self, source_tz: UtcOffset) -> Scanner {
+        self.source_tz = source_tz;
+        self
+    }
+
+    ///
+    /// Files every chart an alias matches under the identity it resolves to
+    #[must_use]
+    pub fn with_aliases(mut self, aliases: ChartAliases) -> Scanner {
+        self.aliases = aliases;
+        self
+    }
+
+    ///
+    /// Reads names by the position of their fields rather than their delimiters, if set
+    #[must_use]
+    pub fn with_fixed_fields(mut self, fixed_fields: Option<FixedFields>) -> Scanner {
+        self.fixed_fields = fixed_fields;
+        self
+    }
+
+    ///
+    /// Files the companions of a bundle's primary file with it, rather than as charts
+    #[must_use]
+    pub fn with_companions(mut self, companions: Vec<CompanionRule>) -> Scanner {
+        self.companions = companions;
+        self
+    }
+
+    ///
+    /// Takes the files of directories that haven't changed since the cache was made from it, as
+    /// they were parsed and hashed, and keys every directory listed for the next one, see
+    /// [`Scan::dir_keys`]
+    #[must_use]
+    pub fn with_dir_cache(mut self, cache: Arc<DirCache>) -> Scanner {
+        self.dir_cache = Some(cache);
+        self
+    }
+
+    ///
+    /// Paces the directory listings issued by the scan, and the reads of any hashing
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Scanner {
+        self.throttle = throttle;
+        self
+    }
+
+    ///
+    /// Reports each directory entered and chart found
+    #[must_use]
+    pub fn with_progress(mut self, progress: Arc<dyn Progress>) -> Scanner {
+        self.progress = progress;
+        self
+    }
+
+    ///
+    /// How many directories are listed at once, and files parsed at once
+    #[must_use]
+    pub fn with_walkers(mut self, walkers: usize) -> Scanner {
+        self.walkers = walkers.max(1);
+        self
+    }
+
+    ///
+    /// Hashes every file found with this many threads, see [`FoundFile::digest`].  Nothing is
+    /// hashed with none, the default.
+    #[must_use]
+    pub fn with_hashers(mut self, hashers: usize) -> Scanner {
+        self.hashers = hashers;
+        self
+    }
+
+    ///
+    /// Plans what to keep on this many threads, one top-level directory per thread at a time
+    #[must_use]
+    pub fn with_planners(mut self, planners: usize) -> Scanner {
+        self.planners = planners.max(1);
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    ///
+    /// The key of how the scanner parses names, which only its timezone, fixed fields and aliases
+    /// change, so that a [`DirCache`] made by one that parsed them otherwise isn't trusted
+    pub fn parser_key(&self) -> Digest {
+        let mut parser = self.source_tz.to_string();
+        if let Some(fields) = &self.fixed_fields {
+            parser.push_str(&format!("\nfixed_fields\t{fields}"));
+        }
+        for alias in self.aliases.iter() {
+            parser.push_str(&format!("\nalias\t{alias}"));
+        }
+        let mut hasher = SHA1::default();
+        hasher.write(parser.as_bytes());
+        Digest::from(hasher.finish())
+    }
+
+    ///
+    /// Scans the tree, checking the token as it goes.  A cancelled scan is abandoned rather than
+    /// returned half done.
+    pub fn run(&self, cancel: &CancelToken) -> Result<Scan, Error> {
+        let queue = DirQueue::new(PathBuf::new());
+        let dir_keys = Mutex::new(BTreeMap::new());
+        let interner = Interner::default();
+        let (entry_tx, entry_rx) = sync_channel::<Entry>(CHANNEL_BOUND);
+        let (hash_tx, hash_rx) = sync_channel::<FoundFile>(CHANNEL_BOUND);
+        let (found_tx, found_rx) = sync_channel::<Result<FoundFile, Error>>(CHANNEL_BOUND);
+        let (entry_rx, hash_rx) = (Mutex::new(entry_rx), Mutex::new(hash_rx));
+        let hash_tx = (self.hashers > 0).then_some(hash_tx);
+        let mut scan = std::thread::scope(|scope| {
+            let (queue, interner, entry_rx, hash_rx) = (&queue, &interner, &entry_rx, &hash_rx);
+            let dir_keys = &dir_keys;
+            for n in 0..self.walkers {
+                let (entries, found) = (entry_tx.clone(), found_tx.clone());
+                let log = LogContext::worker(format!("walk-{n}"));
+                scope.spawn(move || {
+                    let _log = log.enter();
+                    self.walk(queue, dir_keys, &entries, &found, cancel)
+                });
+            }
+            for n in 0..self.walkers {
+                let (hash, found) = (hash_tx.clone(), found_tx.clone());
+                let log = LogContext::worker(format!("parse-{n}"));
+                scope.spawn(move || {
+                    let _log = log.enter();
+                    self.parse(entry_rx, interner, hash.as_ref(), &found)
+                });
+            }
+            for n in 0..self.hashers {
+                let found = found_tx.clone();
+                let log = LogContext::worker(format!("hash-{n}"));
+                scope.spawn(move || {
+                    let _log = log.enter();
+                    self.hash(hash_rx, &found)
+                });
+            }
+            // Only the stages hold senders now, so the results end when the last stage does
+            drop((entry_tx, hash_tx, found_tx));
+            let (plan_txs, planners): (Vec<_>, Vec<_>) = (0..self.planners)
+                .map(|n| {
+                    let (plan_tx, plan_rx) = sync_channel::<FoundFile>(CHANNEL_BOUND);
+                    let log = LogContext::worker(format!("plan-{n}"));
+                    let planner = scope.spawn(move || {
+                        let _log = log.enter();
+                        plan(plan_rx)
+                    });
+                    (plan_tx, planner)
+                })
+                .unzip();
+            let mut scan = Scan::default();
+            let (mut scanned, mut companions) = (0, Vec::new());
+            for found in found_rx {
+                match found {
+                    Ok(file) => {
+                        scanned += 1;
+                        self.progress.event(&Event::FileScanned {
+                            file: &file,
+                            scanned,
+                        });
+                        if self.is_companion(&file) {
+                            companions.push(file);
+                            continue;
+                        }
+                        let planner = &plan_txs[partition(&file) % plan_txs.len()];
+                        let _ = planner.send(file);
+                    }
+                    Err(e) => match unreadable_dir(&e) {
+                        Some(dir) => {
+                            warn!("Skipping {}, it can't be read", dir.display());
+                            scan.skipped.push(dir.to_path_buf());
+                        }
+                        None => scan.errors.push(e),
+                    },
+                }
+            }
+            drop(plan_txs);
+            scan.skipped.sort();
+            for planner in planners {
+                match planner.join() {
+                    Ok(planned) => scan.merge(planned),
+                    Err(panic) => std::panic::resume_unwind(panic),
+                }
+            }
+            self.attach_companions(&mut scan, companions);
+            scan
+        });
+        if let Some(e) = queue.into_aborted() {
+            return Err(e);
+        }
+        debug!("Interned {} chart names and sources", interner.len());
+        scan.dir_keys = dir_keys
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(scan)
+    }
+
+    ///
+    /// Parses the name of the file at `path` under the root as a scan does, filed under its
+    /// alias, without reading it
+    pub fn parse_name(&self, path: &Path) -> Result<FoundFile, Error> {
+        let dir = Arc::from(path.parent().unwrap_or(Path::new("")));
+        let file_name = path.file_name().unwrap_or_default().to_os_string();
+        let file = self.parse_in(dir, file_name, &Interner::default())?;
+        Ok(self.aliases.file(file))
+    }
+
+    fn parse_in(
+        &self,
+        dir: Arc<Path>,
+        file_name: OsString,
+        interner: &Interner,
+    ) -> Result<FoundFile, Error> {
+        let root = self.root.clone();
+        match &self.fixed_fields {
+            Some(fields) => fields.parse_in(root, dir, file_name, self.source_tz, interner),
+            None => FoundFile::parse_in(root, dir, file_name, self.source_tz, interner),
+        }
+    }
+
+    ///
+    /// Plans a recorded scan as if its files had been found under this scanner's root, parsing
+    /// their names and filing them under their aliases afresh.  Nothing under the root is read.
+    pub fn replay(&self, recording: &Recording) -> Scan {
+        let interner = Interner::default();
+        let (mut scan, mut companions) = (Scan::default(), Vec::new());
+        for recorded in &recording.files {
+            let dir = Arc::from(recorded.path.parent().unwrap_or(Path::new("")));
+            let file_name = recorded.path.file_name().unwrap_or_default().to_os_string();
+            match self.parse_in(dir, file_name, &interner) {
+                Ok(file) => {
+                    let file = file.with_size(recorded.size);
+                    let file = match recorded.digest {
+                        Some(digest) => file.with_digest(digest),
+                        None => file,
+                    };
+                    let file = self.aliases.file(file);
+                    if self.is_companion(&file) {
+                        companions.push(file);
+                    } else {
+                        process_file(file, &mut scan);
+                    }
+                }
+                Err(e) => scan.errors.push(e),
+            }
+        }
+        self.attach_companions(&mut scan, companions);
+        scan.to_remove.sort_by(FoundFile::cmp_path);
+        scan.skipped = recording
+            .skipped
+            .iter()
+            .map(|dir| self.root.join(dir))
+            .collect();
+        scan
+    }
+
+    fn is_companion(&self, file: &FoundFile) -> bool {
+        let name = file.file_name().to_string_lossy();
+        !CompanionRule::primaries(&self.companions, &name).is_empty()
+    }
+
+    ///
+    /// Files each companion under its primary, or plans it as a chart of its own if its primary
+    /// wasn't found, ie a `.hdr` left without its `.img`
+    fn attach_companions(&self, scan: &mut Scan, companions: Vec<FoundFile>) {
+        if companions.is_empty() {
+            return;
+        }
+        // By directory and lowercased name, as the patterns ignore case
+        let key = |dir: &Path, name: &str| (dir.to_path_buf(), name.to_lowercase());
+        let files: BTreeMap<(PathBuf, String), PathBuf> = scan
+            .to_keep
+            .iter()
+            .chain(&scan.to_remove)
+            .map(|f| {
+                (
+                    key(f.dir(), &f.file_name().to_string_lossy()),
+                    f.full_path(),
+                )
+            })
+            .collect();
+        for file in companions {
+            let name = file.file_name().to_string_lossy();
+            let primary = CompanionRule::primaries(&self.companions, &name)
+                .into_iter()
+                .find_map(|primary| files.get(&key(file.dir(), &primary)));
+            match primary {
+                Some(primary) => scan
+                    .companions
+                    .entry(primary.clone())
+                    .or_default()
+                    .push(file),
+                None => process_file(file, scan),
+            }
+        }
+        scan.to_remove.sort_by(FoundFile::cmp_path);
+        for files in scan.companions.values_mut() {
+            files.sort_by(FoundFile::cmp_path);
+        }
+    }
+
+    ///
+    /// Lists directories off the queue until there are none left, sending the files found on
+    fn walk(
+        &self,
+        queue: &DirQueue,
+        dir_keys: &Mutex<BTreeMap<PathBuf, Digest>>,
+        entries: &SyncSender<Entry>,
+        found: &SyncSender<Result<FoundFile, Error>>,
+        cancel: &CancelToken,
+    ) {
+        while let Some(dir) = queue.next() {
+            match cancel.check(Phase::Scan) {
+                Ok(()) => self.list_dir(&dir, queue, dir_keys, entries, found),
+                Err(e) => queue.abort(e),
+            }
+            queue.done();
+        }
+    }
+
+    ///
+    /// Lists the directory at `relative` to the root
+    fn list_dir(
+        &self,
+        relative: &Path,
+        queue: &DirQueue,
+        dir_keys: &Mutex<BTreeMap<PathBuf, Digest>>,
+        entries: &SyncSender<Entry>,
+        found: &SyncSender<Result<FoundFile, Error>>,
+    ) {
+        // Joining an empty path would leave the root with a trailing slash
+        let path = if relative.as_os_str().is_empty() {
+            self.root.to_path_buf()
+        } else {
+            self.root.join(relative)
+        };
+        let path = path.as_path();
+        self.progress.event(&Event::EnteringDir(path));
+        let shared: Arc<Path> = Arc::from(relative);
+        self.throttle.request();
+        let dirs = match std::fs::read_dir(path).context(Phase::Scan, path) {
+            Ok(dirs) => dirs,
+            Err(e) => {
+                let _ = found.send(Err(e));
+                return;
+            }
+        };
+        // With a cache, the files are held back until the directory's key is known
+        let mut listed = Vec::new();
+        for dir in dirs {
+            let entry = dir.context(Phase::Scan, path).and_then(|dir| {
+                // Comes with the listing, unless the filesystem leaves it out and it takes a stat
+                let ty = dir.file_type().context(Phase::Scan, &dir.path())?;
+                let name = dir.file_name();
+                // Neither a marker nor a download that hasn't finished is a new edition
+                let marker = name.as_encoded_bytes().ends_with(MARKER_SUFFIX.as_bytes());
+                if marker || is_partial(&name) {
+                    return Ok(None);
+                }
+                if !ty.is_dir() {
+                    if self.dir_cache.is_none() {
+                        return Ok(Some((shared.clone(), name, None)));
+                    }
+                    let meta = dir.metadata().context(Phase::Scan, &dir.path())?;
+                    listed.push((name, meta.len(), meta.modified().ok()));
+                    return Ok(None);
+                }
+                if name == OLD_DIR {
+                    debug!("Skipping superseded editions in {}", dir.path().display());
+                } else {
+                    queue.push(relative.join(name));
+                }
+                Ok(None)
+            });
+            // A send only fails once the scan has stopped listening
+            let _ = match entry {
+                Ok(Some(entry)) => entries.send(entry).is_ok(),
+                Ok(None) => true,
+                Err(e) => found.send(Err(e)).is_ok(),
+            };
+        }
+        if let Some(cache) = &self.dir_cache {
+            self.send_listed(cache, relative, &shared, listed, dir_keys, entries);
+        }
+    }
+
+    ///
+    /// Sends on the files listed in a directory once its key is known, along with their sizes,
+    /// and their hashes and what they parsed as if it hasn't changed since the cache was made
+    fn send_listed(
+        &self,
+        cache: &DirCache,
+        relative: &Path,
+        shared: &Arc<Path>,
+        listed: Vec<(OsString, u64, Option<SystemTime>)>,
+        dir_keys: &Mutex<BTreeMap<PathBuf, Digest>>,
+        entries: &SyncSender<Entry>,
+    ) {
+        let sizes: Vec<(OsString, u64)> = listed
+            .iter()
+            .map(|(name, size, _)| (name.clone(), *size))
+            .collect();
+        let key = DirCache::key(listed);
+        let cached = cache.get(relative, key);
+        if cached.is_some() {
+            debug!("{} is unchanged since the last scan", relative.display());
+        }
+        let mut keys = dir_keys.lock().unwrap_or_else(PoisonError::into_inner);
+        keys.insert(relative.to_path_buf(), key);
+        drop(keys);
+        let parsed_alike = cache.parser() == Some(self.parser_key());
+        for (name, size) in sizes {
+            let file = cached.and_then(|dir| dir.file(&name.to_string_lossy()));
+            let known = Known {
+                size,
+                digest: file.and_then(|f| f.digest),
+                parsed: file
+                    .filter(|_| parsed_alike)
+                    .map(|f| (f.id.clone(), f.version)),
+            };
+            if entries.send((shared.clone(), name, Some(known))).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn parse(
+        &self,
+        entries: &Mutex<Receiver<Entry>>,
+        interner: &Interner,
+        hash: Option<&SyncSender<FoundFile>>,
+        found: &SyncSender<Result<FoundFile, Error>>,
+    ) {
+        loop {
+            let next = entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+            let Ok((dir, file_name, known)) = next else {
+                return;
+            };
+            let (known, parsed) = match known {
+                Some(known) => (Some((known.size, known.digest)), known.parsed),
+                None => (None, None),
+            };
+            let file = match (parsed, known) {
+                // Filed under its alias when it was cached
+                (Some((id, version)), Some((size, _))) => {
+                    let source = id.source().map(|source| interner.intern(source));
+                    let id = ChartId::new(source, interner.intern(id.name()), id.scale());
+                    let root = self.root.clone();
+                    Ok(FoundFile::in_dir(id, version, root, dir, file_name).with_size(size))
+                }
+                _ => self.parse_in(dir, file_name, interner).and_then(|file| {
+                    let size = match known {
+                        Some((size, _)) => size,
+                        None => {
+                            let path = file.full_path();
+                            std::fs::symlink_metadata(&path)
+                                .context(Phase::Scan, &path)?
+                                .len()
+                        }
+                    };
+                    Ok(self.aliases.file(file.with_size(size)))
+                }),
+            };
+            // A cached hash is only taken where the scan would have hashed the file itself
+            let cached = known.and_then(|(_, digest)| digest);
+            let _ = match (file, hash, cached) {
+                (Ok(file), Some(_), Some(digest)) => {
+                    found.send(Ok(file.with_digest(digest))).is_ok()
+                }
+                (Ok(file), Some(hash), None) => hash.send(file).is_ok(),
+                (file, _, _) => found.send(file).is_ok(),
+            };
+        }
+    }
+
+    fn hash(
+        &self,
+        files: &Mutex<Receiver<FoundFile>>,
+        found: &SyncSender<Result<FoundFile, Error>>,
+    ) {
+        loop {
+            let next = files.lock().unwrap_or_else(PoisonError::into_inner).recv();
+            let Ok(file) = next else {
+                return;
+            };
+            self.throttle.transfer(file.size());
+            // A file that can't be read is still a version of its chart, just an unhashed one
+            let path = file.full_path();
+            let file = match hash_file(&path).context(Phase::Scan, &path) {
+                Ok(digest) => file.with_digest(digest),
+                Err(e) => {
+                    let _ = found.send(Err(e));
+                    file
+                }
+            };
+            let _ = found.send(Ok(file));
+        }
+    }
+}
+
+///
+/// The directories waiting to be listed, shared by the walkers.  The walk is over once there are
+/// none waiting and no walker is still listing one, as that's the only way more turn up.
+struct DirQueue {
+    state: Mutex<DirQueueState>,
+    changed: Condvar,
+}
+
+struct DirQueueState {
+    dirs: Vec<PathBuf>,
+    listing: usize,
+    aborted: Option<Error>,
+}
+
+impl DirQueue {
+    fn new(root: PathBuf) -> DirQueue {
+        DirQueue {
+            state: Mutex::new(DirQueueState {
+                dirs: vec![root],
+                listing: 0,
+                aborted: None,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DirQueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    ///
+    /// The next directory to list, waiting for one if others are still being listed.  Every
+    /// directory taken must be handed back with [`DirQueue::done`].
+    fn next(&self) -> Option<PathBuf> {
+        let mut state = self.lock();
+        loop {
+            if state.aborted.is_some() {
+                return None;
+            }
+            if let Some(dir) = state.dirs.pop() {
+                state.listing += 1;
+                return Some(dir);
+            }
+            if state.listing == 0 {
+                return None;
+            }
+            state = self
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn push(&self, dir: PathBuf) {
+        self.lock().dirs.push(dir);
+        self.changed.notify_one();
+    }
+
+    fn done(&self) {
+        let mut state = self.lock();
+        state.listing -= 1;
+        if state.listing == 0 && state.dirs.is_empty() {
+            self.changed.notify_all();
+        }
+    }
+
+    ///
+    /// Stops the walk, keeping the first error it was stopped for
+    fn abort(&self, e: Error) {
+        self.lock().aborted.get_or_insert(e);
+        self.changed.notify_all();
+    }
+
+    fn into_aborted(self) -> Option<Error> {
+        self.state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .aborted
+    }
+}
+
+///
+/// Which planner a file goes to: every file under the same top-level directory goes to the same
+/// one, so that a chart kept in a single directory is settled by a single planner
+fn partition(file: &FoundFile) -> usize {
+    let mut hasher = DefaultHasher::new();
+    file.dir().components().next().hash(&mut hasher);
+    hasher.finish() as usize
+}
+
+///
+/// The directory the error is about, if it's one the scan wasn't allowed to list
+fn unreadable_dir(e: &Error) -> Option<&Path> {
+    let denied = match e.kind() {
+        ErrorKind::IOError(io) => io.kind() == std::io::ErrorKind::PermissionDenied,
+        _ => false,
+    };
+    e.path()
+        .filter(|path| denied && e.phase() == Phase::Scan && path.is_dir())
+}
+
+///
+/// Plans the files sent to it until there are no more
+fn plan(files: Receiver<FoundFile>) -> Scan {
+    let mut scan = Scan::default();
+    for file in files {
+        process_file(file, &mut scan);
+    }
+    scan
+}
+
+///
+/// Keeps the newer of the file and the kept version of its chart, if any.  Versions that tie are
+/// settled by path, so the same tree gives the same scan however the walk went.
+fn process_file(found_file: FoundFile, scan: &mut Scan) {
+    let to_keep = &mut scan.to_keep;
+    let to_remove = &mut scan.to_remove;
+    if let Some(old) = to_keep.take(&found_file) {
+        let newer = found_file
+            .version()
+            .cmp(old.version())
+            .then_with(|| old.cmp_path(&found_file))
+            == Ordering::Greater;
+        if newer {
+            debug!("Replacing existing {old} with {found_file}");
+            to_keep.insert(found_file);
+            to_remove.push(old);
+        } else {
+            debug!("Not replacing existing {old} with {found_file}");
+            to_remove.push(found_file);
+            to_keep.insert(old);
+        }
+    } else {
+        debug!("Found new file {found_file}");
+        to_keep.insert(found_file);
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::Path;
+
+use ed25519_dalek::{Signer, Verifier};
+use irox_tools::hex::{from_hex_str, to_hex_str_lower};
+
+use crate::{Error, ErrorContext, ErrorKind, Phase};
+
+const SIGNING_HEADER: &str = "# charts-clean signing key v1";
+const VERIFYING_HEADER: &str = "# charts-clean verifying key v1";
+
+fn key_error(msg: &str, path: &Path) -> Error {
+    Error::new(ErrorKind::ConfigError(msg.to_string()), Phase::Setup).with_path(path)
+}
+
+///
+/// Reads the key in the file at `path`, a header line followed by the key in hex
+fn read_key(path: &Path, header: &str) -> Result<[u8; 32], Error> {
+    let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some(header) {
+        let msg = format!("not a key made by charts-clean keygen, expected {header}");
+        return Err(key_error(&msg, path));
+    }
+    let key = lines.next().and_then(|hex| from_hex_str(hex).ok());
+    let key = key.and_then(|key| <[u8; 32]>::try_from(key.as_ref()).ok());
+    key.ok_or_else(|| key_error("invalid key", path))
+}
+
+fn write_key(path: &Path, header: &str, key: &[u8], private: bool) -> Result<(), Error> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let mut file = options.open(path).context(Phase::Setup, path)?;
+    let text = format!("{header}\n{}\n", to_hex_str_lower(key));
+    file.write_all(text.as_bytes()).context(Phase::Setup, path)
+}
+
+///
+/// The private half of an ed25519 key pair, kept by whoever reviews and signs plans and nowhere
+/// else.  `charts-clean keygen --output plan.key` makes one, along with the [`VerifyingKey`] in
+/// `plan.key.pub` that the machines applying the plans are given instead, which can check a
+/// signature but not make one.
+pub struct SigningKey(ed25519_dalek::SigningKey);
+
+impl SigningKey {
+    ///
+    /// A new key, from the operating system's random source
+    pub fn generate() -> Result<SigningKey, Error> {
+        let mut secret = [0u8; 32];
+        getrandom::getrandom(&mut secret).map_err(|e| {
+            let e = std::io::Error::other(e.to_string());
+            Error::new(ErrorKind::IOError(e), Phase::Setup)
+        })?;
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&secret)))
+    }
+
+    pub fn load(path: &Path) -> Result<SigningKey, Error> {
+        let secret = read_key(path, SIGNING_HEADER)?;
+        Ok(SigningKey(ed25519_dalek::SigningKey::from_bytes(&secret)))
+    }
+
+    ///
+    /// Writes the key to a new file only its owner can read
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        write_key(path, SIGNING_HEADER, self.0.as_bytes(), true)
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.verifying_key())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature(self.0.sign(message).to_bytes())
+    }
+}
+
+///
+/// The public half of a [`SigningKey`], which checks its signatures.
+pub struct VerifyingKey(ed25519_dalek::VerifyingKey);
+
+impl VerifyingKey {
+    pub fn load(path: &Path) -> Result<VerifyingKey, Error> {
+        let key = read_key(path, VERIFYING_HEADER)?;
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&key);
+        Ok(VerifyingKey(
+            key.map_err(|_| key_error("invalid key", path))?,
+        ))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        write_key(path, VERIFYING_HEADER, self.0.as_bytes(), false)
+    }
+
+    ///
+    /// True if the signature is the signing key's signature of the message
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        let signature = ed25519_dalek::Signature::from_bytes(&signature.0);
+        self.0.verify(message, &signature).is_ok()
+    }
+}
+
+///
+/// An ed25519 signature, made by a [`SigningKey`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Signature([u8; 64]);
+
+impl Signature {
+    pub fn from_hex(hex: &str) -> Option<Signature> {
+        let bytes = from_hex_str(hex).ok()?;
+        Some(Signature(bytes.as_ref().try_into().ok()?))
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex_str_lower(&self.0)
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing::TempDir;
+    use crate::{Digest, Manifest, ManifestAction, ManifestEntry};
+
+    fn manifest() -> Manifest {
+        Manifest {
+            profile: "usgs-topo".to_string(),
+            root: PathBuf::from("/chonko-1/chartdata/USGS-Topo"),
+            created: 1_700_000_000,
+            entries: vec![ManifestEntry {
+                action: ManifestAction::Remove,
+                path: PathBuf::from("OK/OK_Tulsa_20200101_TM_geo.pdf"),
+                size: 100,
+                digest: Digest::default(),
+                superseded_by: Some(PathBuf::from("OK/OK_Tulsa_20230126_TM_geo.pdf")),
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keys_round_trip_through_their_files() {
+        let dir = TempDir::new("signing-keys");
+        let key = SigningKey::generate().unwrap();
+        let (private, public) = (dir.path().join("plan.key"), dir.path().join("plan.key.pub"));
+        key.save(&private).unwrap();
+        key.verifying_key().save(&public).unwrap();
+        // Never written over
+        assert!(key.save(&private).is_err());
+        let signature = SigningKey::load(&private).unwrap().sign(b"plan");
+        assert_eq!(signature, key.sign(b"plan"));
+        assert!(VerifyingKey::load(&public)
+            .unwrap()
+            .verify(b"plan", &signature));
+        // Each half only loads as itself
+        assert!(SigningKey::load(&public).is_err());
+        assert!(VerifyingKey::load(&private).is_err());
+    }
+
+    #[test]
+    fn verifies_a_signed_manifest() {
+        let dir = TempDir::new("signing");
+        let key = SigningKey::generate().unwrap();
+        let mut manifest = manifest();
+        assert!(manifest.verify_signature(&key.verifying_key()).is_err());
+        manifest.sign(&key);
+        let path = dir.path().join("plan.manifest");
+        manifest.write(&path).unwrap();
+        let read = Manifest::read(&path).unwrap();
+        assert_eq!(read.signature, manifest.signature);
+        read.verify_signature(&key.verifying_key()).unwrap();
+        let other = SigningKey::generate().unwrap();
+        assert!(read.verify_signature(&other.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn rejects_a_tampered_manifest() {
+        let dir = TempDir::new("signing-tampered");
+        let key = SigningKey::generate().unwrap();
+        let mut manifest = manifest();
+        manifest.sign(&key);
+        let path = dir.path().join("plan.manifest");
+        manifest.write(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let tampered = text.replace("20230126", "20190126");
+        assert_ne!(text, tampered);
+        std::fs::write(&path, tampered).unwrap();
+        let e = Manifest::read(&path)
+            .unwrap()
+            .verify_signature(&key.verifying_key())
+            .unwrap_err();
+        assert!(e.to_string().contains("bad signature"), "{e}");
+    }
+}
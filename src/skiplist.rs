@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, ErrorContext, Phase};
+
+/// How often the full contents of the skip-list are summarized, in seconds.
+pub const SUMMARY_INTERVAL_SECS: u64 = 7 * 24 * 3600;
+
+///
+/// A file that has failed in one or more consecutive runs.
+#[derive(Debug, Clone)]
+pub struct SkipEntry {
+    pub phase: String,
+    pub count: u32,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub reason: String,
+}
+
+///
+/// A persistent record of files that fail to parse or delete run after run.  The first failure of
+/// a file is reported normally, repeats are suppressed from the routine logs and only show up in
+/// the periodic summary.  Entries are dropped once the file stops failing.
+///
+/// Stored as a tab separated file, one entry per line:
+/// `<path>\t<phase>\t<count>\t<first_seen>\t<last_seen>\t<reason>`
+pub struct SkipList {
+    path: PathBuf,
+    last_summary: u64,
+    entries: BTreeMap<PathBuf, SkipEntry>,
+}
+
+impl SkipList {
+    ///
+    /// Loads the skip-list at the provided path, or starts an empty one if it doesn't exist yet.
+    pub fn load(path: impl Into<PathBuf>) -> Result<SkipList, Error> {
+        let path = path.into();
+        let mut list = SkipList {
+            path,
+            last_summary: 0,
+            entries: BTreeMap::new(),
+        };
+        let contents = match std::fs::read_to_string(&list.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(list),
+            Err(e) => return Err(e).context(Phase::Setup, &list.path),
+        };
+        for line in contents.lines() {
+            if let Some(summary) = line.strip_prefix("#summary\t") {
+                list.last_summary = summary.parse().unwrap_or_default();
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.splitn(6, '\t').collect();
+            let [path, phase, count, first_seen, last_seen, reason] = fields.as_slice() else {
+                continue;
+            };
+            list.entries.insert(
+                PathBuf::from(path),
+                SkipEntry {
+                    phase: phase.to_string(),
+                    count: count.parse().unwrap_or(1),
+                    first_seen: first_seen.parse().unwrap_or_default(),
+                    last_seen: last_seen.parse().unwrap_or_default(),
+                    reason: reason.to_string(),
+                },
+            );
+        }
+        Ok(list)
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = String::from("# charts-clean skip-list\n");
+        let _ = writeln!(out, "#summary\t{}", self.last_summary);
+        for (path, e) in &self.entries {
+            let _ = writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                path.display(),
+                e.phase,
+                e.count,
+                e.first_seen,
+                e.last_seen,
+                e.reason
+            );
+        }
+        std::fs::write(&self.path, out).context(Phase::Setup, &self.path)
+    }
+
+    ///
+    /// Records the errors of a run over `root`, returning the ones that should still be reported:
+    /// errors without a path, and files failing for the first time.  Entries under `root` that
+    /// didn't fail this time are forgotten.
+    pub fn update<'a>(&mut self, root: &Path, errors: &'a [Error], now: u64) -> Vec<&'a Error> {
+        let mut report = Vec::new();
+        let mut failed = BTreeMap::new();
+        for err in errors {
+            let Some(path) = err.path() else {
+                report.push(err);
+                continue;
+            };
+            let reason = err.kind().to_string().replace(['\t', '\n'], " ");
+            let entry = match self.entries.remove(path) {
+                Some(mut entry) => {
+                    entry.count += 1;
+                    entry.last_seen = now;
+                    entry.phase = err.phase().to_string();
+                    entry.reason = reason;
+                    entry
+                }
+                None => {
+                    report.push(err);
+                    SkipEntry {
+                        phase: err.phase().to_string(),
+                        count: 1,
+                        first_seen: now,
+                        last_seen: now,
+                        reason,
+                    }
+                }
+            };
+            failed.insert(path.to_path_buf(), entry);
+        }
+        self.entries.retain(|path, _| !path.starts_with(root));
+        self.entries.append(&mut failed);
+        report
+    }
+
+    ///
+    /// True if there are repeat failures and they haven't been summarized in the last week.
+    pub fn summary_due(&self, now: u64) -> bool {
+        self.repeated().next().is_some()
+            && now.saturating_sub(self.last_summary) >= SUMMARY_INTERVAL_SECS
+    }
+
+    pub fn mark_summarized(&mut self, now: u64) {
+        self.last_summary = now;
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&PathBuf, &SkipEntry)> {
+        self.entries.iter()
+    }
+
+    ///
+    /// The entries that have failed in more than one run, and are being suppressed.
+    pub fn repeated(&self) -> impl Iterator<Item = (&PathBuf, &SkipEntry)> {
+        self.entries.iter().filter(|(_, e)| e.count > 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Write as _};
+use std::path::{Path, PathBuf};
+
+use crate::{CleanPlan, Error, ErrorContext, FoundFile, Phase};
+
+const HEADER: &str = "# charts-clean skipped v1";
+
+///
+/// Why a file was neither kept nor removed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum SkipReason {
+    /// Its name didn't parse as a chart
+    Unparsed,
+    /// A removal candidate the policy held back: past the deletion cap, behind the baseline,
+    /// kept for a suspect newer edition, left for the next run, or changed since it was planned
+    Excluded,
+    /// A removal candidate another application references
+    Protected,
+    /// A removal candidate not yet older than the profile's `cold_after`
+    TooNew,
+    /// A removal candidate whose attributes stop it being removed
+    Locked,
+    /// Its removal, or reading it, failed
+    Error,
+}
+
+impl SkipReason {
+    ///
+    /// What the reason means, for the report
+    pub fn description(&self) -> &'static str {
+        match self {
+            SkipReason::Unparsed => "names that didn't parse as charts",
+            SkipReason::Excluded => "removals the policy held back",
+            SkipReason::Protected => "removals of files other applications reference",
+            SkipReason::TooNew => "removals not yet older than cold_after",
+            SkipReason::Locked => "removals of immutable or read-only files, see --force-attrs",
+            SkipReason::Error => "files that failed to be removed or read",
+        }
+    }
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            SkipReason::Unparsed => "unparsed",
+            SkipReason::Excluded => "excluded",
+            SkipReason::Protected => "protected",
+            SkipReason::TooNew => "too-new",
+            SkipReason::Locked => "locked",
+            SkipReason::Error => "error",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+///
+/// A file that was neither kept nor removed, and the particulars of why, ie `past the deletion
+/// cap` for one excluded.
+#[derive(Debug, Clone)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub detail: String,
+}
+
+///
+/// Every file a profile's run neither kept nor removed, by why, so that "why didn't it clean
+/// this?" can be answered from the report.  Saved in full to the state dir as a tab separated
+/// file, a `<reason>\t<detail>\t<path>` line for each file.
+#[derive(Debug, Clone, Default)]
+pub struct SkippedFiles {
+    files: BTreeMap<SkipReason, Vec<SkippedFile>>,
+}
+
+impl SkippedFiles {
+    ///
+    /// The removal candidates the plan held back
+    pub fn from_plan(plan: &CleanPlan) -> SkippedFiles {
+        let mut skipped = SkippedFiles::default();
+        let mut add = |reason, files: &[&FoundFile], detail: &str| {
+            for file in files {
+                skipped.add(reason, file.full_path(), detail);
+            }
+        };
+        add(
+            SkipReason::Excluded,
+            &plan.deferred,
+            "past the deletion cap",
+        );
+        add(
+            SkipReason::Excluded,
+            &plan.held,
+            "its chart is older than the baseline",
+        );
+        add(
+            SkipReason::Excluded,
+            &plan.retained,
+            "the newest edition is a suspect size",
+        );
+        add(
+            SkipReason::Protected,
+            &plan.protected,
+            "referenced by another application",
+        );
+        add(SkipReason::TooNew, &plan.aging, "younger than cold_after");
+        add(SkipReason::Locked, &plan.locked, "immutable or read-only");
+        skipped
+    }
+
+    pub fn add(&mut self, reason: SkipReason, path: impl Into<PathBuf>, detail: impl Into<String>) {
+        self.files.entry(reason).or_default().push(SkippedFile {
+            path: path.into(),
+            detail: detail.into(),
+        });
+    }
+
+    ///
+    /// Files the errors name, as unparsed if their names didn't parse and as errors if not.
+    /// Errors that don't name a file, or name a directory, aren't a file's.
+    pub fn add_errors<'a>(&mut self, errors: impl IntoIterator<Item = &'a Error>) {
+        for e in errors {
+            let Some(path) = e.path().filter(|path| !path.is_dir()) else {
+                continue;
+            };
+            let reason = match e.phase() {
+                Phase::Parse => SkipReason::Unparsed,
+                _ => SkipReason::Error,
+            };
+            self.add(reason, path, e.kind().to_string());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.values().map(Vec::len).sum()
+    }
+
+    pub fn count(&self, reason: SkipReason) -> usize {
+        self.get(reason).len()
+    }
+
+    pub fn get(&self, reason: SkipReason) -> &[SkippedFile] {
+        self.files
+            .get(&reason)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SkipReason, &[SkippedFile])> {
+        self.files
+            .iter()
+            .map(|(reason, files)| (*reason, files.as_slice()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(Phase::Setup, parent)?;
+        }
+        let mut out = format!("{HEADER}\n");
+        for (reason, files) in self.iter() {
+            for file in files {
+                let _ = writeln!(out, "{reason}\t{}\t{}", file.detail, file.path.display());
+            }
+        }
+        std::fs::write(path, out).context(Phase::Setup, path)
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irox_log::log::info;
+
+use crate::copy::{copy_verified, move_verified};
+use crate::hash::hash_file;
+use crate::{Digest, Error, ErrorContext, ErrorKind, Executor, Phase, Throttle};
+
+const INDEX: &str = "index.tsv";
+const HEADER: &str = "# charts-clean store v1";
+
+///
+/// A file put in a [`BlobStore`].
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    pub digest: Digest,
+    pub size: u64,
+    /// When it was stored, seconds since the epoch
+    pub stored: u64,
+    /// Where it was, relative to the root it was scanned from
+    pub original: PathBuf,
+}
+
+///
+/// A content addressed store for superseded editions.  Each file is kept once, as
+/// `<dir>/blobs/<xx>/<sha1>`, however many charts or runs it was removed from, and
+/// `<dir>/index.tsv` records where every copy came from:
+/// `<sha1>\t<size>\t<stored>\t<original path>`
+pub struct BlobStore {
+    dir: PathBuf,
+    verify: bool,
+    throttle: Arc<Throttle>,
+    index: Mutex<()>,
+}
+
+impl BlobStore {
+    pub fn new(dir: impl Into<PathBuf>, throttle: Arc<Throttle>) -> BlobStore {
+        BlobStore {
+            dir: dir.into(),
+            verify: false,
+            throttle,
+            index: Mutex::new(()),
+        }
+    }
+
+    ///
+    /// Re-reads files that had to be copied (across filesystems) before removing the original
+    #[must_use]
+    pub fn with_verify(mut self, verify: bool) -> BlobStore {
+        self.verify = verify;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn blob_path(&self, digest: &Digest) -> PathBuf {
+        let hex = digest.to_hex();
+        let (fanout, _) = hex.split_at(2);
+        self.dir.join("blobs").join(fanout).join(hex)
+    }
+
+    ///
+    /// Moves the file at `path` under `root` into the store, returning its blob.  The file is
+    /// moved in before it's hashed, so what's named is what's stored; a blob already holding the
+    /// same contents is kept and the new copy dropped.
+    pub fn put(&self, root: &Path, path: &Path) -> Result<PathBuf, Error> {
+        // Named uniquely so a file left behind by an interrupted run is never overwritten
+        let incoming = self.dir.join("incoming");
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let staged = incoming.join(format!("{nanos}-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&incoming).context(Phase::Copy, &incoming)?;
+        move_verified(path, &staged, self.verify, &self.throttle)?;
+        let digest = hash_file(&staged).context(Phase::Copy, &staged)?;
+        let size = std::fs::metadata(&staged)
+            .context(Phase::Copy, &staged)?
+            .len();
+        let blob = self.blob_path(&digest);
+        if std::fs::metadata(&blob).is_ok_and(|m| m.len() == size) {
+            info!("{} is already stored as {digest}", path.display());
+            std::fs::remove_file(&staged).context(Phase::Delete, &staged)?;
+        } else {
+            if let Some(parent) = blob.parent() {
+                std::fs::create_dir_all(parent).context(Phase::Copy, parent)?;
+            }
+            std::fs::rename(&staged, &blob).context(Phase::Copy, &blob)?;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let relative = relative.strip_prefix("/").unwrap_or(relative);
+        self.record(&StoredFile {
+            digest,
+            size,
+            stored: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            original: relative.to_path_buf(),
+        })?;
+        Ok(blob)
+    }
+
+    fn record(&self, file: &StoredFile) -> Result<(), Error> {
+        let _lock = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = self.dir.join(INDEX);
+        let new = !path.exists();
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(Phase::Copy, &path)?;
+        let mut line = String::new();
+        if new {
+            line.push_str(HEADER);
+            line.push('\n');
+        }
+        line.push_str(&format!(
+            "{}\t{}\t{}\t{}\n",
+            file.digest,
+            file.size,
+            file.stored,
+            file.original.display()
+        ));
+        index
+            .write_all(line.as_bytes())
+            .context(Phase::Copy, &path)?;
+        index.sync_data().context(Phase::Copy, &path)
+    }
+
+    ///
+    /// Every file put in the store, in the order they were stored
+    pub fn entries(&self) -> Result<Vec<StoredFile>, Error> {
+        let path = self.dir.join(INDEX);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(Phase::Scan, &path),
+        };
+        let mut entries = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let err = || {
+                let msg = format!("line {}: Unrecognized entry", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Scan).with_path(&path)
+            };
+            let mut fields = line.splitn(4, '\t');
+            let (Some(digest), Some(size), Some(stored), Some(original)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(err());
+            };
+            entries.push(StoredFile {
+                digest: Digest::from_hex(digest).ok_or_else(err)?,
+                size: size.parse().map_err(|_| err())?,
+                stored: stored.parse().map_err(|_| err())?,
+                original: PathBuf::from(original),
+            });
+        }
+        Ok(entries)
+    }
+
+    ///
+    /// The stored files whose hash starts with `prefix`, ie the first few characters of a hash
+    /// printed by `store list`
+    pub fn find(&self, prefix: &str) -> Result<Vec<StoredFile>, Error> {
+        let prefix = prefix.to_ascii_lowercase();
+        let mut entries = self.entries()?;
+        entries.retain(|e| e.digest.to_hex().starts_with(&prefix));
+        Ok(entries)
+    }
+
+    ///
+    /// Copies the blob with the digest out to `dest`, checking it against its hash on the way
+    pub fn restore(&self, digest: &Digest, dest: &Path) -> Result<u64, Error> {
+        let blob = self.blob_path(digest);
+        let actual = hash_file(&blob).context(Phase::Copy, &blob)?;
+        if actual != *digest {
+            let msg = format!("stored blob is corrupt, its contents hash to {actual}");
+            return Err(Error::verify(msg).with_path(&blob));
+        }
+        copy_verified(&blob, dest, true, &self.throttle)
+    }
+}
+
+impl Executor for BlobStore {
+    fn name(&self) -> &'static str {
+        "store"
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        info!("Will store {} in {}", path.display(), self.dir.display());
+        self.put(root, path).map(Some)
+    }
+}
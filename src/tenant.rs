@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::{parse_duration, Error, ErrorContext, ErrorKind, Phase};
+
+///
+/// How often a tenant is cleaned when its section doesn't say
+pub const DEFAULT_EVERY: Duration = Duration::from_secs(24 * 3600);
+
+///
+/// One of the archives `charts-clean serve` looks after, ie a regional office's charts.  Each
+/// tenant has a config file of its own and is cleaned on its own schedule, with its own state
+/// directory, so its journal, skip-list, catalogs and reports are kept apart from every other
+/// tenant's.
+///
+/// Read from a tenants file, one section per tenant.  Relative paths are relative to the file's
+/// directory:
+/// ```text
+/// [tenant south-central]
+/// config = south-central.ini
+/// every = 24h
+/// state_dir = /var/lib/charts-clean/south-central
+/// ```
+/// Without a `state_dir`, the tenant's config's own is used, and if that's unset too, a
+/// directory named after the tenant under `serve`'s state directory.
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub config: PathBuf,
+    /// How long from the start of one clean to the start of the next
+    pub every: Duration,
+    pub state_dir: Option<PathBuf>,
+}
+
+impl Tenant {
+    pub fn load(path: &Path) -> Result<Vec<Tenant>, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Tenant::parse(&text, base).map_err(|e| e.with_path(path))
+    }
+
+    pub fn parse(text: &str, base: &Path) -> Result<Vec<Tenant>, Error> {
+        let mut tenants: Vec<Tenant> = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| tenant_error(format!("line {}: {msg}", idx + 1));
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let section = section.trim();
+                let Some(name) = section.strip_prefix("tenant ").map(str::trim) else {
+                    return Err(err(format!("Unknown section [{section}]")));
+                };
+                if tenants.iter().any(|t| t.name == name) {
+                    return Err(err(format!("Tenant {name} is defined twice")));
+                }
+                tenants.push(Tenant {
+                    name: name.to_string(),
+                    config: PathBuf::new(),
+                    every: DEFAULT_EVERY,
+                    state_dir: None,
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(err(format!("Expected key = value: {line}")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let Some(tenant) = tenants.last_mut() else {
+                return Err(err(format!("{key} is outside a [tenant NAME] section")));
+            };
+            match key {
+                "config" => tenant.config = base.join(value),
+                "state_dir" => tenant.state_dir = Some(base.join(value)),
+                "every" => {
+                    tenant.every = parse_duration(value)
+                        .filter(|every| !every.is_zero())
+                        .ok_or_else(|| err(format!("Invalid duration: {value}")))?;
+                }
+                _ => return Err(err(format!("Unknown tenant key: {key}"))),
+            }
+        }
+        if let Some(tenant) = tenants.iter().find(|t| t.config.as_os_str().is_empty()) {
+            return Err(tenant_error(format!(
+                "Tenant {} has no config",
+                tenant.name
+            )));
+        }
+        Ok(tenants)
+    }
+}
+
+fn tenant_error(msg: String) -> Error {
+    Error::new(ErrorKind::ConfigError(msg), Phase::Setup)
+}
@@ -0,0 +1,86 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+///
+/// Paces filesystem operations so the cleaner doesn't saturate the link to the archive, or trip
+/// the rate limits of whatever is serving it.  Each `read_dir`, delete and copy counts as a
+/// request, and copied bytes count against the bandwidth limit.  The default is unlimited.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    max_requests_per_second: Option<f64>,
+    max_bytes_per_second: Option<u64>,
+    state: Mutex<ThrottleState>,
+}
+
+#[derive(Debug)]
+struct ThrottleState {
+    next_request: Instant,
+    next_transfer: Instant,
+}
+
+impl Default for ThrottleState {
+    fn default() -> Self {
+        let now = Instant::now();
+        ThrottleState {
+            next_request: now,
+            next_transfer: now,
+        }
+    }
+}
+
+impl Throttle {
+    pub fn unlimited() -> Throttle {
+        Throttle::default()
+    }
+
+    #[must_use]
+    pub fn with_max_requests_per_second(mut self, max: Option<f64>) -> Throttle {
+        self.max_requests_per_second = max.filter(|m| *m > 0.);
+        self
+    }
+
+    #[must_use]
+    pub fn with_max_bytes_per_second(mut self, max: Option<u64>) -> Throttle {
+        self.max_bytes_per_second = max.filter(|m| *m > 0);
+        self
+    }
+
+    pub fn limits_bandwidth(&self) -> bool {
+        self.max_bytes_per_second.is_some()
+    }
+
+    ///
+    /// Blocks until another request is allowed
+    pub fn request(&self) {
+        let Some(max) = self.max_requests_per_second else {
+            return;
+        };
+        self.wait(Duration::from_secs_f64(1. / max), |s| &mut s.next_request);
+    }
+
+    ///
+    /// Blocks until `bytes` more are allowed to be transferred
+    pub fn transfer(&self, bytes: u64) {
+        let Some(max) = self.max_bytes_per_second else {
+            return;
+        };
+        let cost = Duration::from_secs_f64(bytes as f64 / max as f64);
+        self.wait(cost, |s| &mut s.next_transfer);
+    }
+
+    fn wait(&self, cost: Duration, slot: impl Fn(&mut ThrottleState) -> &mut Instant) {
+        let now = Instant::now();
+        let at = {
+            let Ok(mut state) = self.state.lock() else {
+                return;
+            };
+            let next = slot(&mut state);
+            let at = (*next).max(now);
+            *next = at + cost;
+            at
+        };
+        if at > now {
+            std::thread::sleep(at - now);
+        }
+    }
+}
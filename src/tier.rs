@@ -0,0 +1,186 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use irox_log::log::info;
+
+use crate::copy::{copy_verified, move_verified};
+use crate::{Error, ErrorContext, ErrorKind, Executor, Phase, Throttle};
+
+const INDEX: &str = "index.tsv";
+const HEADER: &str = "# charts-clean cold tier v1";
+
+///
+/// A file moved to a [`ColdTier`].
+#[derive(Debug, Clone)]
+pub struct TieredFile {
+    pub size: u64,
+    /// When it was moved, seconds since the epoch
+    pub moved: u64,
+    /// Where it was, relative to the root it was scanned from, and where it is in the tier
+    pub original: PathBuf,
+}
+
+///
+/// A slower, cheaper volume that superseded editions are moved to instead of being deleted,
+/// once they're older than the profile's `cold_after`, making the cleaner the manager of an
+/// archive's whole lifecycle.  Each file keeps its path under the root, as `<dir>/<path>`, so
+/// the tier can be browsed like the archive, and `<dir>/index.tsv` records what was moved when:
+/// `<moved>\t<size>\t<original path>`
+pub struct ColdTier {
+    dir: PathBuf,
+    verify: bool,
+    throttle: Arc<Throttle>,
+    index: Mutex<()>,
+}
+
+impl ColdTier {
+    pub fn new(dir: impl Into<PathBuf>, throttle: Arc<Throttle>) -> ColdTier {
+        ColdTier {
+            dir: dir.into(),
+            verify: false,
+            throttle,
+            index: Mutex::new(()),
+        }
+    }
+
+    ///
+    /// Re-reads files that had to be copied (across filesystems) before removing the original
+    #[must_use]
+    pub fn with_verify(mut self, verify: bool) -> ColdTier {
+        self.verify = verify;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    ///
+    /// Where a file that was at `original` under its root is in the tier
+    pub fn path_of(&self, original: &Path) -> PathBuf {
+        self.dir.join(original)
+    }
+
+    ///
+    /// Moves the file at `path` under `root` into the tier, returning where it went.  A file
+    /// already in the tier at that path, ie one of another root's, is never overwritten.
+    pub fn put(&self, root: &Path, path: &Path) -> Result<PathBuf, Error> {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let relative = relative.strip_prefix("/").unwrap_or(relative);
+        let dest = self.path_of(relative);
+        if dest.exists() {
+            let msg = format!("{} is already in the cold tier", dest.display());
+            return Err(std::io::Error::other(msg)).context(Phase::Copy, path);
+        }
+        let size = std::fs::metadata(path).context(Phase::Copy, path)?.len();
+        move_verified(path, &dest, self.verify, &self.throttle)?;
+        self.record(&TieredFile {
+            size,
+            moved: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            original: relative.to_path_buf(),
+        })?;
+        Ok(dest)
+    }
+
+    fn record(&self, file: &TieredFile) -> Result<(), Error> {
+        let _lock = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let path = self.dir.join(INDEX);
+        let new = !path.exists();
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(Phase::Copy, &path)?;
+        let mut line = String::new();
+        if new {
+            line.push_str(HEADER);
+            line.push('\n');
+        }
+        line.push_str(&format!(
+            "{}\t{}\t{}\n",
+            file.moved,
+            file.size,
+            file.original.display()
+        ));
+        index
+            .write_all(line.as_bytes())
+            .context(Phase::Copy, &path)?;
+        index.sync_data().context(Phase::Copy, &path)
+    }
+
+    ///
+    /// Every file moved to the tier, in the order they were moved
+    pub fn entries(&self) -> Result<Vec<TieredFile>, Error> {
+        let path = self.dir.join(INDEX);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(Phase::Scan, &path),
+        };
+        let mut entries = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let err = || {
+                let msg = format!("line {}: Unrecognized entry", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Scan).with_path(&path)
+            };
+            let mut fields = line.splitn(3, '\t');
+            let (Some(moved), Some(size), Some(original)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(err());
+            };
+            entries.push(TieredFile {
+                size: size.parse().map_err(|_| err())?,
+                moved: moved.parse().map_err(|_| err())?,
+                original: PathBuf::from(original),
+            });
+        }
+        Ok(entries)
+    }
+
+    ///
+    /// The files in the tier whose original path is `path` or ends with it, ie their file name,
+    /// that are still there
+    pub fn find(&self, path: &Path) -> Result<Vec<TieredFile>, Error> {
+        let mut found: Vec<TieredFile> = Vec::new();
+        for file in self.entries()? {
+            if !file.original.ends_with(path) || !self.path_of(&file.original).exists() {
+                continue;
+            }
+            // Moved again after it was restored, the latest move is the one that's there
+            found.retain(|f| f.original != file.original);
+            found.push(file);
+        }
+        Ok(found)
+    }
+
+    ///
+    /// Copies the file out of the tier to `dest`, leaving it in the tier
+    pub fn restore(&self, file: &TieredFile, dest: &Path) -> Result<u64, Error> {
+        copy_verified(&self.path_of(&file.original), dest, true, &self.throttle)
+    }
+}
+
+impl Executor for ColdTier {
+    fn name(&self) -> &'static str {
+        "cold"
+    }
+
+    fn remove(&self, root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+        info!(
+            "Will move {} to the cold tier in {}",
+            path.display(),
+            self.dir.display()
+        );
+        self.put(root, path).map(Some)
+    }
+}
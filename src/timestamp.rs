@@ -0,0 +1,184 @@
+use std::fmt::{Display, Formatter};
+
+use irox_time::datetime::UTCDateTime;
+use irox_time::format::iso8601::{BASIC_CALENDAR_DATE, BASIC_TIME_OF_DAY};
+use irox_time::format::{FormatError, FormatErrorType, FormatParser};
+use irox_time::gregorian::Date;
+use irox_time::{Time, SECONDS_IN_DAY};
+
+use crate::Error;
+
+///
+/// A fixed offset from UTC, as carried by an ISO8601 zone designator (`Z`, `+01`, `+0100`,
+/// `-05:00`) or declared as the timezone of a source.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct UtcOffset {
+    seconds: i32,
+}
+
+impl UtcOffset {
+    pub const UTC: UtcOffset = UtcOffset { seconds: 0 };
+
+    ///
+    /// Parses a zone designator, returning [`None`] if it isn't one.
+    pub fn parse(value: &str) -> Option<UtcOffset> {
+        if value.eq_ignore_ascii_case("z") {
+            return Some(UtcOffset::UTC);
+        }
+        let (sign, rest) = match value.split_at_checked(1)? {
+            ("+", rest) => (1, rest),
+            ("-", rest) => (-1, rest),
+            _ => return None,
+        };
+        let rest = rest.replace(':', "");
+        if !rest.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let (hours, minutes) = match rest.len() {
+            2 => (rest.parse::<i32>().ok()?, 0),
+            4 => (
+                rest[..2].parse::<i32>().ok()?,
+                rest[2..].parse::<i32>().ok()?,
+            ),
+            _ => return None,
+        };
+        if hours > 14 || minutes > 59 {
+            return None;
+        }
+        Some(UtcOffset {
+            seconds: sign * (hours * 3600 + minutes * 60),
+        })
+    }
+}
+
+impl Display for UtcOffset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.seconds < 0 { '-' } else { '+' };
+        let minutes = self.seconds.abs() / 60;
+        write!(f, "{sign}{:02}{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+///
+/// Splits a trailing zone designator off of a time token, ie `120000+0100` -> (`120000`, `+0100`)
+fn split_zone(value: &str) -> Result<(&str, Option<UtcOffset>), Error> {
+    let Some(idx) = value.find(['Z', 'z', '+', '-']) else {
+        return Ok((value, None));
+    };
+    let (time, zone) = value.split_at(idx);
+    let Some(offset) = UtcOffset::parse(zone) else {
+        let msg = format!("Invalid zone designator: {zone}");
+        return Err(FormatError::new(FormatErrorType::Other, msg).into());
+    };
+    Ok((time, Some(offset)))
+}
+
+///
+/// Returns true if the token looks like a basic time of day (`1200`, `T120000`, `120000Z`).
+pub(crate) fn is_time_token(value: &str) -> bool {
+    let value = value.strip_prefix(['T', 't']).unwrap_or(value);
+    value.len() >= 4 && value.chars().take(4).all(|c| c.is_ascii_digit())
+}
+
+///
+/// Parses a basic time of day with an optional zone designator.  Minutes-only times (`1200`) are
+/// accepted and treated as `120000`.
+fn parse_time(value: &str) -> Result<(Time, Option<UtcOffset>), Error> {
+    let value = value.strip_prefix(['T', 't']).unwrap_or(value);
+    let (time, offset) = split_zone(value)?;
+    let time = if time.len() == 4 {
+        BASIC_TIME_OF_DAY.try_from(&format!("{time}00"))?
+    } else {
+        BASIC_TIME_OF_DAY.try_from(time)?
+    };
+    Ok((time, offset))
+}
+
+///
+/// Shifts a local date and time by the provided offset to UTC.
+fn to_utc(date: Date, time: Time, offset: UtcOffset) -> UTCDateTime {
+    let day = SECONDS_IN_DAY as i64;
+    let mut seconds = time.get_seconds() as i64 - offset.seconds as i64;
+    let mut date = date;
+    if seconds < 0 {
+        date = date.sub_days(1);
+        seconds += day;
+    } else if seconds >= day {
+        date = date.add_days(1);
+        seconds -= day;
+    }
+    let time = Time::new(seconds as u32, time.get_nanoseconds()).unwrap_or_default();
+    UTCDateTime::new(date, time)
+}
+
+///
+/// Parses the date token (and the following time token, if it looks like a time) of a file name
+/// into a UTC timestamp.  Tokens without a zone designator are assumed to be in `source_tz`.
+pub fn parse_timestamp(
+    date: &str,
+    time: Option<&str>,
+    source_tz: UtcOffset,
+) -> Result<UTCDateTime, Error> {
+    let (date, time) = match date.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (date, time.filter(|t| is_time_token(t))),
+    };
+    let date = BASIC_CALENDAR_DATE.try_from(date)?;
+    let (time, offset) = match time {
+        Some(time) => parse_time(time)?,
+        None => (Time::default(), None),
+    };
+    Ok(to_utc(date, time, offset.unwrap_or(source_tz)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str, time: &str) -> UTCDateTime {
+        parse_timestamp(date, Some(time), UtcOffset::UTC).unwrap()
+    }
+
+    #[test]
+    fn normalises_zone_designators() {
+        assert_eq!(UtcOffset::parse("Z"), Some(UtcOffset::UTC));
+        assert_eq!(UtcOffset::parse("z"), Some(UtcOffset::UTC));
+        assert_eq!(UtcOffset::parse("+00"), Some(UtcOffset::UTC));
+        for (zone, normal) in [
+            ("+01", "+0100"),
+            ("+0100", "+0100"),
+            ("+01:00", "+0100"),
+            ("-05:00", "-0500"),
+            ("+0530", "+0530"),
+            ("-0930", "-0930"),
+            ("+14", "+1400"),
+        ] {
+            let offset = UtcOffset::parse(zone).unwrap();
+            assert_eq!(offset.to_string(), normal, "{zone}");
+            assert_eq!(UtcOffset::parse(normal), Some(offset));
+        }
+        assert_eq!(UtcOffset::UTC.to_string(), "+0000");
+    }
+
+    #[test]
+    fn rejects_anything_else() {
+        for zone in [
+            "", "0100", "+1", "+010", "+01000", "+15", "+0160", "+ab", "UTC",
+        ] {
+            assert_eq!(UtcOffset::parse(zone), None, "{zone}");
+        }
+    }
+
+    #[test]
+    fn shifts_to_utc_across_days() {
+        assert_eq!(at("20200101", "120000+0100"), at("20200101", "110000Z"));
+        assert_eq!(at("20200101", "0030+0100"), at("20191231", "2330Z"));
+        assert_eq!(at("20201231", "2000-05:00"), at("20210101", "0100Z"));
+        let eastern = UtcOffset::parse("-05").unwrap();
+        let date = parse_timestamp("20200101", None, eastern).unwrap();
+        assert_eq!(date, at("20200101", "0500"));
+        // A designator on the token wins over the source's timezone
+        let zoned = parse_timestamp("20200101T1200Z", None, eastern).unwrap();
+        assert_eq!(zoned, at("20200101", "1200"));
+    }
+}
@@ -0,0 +1,83 @@
+///
+/// Parses a byte size with an optional binary suffix, ie `512`, `64K`, `10M`, `32G`, `2T`
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix(['b', 'B'])
+        .filter(|v| v.ends_with(|c: char| c.is_ascii_alphabetic()))
+        .unwrap_or(value);
+    let (num, mult) = match value.chars().last()? {
+        'k' | 'K' => (&value[..value.len() - 1], 1u64 << 10),
+        'm' | 'M' => (&value[..value.len() - 1], 1 << 20),
+        'g' | 'G' => (&value[..value.len() - 1], 1 << 30),
+        't' | 'T' => (&value[..value.len() - 1], 1 << 40),
+        _ => (value, 1),
+    };
+    let num: f64 = num.trim().parse().ok()?;
+    if num < 0. {
+        return None;
+    }
+    Some((num * mult as f64) as u64)
+}
+
+///
+/// Formats a byte count with a binary suffix, ie `1.5G`
+pub fn format_size(bytes: u64) -> String {
+    const SUFFIXES: [&str; 5] = ["K", "M", "G", "T", "P"];
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut value = bytes as f64 / 1024.;
+    let mut suffix = "K";
+    for next in SUFFIXES.iter().skip(1) {
+        if value < 1024. {
+            break;
+        }
+        value /= 1024.;
+        suffix = next;
+    }
+    format!("{value:.1}{suffix}")
+}
+
+///
+/// Parses a duration with a unit suffix, ie `90s`, `30m`, `12h`, `30d`, `2w`, `1y`.  A bare number
+/// is in seconds.
+pub fn parse_duration(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    let (num, mult) = match value.chars().last()? {
+        's' => (&value[..value.len() - 1], 1u64),
+        'm' => (&value[..value.len() - 1], 60),
+        'h' => (&value[..value.len() - 1], 3600),
+        'd' => (&value[..value.len() - 1], 86400),
+        'w' => (&value[..value.len() - 1], 7 * 86400),
+        'y' => (&value[..value.len() - 1], 365 * 86400),
+        _ => (value, 1),
+    };
+    let num: f64 = num.trim().parse().ok()?;
+    if num < 0. {
+        return None;
+    }
+    Some(std::time::Duration::from_secs_f64(num * mult as f64))
+}
+
+///
+/// Formats a duration in the largest unit [`parse_duration`] reads that it's a whole number of,
+/// ie `12h` or `90s`
+pub fn format_duration(duration: std::time::Duration) -> String {
+    const UNITS: [(char, u64); 5] = [
+        ('w', 7 * 86400),
+        ('d', 86400),
+        ('h', 3600),
+        ('m', 60),
+        ('s', 1),
+    ];
+    let secs = duration.as_secs();
+    if duration.subsec_nanos() != 0 || secs == 0 {
+        return format!("{}s", duration.as_secs_f64());
+    }
+    let (unit, mult) = UNITS
+        .iter()
+        .find(|(_, mult)| secs.is_multiple_of(*mult))
+        .unwrap_or(&('s', 1));
+    format!("{}{unit}", secs / mult)
+}
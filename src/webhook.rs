@@ -0,0 +1,56 @@
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use crate::download::connect;
+use crate::{Error, ErrorKind, Phase};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+///
+/// Escapes a string for a JSON string literal
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+///
+/// POSTs a JSON body to a plain `http://` URL, failing unless it's answered with a 2xx.  There's
+/// no TLS here; for an `https://` endpoint, point the hook at a local relay.
+pub fn post_webhook(url: &str, json: &str) -> Result<(), Error> {
+    let err = |msg: String| {
+        Error::new(
+            ErrorKind::ConfigError(format!("webhook {url}: {msg}")),
+            Phase::Setup,
+        )
+    };
+    let (mut stream, authority, path) = connect(url, TIMEOUT).map_err(err)?;
+    let request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {authority}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\nUser-Agent: charts-clean\r\n\r\n{json}",
+        json.len()
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| err(e.to_string()))?;
+    let mut response = Vec::new();
+    let _ = stream.take(4096).read_to_end(&mut response);
+    let response = String::from_utf8_lossy(&response);
+    let status = response.lines().next().unwrap_or_default();
+    match status.split(' ').nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(err(format!("unexpected response: {status}"))),
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{ChartId, ChartPattern, Error, ErrorContext, ErrorKind, Phase};
+
+///
+/// What a plan does with charts their publisher has withdrawn.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum WithdrawnAction {
+    /// Keep them, but report them
+    #[default]
+    Flag,
+    /// Remove every version of them, the newest included
+    Remove,
+}
+
+impl FromStr for WithdrawnAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flag" => Ok(WithdrawnAction::Flag),
+            "remove" => Ok(WithdrawnAction::Remove),
+            _ => Err(format!(
+                "Unknown withdrawn action {s}, expected flag or remove"
+            )),
+        }
+    }
+}
+
+impl Display for WithdrawnAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WithdrawnAction::Flag => write!(f, "flag"),
+            WithdrawnAction::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+///
+/// A chart on a [`WithdrawnList`], or every chart a [`ChartPattern`] matches.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WithdrawnChart {
+    pub chart: ChartPattern,
+    /// Whatever the list says about it, ie when it was cancelled
+    pub note: Option<String>,
+}
+
+impl WithdrawnChart {
+    pub fn matches(&self, id: &ChartId) -> bool {
+        self.chart.matches(id)
+    }
+}
+
+impl FromStr for WithdrawnChart {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chart, note) = match s.split_once('\t') {
+            Some((chart, note)) => (chart, Some(note.trim().to_string())),
+            None => (s, None),
+        };
+        Ok(WithdrawnChart {
+            chart: chart.parse()?,
+            note: note.filter(|n| !n.is_empty()),
+        })
+    }
+}
+
+///
+/// The charts a publisher no longer produces, ie NOAA's cancelled RNCs or the FAA's discontinued
+/// charts.  Their last editions are never superseded, so without a list they'd be kept forever.
+///
+/// One chart per line, optionally followed by a tab and a note, with `#` comments (shown here
+/// with spaces):
+/// ```text
+/// # NOAA RNC cancellations
+/// 18423    cancelled 2024-03-01
+/// OK_Tulsa[TM]
+/// ```
+#[derive(Debug, Default)]
+pub struct WithdrawnList {
+    charts: Vec<WithdrawnChart>,
+}
+
+impl FromIterator<WithdrawnChart> for WithdrawnList {
+    fn from_iter<T: IntoIterator<Item = WithdrawnChart>>(iter: T) -> Self {
+        WithdrawnList {
+            charts: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl WithdrawnList {
+    pub fn load(path: &Path) -> Result<WithdrawnList, Error> {
+        let text = std::fs::read_to_string(path).context(Phase::Setup, path)?;
+        let mut charts = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let chart = line.parse().map_err(|msg| {
+                let msg = format!("line {}: {msg}", idx + 1);
+                Error::new(ErrorKind::ConfigError(msg), Phase::Setup).with_path(path)
+            })?;
+            charts.push(chart);
+        }
+        Ok(WithdrawnList { charts })
+    }
+
+    ///
+    /// The entry that withdraws the chart, if any
+    pub fn get(&self, id: &ChartId) -> Option<&WithdrawnChart> {
+        self.charts.iter().find(|c| c.matches(id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.charts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.charts.is_empty()
+    }
+}